@@ -1,22 +1,36 @@
 // CLI command implementations
-use crate::config;
-use crate::symlink;
-use crate::utils::path_utils;
+use super::render;
+use forge_rs::config;
+use forge_rs::config::folder::{ConflictPolicy, DeployMode, FolderConfig, LinkStyle};
+use forge_rs::engine::{Layout, Repair};
+use forge_rs::error::exit_code;
+use forge_rs::symlink;
+use forge_rs::utils::{path_utils, ui};
+use forge_rs::{Action, Engine, ErrorKind, LinkOptions, Outcome, Report, Selection, StageOptions};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
-use walkdir;
+use std::process::ExitCode;
 
 /// Initialize a directory as a forge managed folder
-pub fn init_command(name: Option<&str>, dir: Option<&Path>) {
+pub fn init_command(
+    name: Option<&str>,
+    dir: Option<&Path>,
+    layout: Option<Layout>,
+    links: Option<LinkStyle>,
+    on_conflict: Option<ConflictPolicy>,
+) -> ExitCode {
     // Determine the directory to initialize
     let init_dir = match dir {
         Some(d) => path_utils::normalize(d),
         None => match env::current_dir() {
             Ok(d) => d,
             Err(e) => {
-                eprintln!("Failed to get current directory: {}", e);
-                return;
+                render::error(
+                    format!("Failed to get current directory: {}", e),
+                    Some(ErrorKind::Io),
+                );
+                return ExitCode::from(exit_code::FATAL);
             }
         },
     };
@@ -29,14 +43,17 @@ pub fn init_command(name: Option<&str>, dir: Option<&Path>) {
             match init_dir.file_name() {
                 Some(n) => n.to_string_lossy().to_string(),
                 None => {
-                    eprintln!("Could not determine folder name. Please specify a name with --name");
-                    return;
+                    render::error(
+                        "Could not determine folder name. Please specify a name with --name",
+                        Some(ErrorKind::InvalidInput),
+                    );
+                    return ExitCode::from(exit_code::USAGE);
                 }
             }
         }
     };
 
-    println!(
+    note!(
         "Initializing forge managed folder '{}' at {}",
         folder_name,
         init_dir.display()
@@ -45,10 +62,13 @@ pub fn init_command(name: Option<&str>, dir: Option<&Path>) {
     // Check if directory exists, create if needed
     if !init_dir.exists() {
         match fs::create_dir_all(&init_dir) {
-            Ok(_) => println!("Created directory: {}", init_dir.display()),
+            Ok(_) => note!("Created directory: {}", init_dir.display()),
             Err(e) => {
-                eprintln!("Failed to create directory: {}", e);
-                return;
+                render::error(
+                    format!("Failed to create directory: {}", e),
+                    Some(ErrorKind::Io),
+                );
+                return ExitCode::from(exit_code::FATAL);
             }
         }
     }
@@ -57,849 +77,596 @@ pub fn init_command(name: Option<&str>, dir: Option<&Path>) {
     let forge_dir = init_dir.join(".forge");
     if !forge_dir.exists() {
         match fs::create_dir_all(&forge_dir) {
-            Ok(_) => println!("Created .forge directory"),
+            Ok(_) => note!("Created .forge directory"),
             Err(e) => {
-                eprintln!("Failed to create .forge directory: {}", e);
-                return;
+                render::error(
+                    format!("Failed to create .forge directory: {}", e),
+                    Some(ErrorKind::Io),
+                );
+                return ExitCode::from(exit_code::FATAL);
+            }
+        }
+    }
+
+    // Record the folder's settings with it so they travel with it
+    if layout.is_some() || links.is_some() || on_conflict.is_some() {
+        let saved = FolderConfig::load(&init_dir).and_then(|mut settings| {
+            settings.layout = layout.unwrap_or(settings.layout);
+            settings.links = links.unwrap_or(settings.links);
+            settings.on_conflict = on_conflict.or(settings.on_conflict);
+            settings.save(&init_dir)?;
+            Ok(settings)
+        });
+        match saved {
+            Ok(settings) => {
+                note!("Staging layout: {}", settings.layout.as_str());
+                note!("Symlinks: {}", settings.links.as_str());
+                if let Some(policy) = settings.on_conflict {
+                    note!("Conflicts: {}", policy.as_str());
+                }
             }
+            Err(e) => return fatal("save folder settings", &e),
         }
     }
 
     // Add to managed folders
     match config::add_managed_folder(&folder_name, &init_dir) {
         Ok(_) => {
-            println!("Added '{}' to managed folders", folder_name);
-            println!("\nForge repository initialized successfully!");
-            println!("You can now use 'forge stage' to stage files for tracking.");
+            note!("Added '{}' to managed folders", folder_name);
+            note!("\nForge repository initialized successfully!");
+            note!("You can now use 'forge stage' to stage files for tracking.");
+            render::emit(&[Outcome::done(Action::Init, &init_dir)
+                .with_message(format!("managed folder '{}'", folder_name))]);
+            ExitCode::SUCCESS
         }
         Err(e) => {
-            eprintln!("Failed to add to managed folders: {}", e);
+            render::error(
+                format!("Failed to add to managed folders: {}", e),
+                Some(ErrorKind::Io),
+            );
+            ExitCode::from(exit_code::FATAL)
         }
     }
 }
 
-/// Add files to be tracked for symlinking (legacy heat command)
-pub fn heat_command(files: &[PathBuf]) {
-    stage_command(files, false, None)
+/// Open the engine, reporting failures to the user
+///
+/// On failure the exit code the command should finish with is returned.
+pub(crate) fn open_engine() -> Result<Engine, ExitCode> {
+    Engine::open().map_err(|e| fatal("open forge database", &e))
 }
 
-/// Stage files to be tracked for symlinking
-pub fn stage_command(files: &[PathBuf], recursive: bool, max_depth: Option<usize>) {
-    if recursive {
-        println!("Staging files and directories recursively");
-    } else if let Some(depth) = max_depth {
-        println!("Staging files and directories with max depth: {}", depth);
+/// Let the engine ask how to resolve conflicts under the prompt policy
+///
+/// Structured output cannot be interleaved with questions, so such conflicts are
+/// then reported as failures instead.
+pub(crate) fn prompting(engine: Engine) -> Engine {
+    if render::is_text() {
+        engine.with_prompt(ui::choose_conflict)
     } else {
-        println!("Staging files/directories: {:?}", files);
+        engine
     }
+}
+
+/// Open the engine and announce the active managed folder
+fn open_with_folder() -> Result<Engine, ExitCode> {
+    let engine = open_engine()?;
 
-    // Get the active managed folder
-    let (folder_name, forge_path) = match config::get_active_managed_folder() {
-        Ok(Some((name, path))) => (name, path),
-        Ok(None) => {
-            eprintln!("No managed folders found. Please run 'forge init' first.");
-            return;
+    match engine.active_folder() {
+        Ok((folder_name, forge_path)) => {
+            note!(
+                "Using managed folder '{}' at {}",
+                folder_name,
+                forge_path.display()
+            );
+            Ok(engine)
         }
         Err(e) => {
-            eprintln!("Failed to get managed folder: {}", e);
-            return;
-        }
-    };
-
-    println!(
-        "Using managed folder '{}' at {}",
-        folder_name,
-        forge_path.display()
-    );
-
-    if !forge_path.exists() {
-        match fs::create_dir_all(&forge_path) {
-            Ok(_) => println!("Created forge directory: {}", forge_path.display()),
-            Err(e) => {
-                eprintln!(
-                    "Failed to create forge directory {}: {}",
-                    forge_path.display(),
-                    e
-                );
-                return;
-            }
+            render::error(&e, Some(e.kind()));
+            Err(ExitCode::from(e.exit_code()))
         }
     }
+}
 
-    // Process each file or directory
-    for file in files {
-        // Normalize path
-        let abs_source = path_utils::normalize(file);
-
-        if !abs_source.exists() {
-            eprintln!("Path does not exist: {}", abs_source.display());
-            continue;
-        }
+/// Report an operation that could not run and return its exit code
+pub(crate) fn fatal(what: &str, error: &forge_rs::ForgeError) -> ExitCode {
+    render::error(format!("Failed to {}: {}", what, error), Some(error.kind()));
+    ExitCode::from(error.exit_code())
+}
 
-        if abs_source.is_dir() {
-            // Process directory
-            if recursive || max_depth.is_some() {
-                let walkdir_depth = match max_depth {
-                    Some(depth) => depth,
-                    None => usize::MAX, // Unlimited depth for recursive mode
-                };
-
-                println!(
-                    "Processing directory: {} (max depth: {})",
-                    abs_source.display(),
-                    if walkdir_depth == usize::MAX {
-                        "unlimited".to_string()
-                    } else {
-                        walkdir_depth.to_string()
-                    }
-                );
+/// Exit code summarizing a report
+pub(crate) fn report_exit(report: &forge_rs::Report) -> ExitCode {
+    ExitCode::from(report.exit_code())
+}
 
-                // Use walkdir to recursively process directory
-                for entry in walkdir::WalkDir::new(&abs_source)
-                    .min_depth(1) // Skip the root dir itself
-                    .max_depth(walkdir_depth)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(|e| e.path().is_file())
-                {
-                    // Get the directory name to create proper nesting
-                    let dir_name = abs_source.file_name().unwrap_or_default();
-
-                    // Calculate relative path from original directory
-                    let rel_path = entry
-                        .path()
-                        .strip_prefix(&abs_source)
-                        .unwrap_or_else(|_| Path::new(entry.file_name()));
-
-                    // Calculate target path in forge directory preserving subdirectories
-                    // Ensure the top-level directory name is included
-                    let target = forge_path.join(dir_name).join(rel_path);
-
-                    // Ensure target parent directory exists
-                    if let Some(parent) = target.parent() {
-                        if !parent.exists() {
-                            if let Err(e) = fs::create_dir_all(parent) {
-                                eprintln!("Failed to create directory {}: {}", parent.display(), e);
-                                continue;
-                            }
-                        }
-                    }
+/// Stage files to be tracked for symlinking
+pub fn stage_command(
+    files: &[PathBuf],
+    recursive: bool,
+    max_depth: Option<usize>,
+    layout: Option<Layout>,
+    package: Option<&str>,
+    mode: Option<DeployMode>,
+) -> ExitCode {
+    if recursive {
+        note!("Staging files and directories recursively");
+    } else if let Some(depth) = max_depth {
+        note!("Staging files and directories with max depth: {}", depth);
+    } else {
+        note!("Staging files/directories: {:?}", files);
+    }
 
-                    // Skip existing targets
-                    if target.exists() {
-                        println!(
-                            "Target already exists in forge folder: {}",
-                            target.display()
-                        );
-                        continue;
-                    }
+    let engine = match open_with_folder() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
 
-                    // Create a symlink from forge folder TO original file (reverse of final state)
-                    match symlink::create_symlink(entry.path(), &target) {
-                        Ok(_) => {
-                            println!(
-                                "Created staging symlink: {} → {}",
-                                target.display(),
-                                entry.path().display()
-                            );
-
-                            // Add to database as staged
-                            match config::stage_dotfile(entry.path(), &target, None) {
-                                Ok(_) => println!(
-                                    "Staged file: {} (use 'link' to make permanent)",
-                                    entry.path().display()
-                                ),
-                                Err(e) => {
-                                    eprintln!("Failed to stage {}: {}", entry.path().display(), e)
-                                }
-                            }
-                        }
-                        Err(e) => eprintln!(
-                            "Failed to create staging symlink for {}: {}",
-                            entry.path().display(),
-                            e
-                        ),
-                    }
-                }
-            } else {
-                println!(
-                    "Skipping directory: {} (use --recursive or --depth to include contents)",
-                    abs_source.display()
-                );
+    let options = StageOptions {
+        recursive,
+        depth: max_depth,
+        layout,
+        package: package.map(str::to_string),
+        mode,
+    };
 
-                // Even with no recursion, we still stage the directory itself
-                // Extract directory name for the target
-                if let Some(dirname) = file.file_name() {
-                    let target = forge_path.join(dirname);
-
-                    // Check if target exists
-                    if target.exists() {
-                        println!(
-                            "Target already exists in forge folder: {}",
-                            target.display()
-                        );
-                        continue;
-                    }
+    match engine.stage(files, &options) {
+        Ok(report) => {
+            render::print_report(&report);
+            note!("\nNOTE: Files are only staged. Use 'forge link' to create permanent symlinks.");
+            report_exit(&report)
+        }
+        Err(e) => fatal("stage files", &e),
+    }
+}
 
-                    // Create the directory in the forge folder
-                    if let Err(e) = fs::create_dir_all(&target) {
-                        eprintln!("Failed to create directory {}: {}", target.display(), e);
-                        continue;
-                    }
+/// Create symlinks for all staged/tracked files
+pub fn link_command(
+    files: &[PathBuf],
+    style: Option<LinkStyle>,
+    on_conflict: Option<ConflictPolicy>,
+) -> ExitCode {
+    note!("Creating symlinks");
+
+    let engine = match open_with_folder() {
+        Ok(engine) => prompting(engine),
+        Err(code) => return code,
+    };
 
-                    println!("Created directory in forge folder: {}", target.display());
-
-                    // Add to database as staged directory
-                    match config::stage_dotfile(&abs_source, &target, None) {
-                        Ok(_) => println!(
-                            "Staged directory: {} (use 'link' to make permanent)",
-                            abs_source.display()
-                        ),
-                        Err(e) => {
-                            eprintln!("Failed to stage directory {}: {}", abs_source.display(), e)
-                        }
-                    }
-                } else {
-                    eprintln!("Invalid directory path: {}", file.display());
-                }
+    match engine.link(files, &LinkOptions { style, on_conflict }) {
+        Ok(report) => {
+            render::print_report(&report);
+            if report.is_empty() {
+                note!("No files to link. Use 'stage' command to stage files first.");
+            } else {
+                render::print_summary("Symlink creation", &report);
             }
-        } else {
-            // Process regular file
-            // Extract filename for the target
-            if let Some(filename) = file.file_name() {
-                let target = forge_path.join(filename);
-
-                // Create a temporary symlink (or copy) from forge folder TO original file
-                if target.exists() {
-                    println!(
-                        "Target already exists in forge folder: {}",
-                        target.display()
-                    );
-                    continue;
-                }
+            report_exit(&report)
+        }
+        Err(e) => fatal("link files", &e),
+    }
+}
 
-                // Create a symlink from forge folder TO original file (reverse of final state)
-                match symlink::create_symlink(&abs_source, &target) {
-                    Ok(_) => {
-                        println!(
-                            "Created staging symlink: {} → {}",
-                            target.display(),
-                            abs_source.display()
-                        );
-
-                        // Add to database as staged
-                        match config::stage_dotfile(&abs_source, &target, None) {
-                            Ok(_) => println!(
-                                "Staged file: {} (use 'link' to make permanent)",
-                                abs_source.display()
-                            ),
-                            Err(e) => eprintln!("Failed to stage {}: {}", abs_source.display(), e),
-                        }
-                    }
-                    Err(e) => eprintln!(
-                        "Failed to create staging symlink for {}: {}",
-                        abs_source.display(),
-                        e
-                    ),
-                }
+/// Copy changes between files deployed as copies and their forge copies, and render
+/// stale templates again
+pub fn sync_command(files: &[PathBuf], on_conflict: Option<ConflictPolicy>) -> ExitCode {
+    let engine = match open_with_folder() {
+        Ok(engine) => prompting(engine),
+        Err(code) => return code,
+    };
+
+    match engine.sync(files, on_conflict) {
+        Ok(report) => {
+            render::print_report(&report);
+            if report.is_empty() {
+                note!("No files are deployed as copies or templates.");
             } else {
-                eprintln!("Invalid file path: {}", file.display());
+                render::print_summary("Sync", &report);
             }
+            report_exit(&report)
         }
+        Err(e) => fatal("sync files", &e),
     }
-
-    println!("\nNOTE: Files are only staged. Use 'forge link' to create permanent symlinks.");
 }
 
-/// Create symlinks for all staged/tracked files (legacy forge command)
-pub fn forge_command() {
-    link_command(&[])
-}
+/// Print what a template renders as on this machine
+pub fn render_command(file: &Path) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
 
-/// Create symlinks for all staged/tracked files
-pub fn link_command(files: &[PathBuf]) {
-    println!("Creating symlinks");
-
-    // Get the active managed folder
-    let (folder_name, forge_path) = match config::get_active_managed_folder() {
-        Ok(Some((name, path))) => (name, path),
-        Ok(None) => {
-            eprintln!("No managed folders found. Please run 'forge init' first.");
-            return;
-        }
-        Err(e) => {
-            eprintln!("Failed to get managed folder: {}", e);
-            return;
+    match engine.render(file) {
+        Ok(rendered) => {
+            render::print_rendered(&rendered);
+            ExitCode::SUCCESS
         }
-    };
+        Err(e) => fatal("render template", &e),
+    }
+}
 
-    println!(
-        "Using managed folder '{}' at {}",
-        folder_name,
-        forge_path.display()
-    );
+/// Unfold folded directories into per-file symlinks
+pub fn unfold_command(dirs: &[PathBuf]) -> ExitCode {
+    let engine = match open_with_folder() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
 
-    // Get all staged dotfiles from the database
-    let dotfiles = if files.is_empty() {
-        match config::get_staged_dotfiles(None) {
-            Ok(df) => df,
-            Err(e) => {
-                eprintln!("Error fetching staged files: {}", e);
-                return;
-            }
+    match engine.unfold(dirs) {
+        Ok(report) => {
+            render::print_report(&report);
+            render::print_summary("Unfolding", &report);
+            report_exit(&report)
         }
-    } else {
-        // If specific files requested, check if they exist and are staged
-        let mut result = Vec::new();
-
-        for file in files {
-            let abs_path = path_utils::normalize(file);
-
-            // Check if it's a specific file or a directory name
-            if abs_path.is_dir() {
-                // If it's a directory, find all staged files under that directory
-                match config::get_staged_dotfiles(None) {
-                    Ok(all_dotfiles) => {
-                        for df in all_dotfiles {
-                            // Check if this file is within the specified directory
-                            if df.is_staged() && df.source.starts_with(&abs_path) {
-                                result.push(df);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Error fetching staged files: {}", e);
-                    }
-                }
-
-                // Also check if the directory itself is staged
-                match config::find_dotfile_by_source(&abs_path) {
-                    Ok(Some(df)) => {
-                        if df.is_staged() {
-                            result.push(df);
-                        }
-                    }
-                    Ok(None) => {}
-                    Err(e) => {
-                        eprintln!("Error checking directory {}: {}", abs_path.display(), e);
-                    }
-                }
+        Err(e) => fatal("unfold directories", &e),
+    }
+}
 
-                // If using a relative path or just a directory name
-                if !abs_path.is_absolute() {
-                    let dir_name = file.file_name().unwrap_or_default();
-                    let forge_dir_path = forge_path.join(dir_name);
-
-                    // Check if dotfiles are in the forge directory with this name
-                    match config::get_staged_dotfiles(None) {
-                        Ok(all_dotfiles) => {
-                            for df in all_dotfiles {
-                                if df.is_staged() && df.target.starts_with(&forge_dir_path) {
-                                    result.push(df);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Error fetching staged files: {}", e);
-                        }
-                    }
-                }
+/// List all tracked files
+pub fn list_command(profile: Option<&str>) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
 
-                if result.is_empty() {
-                    eprintln!(
-                        "No staged files found for directory: {}",
-                        abs_path.display()
-                    );
-                }
-            } else {
-                // Original behavior for specific files
-                match config::find_dotfile_by_target(&abs_path) {
-                    Ok(Some(df)) => {
-                        if df.is_staged() {
-                            result.push(df);
-                        } else {
-                            println!("File already linked: {}", abs_path.display());
-                        }
-                    }
-                    Ok(None) => {
-                        // Try looking it up by source instead
-                        match config::find_dotfile_by_source(&abs_path) {
-                            Ok(Some(df)) => {
-                                if df.is_staged() {
-                                    result.push(df);
-                                } else {
-                                    println!("File already linked: {}", abs_path.display());
-                                }
-                            }
-                            Ok(None) => {
-                                eprintln!("File not found in staging: {}", abs_path.display());
-                            }
-                            Err(e) => {
-                                eprintln!("Error checking file {}: {}", abs_path.display(), e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Error checking file {}: {}", abs_path.display(), e);
-                    }
-                }
-            }
+    match engine.list(profile) {
+        Ok(dotfiles) => {
+            render::print_dotfiles(&dotfiles, profile);
+            ExitCode::SUCCESS
         }
+        Err(e) => fatal("fetch dotfiles", &e),
+    }
+}
+
+/// Check tracked files against the filesystem
+pub fn status_command(profile: Option<&str>, check: bool) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
 
-        result
+    let mut statuses = match engine.status(profile) {
+        Ok(statuses) => statuses,
+        Err(e) => return fatal("check dotfiles", &e),
     };
 
-    if dotfiles.is_empty() {
-        println!("No files to link. Use 'stage' command to stage files first.");
-        return;
+    if !check {
+        render::print_statuses(&statuses);
+        return ExitCode::SUCCESS;
     }
 
-    let mut success_count = 0;
-    let mut error_count = 0;
+    statuses.retain(|status| status.is_drifted());
+    render::print_statuses(&statuses);
+    if statuses.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(exit_code::DRIFT)
+    }
+}
 
-    // Link each dotfile
-    for dotfile in dotfiles {
-        // Check if source is a directory
-        if dotfile.source.is_dir() {
-            // For directories, we have a different process
-            println!("Processing directory: {}", dotfile.source.display());
+/// Diagnose drifted files and optionally repair them
+pub fn doctor_command(
+    profile: Option<&str>,
+    fix: bool,
+    dry_run: bool,
+    skip_confirm: bool,
+) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
 
-            // We can update the database status to linked but don't move the directory itself
-            if let Err(e) = config::link_dotfile(&dotfile.source, &dotfile.target) {
-                eprintln!("Failed to update directory tracking status: {}", e);
-                error_count += 1;
-            } else {
-                success_count += 1;
-            }
+    let diagnoses = match engine.diagnose(profile) {
+        Ok(diagnoses) => diagnoses,
+        Err(e) => return fatal("check dotfiles", &e),
+    };
 
-            // Continue to the next item
-            continue;
+    if !fix && !dry_run {
+        render::print_diagnoses(&diagnoses);
+        if !diagnoses.is_empty() {
+            note!("\nRun 'forge doctor --fix' to repair, or add --dry-run to preview.");
         }
+        return ExitCode::SUCCESS;
+    }
 
-        // Process files - first check if target is a file or symlink (not a dir)
-        if dotfile.target.is_file() || symlink::is_symlink(&dotfile.target) {
-            // Remove the staging symlink
-            if let Err(e) = fs::remove_file(&dotfile.target) {
-                eprintln!(
-                    "Failed to remove staging symlink {}: {}",
-                    dotfile.target.display(),
-                    e
-                );
-                error_count += 1;
+    if !dry_run && let Err(code) = require_yes(skip_confirm) {
+        return code;
+    }
+
+    // Every accepted repair is then made in one batch
+    let mut report = Report::new();
+    let mut accepted = Vec::new();
+    for diagnosis in diagnoses {
+        let link = &diagnosis.status.link;
+        if !dry_run && !skip_confirm && diagnosis.repair != Repair::Manual {
+            let prompt = format!(
+                "{} is {}: {}?",
+                link.display(),
+                diagnosis.status.health.as_str(),
+                diagnosis.repair.describe()
+            );
+            if !ui::confirm(&prompt) {
+                report.push(Outcome::skipped(Action::Repair, link, "declined"));
                 continue;
             }
-        } else if dotfile.target.is_dir() {
-            // For directory targets, just skip removal
-            println!(
-                "Target is a directory, skipping removal: {}",
-                dotfile.target.display()
-            );
         }
+        accepted.push(diagnosis);
+    }
+    match engine.repair(&accepted, dry_run) {
+        Ok(repaired) => report.extend(repaired),
+        Err(e) => return fatal("repair files", &e),
+    }
 
-        // Ensure target parent directory exists
-        if let Some(parent) = dotfile.target.parent() {
-            if !parent.exists() {
-                if let Err(e) = fs::create_dir_all(parent) {
-                    eprintln!("Failed to create directory {}: {}", parent.display(), e);
-                    error_count += 1;
-                    continue;
-                }
-            }
-        }
+    render::print_report(&report);
+    if report.is_empty() {
+        note!("No drift detected");
+    } else {
+        render::print_summary("Repair", &report);
+    }
+    report_exit(&report)
+}
 
-        // Move the original file to the forge directory
-        match fs::copy(&dotfile.source, &dotfile.target) {
-            Ok(_) => {
-                // Create symlink from original location to forge directory FIRST
-                match symlink::create_symlink(&dotfile.target, &dotfile.source) {
-                    Ok(_) => {
-                        // Only remove the original file AFTER symlink is successfully created
-                        if let Err(e) = fs::remove_file(&dotfile.source) {
-                            eprintln!(
-                                "Failed to remove original file {}: {}",
-                                dotfile.source.display(),
-                                e
-                            );
-                            // Try to remove the symlink we just created to maintain consistency
-                            if let Err(cleanup_err) = fs::remove_file(&dotfile.source) {
-                                eprintln!(
-                                    "Failed to cleanup symlink during error recovery: {}",
-                                    cleanup_err
-                                );
-                            }
-                            error_count += 1;
-                            continue;
-                        }
-
-                        println!(
-                            "Created symlink: {} → {}",
-                            dotfile.source.display(),
-                            dotfile.target.display()
-                        );
-
-                        // Update database status to linked
-                        if let Err(e) = config::link_dotfile(&dotfile.source, &dotfile.target) {
-                            eprintln!("Failed to update database: {}", e);
-                        }
-
-                        success_count += 1;
-                    }
-                    Err(e) => {
-                        eprintln!(
-                            "Failed to create symlink {} → {}: {}",
-                            dotfile.source.display(),
-                            dotfile.target.display(),
-                            e
-                        );
-
-                        // Original file is still intact, no need to restore
-                        println!("Original file preserved at: {}", dotfile.source.display());
-
-                        error_count += 1;
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!(
-                    "Failed to copy file {} to {}: {}",
-                    dotfile.source.display(),
-                    dotfile.target.display(),
-                    e
-                );
-                error_count += 1;
-            }
-        }
+/// Roll back or resume an interrupted batch of file operations
+pub fn recover_command(resume: bool) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
+
+    match engine.pending_journal() {
+        Ok(Some(journal)) => note!(
+            "Recovering interrupted {} of {} file(s)",
+            journal.action.as_str(),
+            journal.entries.len()
+        ),
+        Ok(None) => note!("Nothing to recover"),
+        Err(e) => return fatal("read journal", &e),
     }
 
-    println!(
-        "\nSymlink creation completed: {} succeeded, {} failed",
-        success_count, error_count
-    );
-}
+    let report = match engine.recover(resume) {
+        Ok(report) => report,
+        Err(e) => return fatal("recover", &e),
+    };
 
-/// Remove symlinks for specific files (legacy cool command)
-pub fn cool_command(files: &[PathBuf], _skip_confirm: bool) {
-    unlink_command(files, _skip_confirm)
+    render::print_report(&report);
+    if !report.is_empty() {
+        render::print_summary("Recovery", &report);
+    }
+    report_exit(&report)
 }
 
-/// List all tracked files
-pub fn list_command(profile: Option<&str>) {
-    crate::dotfile::list::print_dotfiles(profile);
+/// Show recorded operations, newest first
+pub fn log_command(limit: Option<usize>, verbose: bool) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
+
+    match engine.history(limit) {
+        Ok(operations) => {
+            render::print_history(&operations, verbose);
+            ExitCode::from(exit_code::SUCCESS)
+        }
+        Err(e) => fatal("read history", &e),
+    }
 }
 
-/// Remove symlinks but keep files in forge folder
-pub fn unlink_command(files: &[PathBuf], skip_confirm: bool) {
-    if files.is_empty() {
-        println!("No files specified to unlink. Here are all tracked files:");
-        crate::dotfile::list::print_dotfiles(None);
-        return;
+/// Reverse a recorded operation, or the most recent one that has not been undone
+pub fn undo_command(id: Option<&str>, skip_confirm: bool) -> ExitCode {
+    if let Err(code) = require_yes(skip_confirm) {
+        return code;
     }
 
-    // Get the active managed folder
-    let (folder_name, forge_path) = match config::get_active_managed_folder() {
-        Ok(Some((name, path))) => (name, path),
-        Ok(None) => {
-            eprintln!("No managed folders found. Please run 'forge init' first.");
-            return;
-        }
-        Err(e) => {
-            eprintln!("Failed to get managed folder: {}", e);
-            return;
-        }
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
     };
 
-    println!(
-        "Using managed folder '{}' at {}",
-        folder_name,
-        forge_path.display()
+    let operation = match engine.undo_candidate(id) {
+        Ok(operation) => operation,
+        Err(e) => return fatal("undo", &e),
+    };
+
+    let prompt = format!(
+        "Undo {} {} of {} file(s)?",
+        operation.action.as_str(),
+        operation.id,
+        operation.entries.len()
     );
+    if !skip_confirm && !ui::confirm(&prompt) {
+        note!("Undo cancelled");
+        return ExitCode::from(exit_code::SUCCESS);
+    }
 
-    // For each file, restore the original and remove the symlink
-    for file in files {
-        // Determine target path
-        let target = if file.is_absolute() {
-            // If absolute path, use it directly (might be a target)
-            file.clone()
-        } else if let Some(filename) = file.file_name() {
-            // If relative, assume it's a filename to be combined with forge dir
-            forge_path.join(filename)
-        } else {
-            eprintln!("Invalid file path: {}", file.display());
-            continue;
-        };
+    let report = match engine.undo(&operation) {
+        Ok(report) => report,
+        Err(e) => return fatal("undo", &e),
+    };
 
-        // Try to find the dotfile in the database
-        let dotfile = match config::find_dotfile_by_target(&target) {
-            Ok(Some(df)) => df,
-            Ok(None) => {
-                println!("No tracking record found for {}", target.display());
-
-                // Even if not tracked, check if it's a symlink we can remove
-                if file.file_name().is_some() {
-                    let orig_path = PathBuf::from(file);
-                    if symlink::is_symlink(&orig_path) {
-                        // Confirm with user
-                        if !skip_confirm {
-                            let msg = format!(
-                                "This will remove the symlink at {} but keep the file in the forge folder.",
-                                orig_path.display()
-                            );
-                            if !crate::utils::ui::confirm(&msg) {
-                                println!("Skipping {}", orig_path.display());
-                                continue;
-                            }
-                        }
-
-                        match fs::remove_file(&orig_path) {
-                            Ok(_) => println!("Removed untracked symlink: {}", orig_path.display()),
-                            Err(e) => {
-                                println!("Failed to remove symlink {}: {}", orig_path.display(), e)
-                            }
-                        }
-                    } else {
-                        println!("Not a symlink or doesn't exist: {}", orig_path.display());
-                    }
-                }
-                continue;
-            }
-            Err(e) => {
-                eprintln!("Error looking up dotfile: {}", e);
-                continue;
-            }
-        };
+    render::print_report(&report);
+    render::print_summary("Undo", &report);
+    report_exit(&report)
+}
 
-        // Confirm with user
-        if !skip_confirm {
-            let msg = format!(
-                "This will remove the symlink at {} but keep the file in the forge folder.\nThe original file will be restored to {}.",
-                dotfile.source.display(),
-                dotfile.source.display()
-            );
-            if !crate::utils::ui::confirm(&msg) {
-                println!("Skipping {}", dotfile.source.display());
-                continue;
-            }
-        }
+/// Rebuild the tracking database from the symlinks on disk
+pub fn reindex_command(roots: &[PathBuf], dry_run: bool) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
 
-        // Copy from forge to original location
-        match fs::copy(&dotfile.target, &dotfile.source) {
-            Ok(_) => {
-                // Remove the symlink
-                match fs::remove_file(&dotfile.source) {
-                    Ok(_) => {
-                        println!(
-                            "Removed symlink and restored file: {}",
-                            dotfile.source.display()
-                        );
-
-                        // Update status in database
-                        match config::deactivate_dotfile(&dotfile.target) {
-                            Ok(_) => (),
-                            Err(e) => eprintln!("Failed to update database: {}", e),
-                        }
-                    }
-                    Err(e) => println!(
-                        "Failed to remove symlink {}: {}",
-                        dotfile.source.display(),
-                        e
-                    ),
-                }
-            }
-            Err(e) => println!(
-                "Failed to restore file from {}: {}",
-                dotfile.target.display(),
-                e
-            ),
-        }
-    }
-}
+    let roots = if roots.is_empty() {
+        vec![dirs::home_dir().unwrap_or_else(|| PathBuf::from("."))]
+    } else {
+        roots.to_vec()
+    };
 
-/// Remove files from forge folder but keep original files
-pub fn remove_command(files: &[PathBuf], skip_confirm: bool) {
-    if files.is_empty() {
-        println!("No files specified to remove. Here are all tracked files:");
-        crate::dotfile::list::print_dotfiles(None);
-        return;
-    }
+    let report = match engine.reindex(&roots, dry_run) {
+        Ok(report) => report,
+        Err(e) => return fatal("reindex", &e),
+    };
 
-    // Get the active managed folder
-    let (folder_name, forge_path) = match config::get_active_managed_folder() {
-        Ok(Some((name, path))) => (name, path),
-        Ok(None) => {
-            eprintln!("No managed folders found. Please run 'forge init' first.");
-            return;
-        }
+    render::print_report(&report);
+    render::print_summary("Reindex", &report);
+    report_exit(&report)
+}
+
+/// Show all tracked files when a command was given nothing to work on
+fn print_tracked(engine: &Engine) {
+    match engine.list(None) {
+        Ok(dotfiles) => render::print_dotfiles(&dotfiles, None),
         Err(e) => {
-            eprintln!("Failed to get managed folder: {}", e);
-            return;
+            fatal("fetch dotfiles", &e);
         }
-    };
+    }
+}
 
-    println!(
-        "Using managed folder '{}' at {}",
-        folder_name,
-        forge_path.display()
-    );
+/// Refuse to prompt when output is structured, since prompts would corrupt it
+fn require_yes(skip_confirm: bool) -> Result<(), ExitCode> {
+    if !skip_confirm && !render::is_text() {
+        render::error(
+            "--yes is required with --format json or ndjson",
+            Some(ErrorKind::InvalidInput),
+        );
+        return Err(ExitCode::from(exit_code::USAGE));
+    }
+    Ok(())
+}
 
-    // For each file, remove tracking and delete from forge folder
-    for file in files {
-        // Determine target path
-        let target = if file.is_absolute() {
-            // If absolute path, use it directly (might be a target)
-            file.clone()
-        } else if let Some(filename) = file.file_name() {
-            // If relative, assume it's a filename to be combined with forge dir
-            forge_path.join(filename)
-        } else {
-            eprintln!("Invalid file path: {}", file.display());
-            continue;
-        };
+/// Resolve the named files and ask the user to confirm each one
+fn select_confirmed<F>(
+    engine: &Engine,
+    files: &[PathBuf],
+    skip_confirm: bool,
+    confirm: F,
+) -> Result<Vec<Selection>, ExitCode>
+where
+    F: Fn(&Selection) -> bool,
+{
+    require_yes(skip_confirm)?;
+
+    let selections = engine
+        .select(files)
+        .map_err(|e| fatal("look up dotfiles", &e))?;
+
+    Ok(selections
+        .into_iter()
+        .filter(|selection| skip_confirm || confirm(selection))
+        .collect())
+}
 
-        // Try to find the dotfile in the database
-        let dotfile = match config::find_dotfile_by_target(&target) {
-            Ok(Some(df)) => df,
-            Ok(None) => {
-                println!("No tracking record found for {}", target.display());
-                continue;
-            }
-            Err(e) => {
-                eprintln!("Error looking up dotfile: {}", e);
-                continue;
-            }
-        };
+/// Remove symlinks but keep files in forge folder
+pub fn unlink_command(files: &[PathBuf], skip_confirm: bool) -> ExitCode {
+    let engine = match open_with_folder() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
 
-        // Confirm with user
-        if !skip_confirm {
-            let msg = format!(
-                "This will:\n- Remove the symlink at {}\n- Delete the file from the forge folder\n- Keep the original file at {}\n- Remove tracking information from the database",
-                dotfile.source.display(),
-                dotfile.source.display()
-            );
-            if !crate::utils::ui::confirm(&msg) {
-                println!("Skipping {}", dotfile.source.display());
-                continue;
-            }
-        }
+    if files.is_empty() {
+        note!("No files specified to unlink. Here are all tracked files:");
+        print_tracked(&engine);
+        return ExitCode::SUCCESS;
+    }
 
-        // Unlink first
-        if symlink::is_symlink(&dotfile.source) {
-            // Copy from forge to original location
-            match fs::copy(&dotfile.target, &dotfile.source) {
-                Ok(_) => {
-                    // Remove the symlink
-                    if let Err(e) = fs::remove_file(&dotfile.source) {
-                        println!(
-                            "Failed to remove symlink {}: {}",
-                            dotfile.source.display(),
-                            e
-                        );
-                        continue;
-                    }
-                }
-                Err(e) => {
-                    println!(
-                        "Failed to restore file from {}: {}",
-                        dotfile.target.display(),
-                        e
-                    );
-                    continue;
+    let selections = match select_confirmed(&engine, files, skip_confirm, |selection| {
+        let (path, msg) = match selection {
+            Selection::Tracked(dotfile) => (
+                &dotfile.source,
+                format!(
+                    "This will remove the symlink at {} but keep the file in the forge folder.\nThe original file will be restored to {}.",
+                    dotfile.source.display(),
+                    dotfile.source.display()
+                ),
+            ),
+            Selection::Untracked(path) => {
+                // Only untracked symlinks need confirming; anything else is reported as skipped
+                if !symlink::is_symlink(path) {
+                    return true;
                 }
+                (
+                    path,
+                    format!(
+                        "This will remove the symlink at {} but keep the file in the forge folder.",
+                        path.display()
+                    ),
+                )
             }
-        }
-
-        // Delete from forge folder
-        match fs::remove_file(&dotfile.target) {
-            Ok(_) => {
-                println!(
-                    "Removed file from forge folder: {}",
-                    dotfile.target.display()
-                );
+        };
+        let confirmed = ui::confirm(&msg);
+        if !confirmed {
+            note!("Skipping {}", path.display());
+        }
+        confirmed
+    }) {
+        Ok(selections) => selections,
+        Err(code) => return code,
+    };
 
-                // Remove from database
-                match config::remove_dotfile(&dotfile.target) {
-                    Ok(_) => println!("Removed tracking for {}", dotfile.target.display()),
-                    Err(e) => eprintln!("Failed to update database: {}", e),
-                }
-            }
-            Err(e) => println!("Failed to remove file {}: {}", dotfile.target.display(), e),
+    match engine.unlink(&selections) {
+        Ok(report) => {
+            render::print_report(&report);
+            report_exit(&report)
         }
+        Err(e) => fatal("unlink files", &e),
     }
 }
 
-/// Delete files completely from the system
-pub fn delete_command(files: &[PathBuf], skip_confirm: bool) {
+/// Remove files from forge folder but keep original files
+pub fn remove_command(files: &[PathBuf], skip_confirm: bool) -> ExitCode {
+    let engine = match open_with_folder() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
+
     if files.is_empty() {
-        println!("No files specified to delete. Here are all tracked files:");
-        crate::dotfile::list::print_dotfiles(None);
-        return;
+        note!("No files specified to remove. Here are all tracked files:");
+        print_tracked(&engine);
+        return ExitCode::SUCCESS;
     }
 
-    // Get the active managed folder
-    let (folder_name, forge_path) = match config::get_active_managed_folder() {
-        Ok(Some((name, path))) => (name, path),
-        Ok(None) => {
-            eprintln!("No managed folders found. Please run 'forge init' first.");
-            return;
-        }
-        Err(e) => {
-            eprintln!("Failed to get managed folder: {}", e);
-            return;
-        }
+    let selections = match select_confirmed(&engine, files, skip_confirm, |selection| {
+        let Selection::Tracked(dotfile) = selection else {
+            return true;
+        };
+        let msg = format!(
+            "This will:\n- Remove the symlink at {}\n- Delete the file from the forge folder\n- Keep the original file at {}\n- Remove tracking information from the database",
+            dotfile.source.display(),
+            dotfile.source.display()
+        );
+        let confirmed = ui::confirm(&msg);
+        if !confirmed {
+            note!("Skipping {}", dotfile.source.display());
+        }
+        confirmed
+    }) {
+        Ok(selections) => selections,
+        Err(code) => return code,
     };
 
-    println!(
-        "Using managed folder '{}' at {}",
-        folder_name,
-        forge_path.display()
-    );
-
-    // For each file, delete it completely
-    for file in files {
-        // Determine target path
-        let target = if file.is_absolute() {
-            // If absolute path, use it directly (might be a target)
-            file.clone()
-        } else if let Some(filename) = file.file_name() {
-            // If relative, assume it's a filename to be combined with forge dir
-            forge_path.join(filename)
-        } else {
-            eprintln!("Invalid file path: {}", file.display());
-            continue;
-        };
+    match engine.remove(&selections) {
+        Ok(report) => {
+            render::print_report(&report);
+            report_exit(&report)
+        }
+        Err(e) => fatal("remove files", &e),
+    }
+}
 
-        // Try to find the dotfile in the database
-        let dotfile = match config::find_dotfile_by_target(&target) {
-            Ok(Some(df)) => df,
-            Ok(None) => {
-                println!("No tracking record found for {}", target.display());
-
-                // Even if not tracked, confirm deletion
-                if !skip_confirm {
-                    let msg = format!(
-                        "WARNING: This will PERMANENTLY DELETE the file {} from your system.\nThis action CANNOT be undone.",
-                        file.display()
-                    );
-                    if !crate::utils::ui::confirm_with_text(&msg, "DELETE") {
-                        println!("Deletion cancelled.");
-                        continue;
-                    }
-                }
+/// Delete files completely from the system
+pub fn delete_command(files: &[PathBuf], skip_confirm: bool, purge: bool) -> ExitCode {
+    let engine = match open_with_folder() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
 
-                match fs::remove_file(file) {
-                    Ok(_) => println!("Deleted file: {}", file.display()),
-                    Err(e) => println!("Failed to delete file {}: {}", file.display(), e),
-                }
-                continue;
-            }
-            Err(e) => {
-                eprintln!("Error looking up dotfile: {}", e);
-                continue;
-            }
-        };
+    if files.is_empty() {
+        note!("No files specified to delete. Here are all tracked files:");
+        print_tracked(&engine);
+        return ExitCode::SUCCESS;
+    }
 
-        // Confirm with user
-        if !skip_confirm {
-            let msg = format!(
+    let selections = match select_confirmed(&engine, files, skip_confirm, |selection| {
+        let msg = match (selection, purge) {
+            (Selection::Tracked(dotfile), true) => format!(
                 "WARNING: This will PERMANENTLY DELETE the file from your system.\n\
                 - The symlink at {} will be removed\n\
                 - The file will be deleted from {}\n\
@@ -908,191 +675,190 @@ pub fn delete_command(files: &[PathBuf], skip_confirm: bool) {
                 This action CANNOT be undone.",
                 dotfile.source.display(),
                 dotfile.source.display()
-            );
-            if !crate::utils::ui::confirm_with_text(&msg, "DELETE") {
-                println!("Deletion cancelled.");
-                continue;
-            }
-        }
-
-        // Remove symlink if it exists
-        if symlink::is_symlink(&dotfile.source) {
-            if let Err(e) = fs::remove_file(&dotfile.source) {
-                println!(
-                    "Failed to remove symlink {}: {}",
-                    dotfile.source.display(),
-                    e
-                );
-                println!("Continuing with deletion...");
+            ),
+            (Selection::Untracked(path), true) => format!(
+                "WARNING: This will PERMANENTLY DELETE the file {} from your system.\nThis action CANNOT be undone.",
+                path.display()
+            ),
+            (Selection::Tracked(dotfile), false) => format!(
+                "This will delete the file from your system.\n\
+                - The file at {} and the forge copy will be moved to the trash\n\
+                - All tracking information will be removed from the database\n\
+                'forge undo' or your file manager can bring it back. Continue?",
+                dotfile.source.display()
+            ),
+            (Selection::Untracked(path), false) => {
+                format!("Move the file {} to the trash?", path.display())
             }
+        };
+        // Only permanent deletion asks for the word to be typed out
+        let confirmed = if purge {
+            ui::confirm_with_text(&msg, "DELETE")
         } else {
-            // Remove original file if it's not a symlink
-            if let Err(e) = fs::remove_file(&dotfile.source) {
-                println!(
-                    "Failed to remove original file {}: {}",
-                    dotfile.source.display(),
-                    e
-                );
-                println!("Continuing with deletion...");
-            }
+            ui::confirm(&msg)
+        };
+        if !confirmed {
+            note!("Deletion cancelled.");
         }
+        confirmed
+    }) {
+        Ok(selections) => selections,
+        Err(code) => return code,
+    };
 
-        // Delete from forge folder
-        match fs::remove_file(&dotfile.target) {
-            Ok(_) => {
-                println!(
-                    "Deleted file from forge folder: {}",
-                    dotfile.target.display()
-                );
-
-                // Remove from database
-                match config::remove_dotfile(&dotfile.target) {
-                    Ok(_) => println!("Removed tracking for {}", dotfile.target.display()),
-                    Err(e) => eprintln!("Failed to update database: {}", e),
-                }
-            }
-            Err(e) => println!("Failed to delete file {}: {}", dotfile.target.display(), e),
+    match engine.delete(&selections, purge) {
+        Ok(report) => {
+            render::print_report(&report);
+            report_exit(&report)
         }
-
-        println!(
-            "File {} has been completely deleted from the system.",
-            dotfile.source.display()
-        );
+        Err(e) => fatal("delete files", &e),
     }
 }
 
+pub mod backups;
+pub mod db;
 pub mod pack;
+pub mod stow;
 
 pub mod profile {
-    use crate::config;
+    use super::super::render;
+    use super::{fatal, open_engine, prompting, report_exit};
+    use forge_rs::config;
+    use forge_rs::config::folder::ConflictPolicy;
+    use forge_rs::error::exit_code;
+    use forge_rs::{Action, ErrorKind, Outcome};
+    use serde::Serialize;
     use std::fs;
     use std::path::PathBuf;
+    use std::process::ExitCode;
 
     const PROFILES_DIR: &str = ".forge/profiles";
 
+    /// A profile as listed with structured output
+    #[derive(Serialize)]
+    struct ProfileRecord {
+        name: String,
+        path: PathBuf,
+    }
+
     /// Create a new profile
-    pub fn create(name: &str) {
-        println!("Creating profile: {}", name);
+    pub fn create(name: &str) -> ExitCode {
+        note!("Creating profile: {}", name);
 
         // Create profile directory
         let home_dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
         let profile_dir = home_dir.join(PROFILES_DIR).join(name);
 
         if profile_dir.exists() {
-            println!("Profile '{}' already exists", name);
-            return;
+            render::error(
+                format!("Profile '{}' already exists", name),
+                Some(ErrorKind::Conflict),
+            );
+            return ExitCode::from(exit_code::CONFLICT);
         }
 
         match fs::create_dir_all(&profile_dir) {
-            Ok(_) => println!("Profile '{}' created at {:?}", name, profile_dir),
-            Err(e) => println!("Failed to create profile directory: {}", e),
+            Ok(_) => {
+                note!("Profile '{}' created at {:?}", name, profile_dir);
+                render::emit(&[Outcome::done(Action::Init, &profile_dir)
+                    .with_message(format!("profile '{}'", name))]);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                render::error(
+                    format!("Failed to create profile directory: {}", e),
+                    Some(ErrorKind::Io),
+                );
+                ExitCode::from(exit_code::FATAL)
+            }
         }
     }
 
     /// List available profiles
-    pub fn list() {
-        println!("Available profiles:");
+    pub fn list() -> ExitCode {
+        note!("Available profiles:");
 
         // Get the profiles directory
         let home_dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
         let profiles_dir = home_dir.join(PROFILES_DIR);
 
         if !profiles_dir.exists() {
-            println!("No profiles found");
-            return;
+            note!("No profiles found");
+            render::emit::<ProfileRecord>(&[]);
+            return ExitCode::SUCCESS;
         }
 
         // Read the directories in the profiles directory
         match fs::read_dir(&profiles_dir) {
             Ok(entries) => {
-                let mut found = false;
+                let mut profiles = Vec::new();
                 for entry in entries {
-                    if let Ok(entry) = entry {
-                        if let Ok(metadata) = entry.metadata() {
-                            if metadata.is_dir() {
-                                found = true;
-                                if let Some(name) = entry.file_name().to_str() {
-                                    println!("  - {}", name);
-                                }
-                            }
-                        }
+                    if let Ok(entry) = entry
+                        && let Ok(metadata) = entry.metadata()
+                        && metadata.is_dir()
+                        && let Some(name) = entry.file_name().to_str()
+                    {
+                        note!("  - {}", name);
+                        profiles.push(ProfileRecord {
+                            name: name.to_string(),
+                            path: entry.path(),
+                        });
                     }
                 }
 
-                if !found {
-                    println!("No profiles found");
+                if profiles.is_empty() {
+                    note!("No profiles found");
                 }
+                render::emit(&profiles);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                render::error(
+                    format!("Error reading profiles directory: {}", e),
+                    Some(ErrorKind::Io),
+                );
+                ExitCode::from(exit_code::FATAL)
             }
-            Err(e) => println!("Error reading profiles directory: {}", e),
         }
     }
 
     /// Switch to a profile
-    pub fn switch(name: &str) {
-        println!("Switching to profile: {}", name);
+    pub fn switch(name: &str, on_conflict: Option<ConflictPolicy>) -> ExitCode {
+        note!("Switching to profile: {}", name);
 
         // Check if profile exists
         let home_dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
         let profile_dir = home_dir.join(PROFILES_DIR).join(name);
 
         if !profile_dir.exists() {
-            println!("Profile '{}' does not exist", name);
-            return;
+            render::error(
+                format!("Profile '{}' does not exist", name),
+                Some(ErrorKind::NotFound),
+            );
+            return ExitCode::from(exit_code::FATAL);
         }
 
         // Get default target directory from config
-        let target_dir = config::read_default_path();
-        let target_path = PathBuf::from(&target_dir);
+        let target_dir = PathBuf::from(config::read_default_path());
 
-        // Create symlinks from profile directory to target
-        match crate::symlink::create_symlinks(&profile_dir, &target_dir) {
-            Ok(_) => {
-                println!("Created symlinks from profile '{}' successfully", name);
-
-                // Track the files in the database with the profile
-                let mut success_count = 0;
-                let mut error_count = 0;
-
-                for entry in walkdir::WalkDir::new(&profile_dir)
-                    .min_depth(1)
-                    .max_depth(1)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(|e| e.path().is_file())
-                {
-                    let source = entry.path();
-                    let filename = entry.file_name();
-                    let target = target_path.join(filename);
-
-                    match config::add_dotfile(source, &target, Some(name)) {
-                        Ok(_) => {
-                            println!(
-                                "Added to profile '{}': {} → {}",
-                                name,
-                                source.display(),
-                                target.display()
-                            );
-                            success_count += 1;
-                        }
-                        Err(e) => {
-                            eprintln!(
-                                "Failed to add to profile '{}': {} → {}: {}",
-                                name,
-                                source.display(),
-                                target.display(),
-                                e
-                            );
-                            error_count += 1;
-                        }
-                    }
-                }
+        let engine = match open_engine() {
+            Ok(engine) => prompting(engine),
+            Err(code) => return code,
+        };
 
-                println!(
-                    "\nProfile '{}' activated: {} files tracked, {} failed",
-                    name, success_count, error_count
+        // Create symlinks from profile directory to target and track them
+        match engine.switch_profile(name, &profile_dir, &target_dir, on_conflict) {
+            Ok(report) => {
+                render::print_report(&report);
+                note!(
+                    "\nProfile '{}' activated: {} symlinks created, {} failed",
+                    name,
+                    report.succeeded(),
+                    report.failed()
                 );
+                report_exit(&report)
             }
-            Err(e) => println!("Error switching to profile '{}': {}", name, e),
+            Err(e) => fatal(&format!("switch to profile '{}'", name), &e),
         }
     }
 }
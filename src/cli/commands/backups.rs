@@ -0,0 +1,99 @@
+// Backup store commands
+use super::super::render;
+use super::{fatal, open_engine, prompting, report_exit};
+use forge_rs::config::folder::ConflictPolicy;
+use forge_rs::engine::Retention;
+use forge_rs::error::exit_code;
+use std::path::Path;
+use std::process::ExitCode;
+
+/// List stored backups, optionally only those of one path
+pub fn list(path: Option<&Path>) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
+
+    match engine.backups(path) {
+        Ok(backups) => {
+            render::print_backups(&backups);
+            ExitCode::from(exit_code::SUCCESS)
+        }
+        Err(e) => fatal("list backups", &e),
+    }
+}
+
+/// Show a backup's details and content
+pub fn show(id: &str) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
+
+    match engine.find_backup(id) {
+        Ok(backup) => {
+            render::print_backup(&backup, &engine.backup_content(&backup));
+            ExitCode::from(exit_code::SUCCESS)
+        }
+        Err(e) => fatal("show backup", &e),
+    }
+}
+
+/// Put a backup back at its original path, or at `to`
+pub fn restore(id: &str, to: Option<&Path>, on_conflict: Option<ConflictPolicy>) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => prompting(engine),
+        Err(code) => return code,
+    };
+
+    match engine.restore_backup(id, to, on_conflict) {
+        Ok(report) => {
+            render::print_report(&report);
+            render::print_summary("Restore", &report);
+            report_exit(&report)
+        }
+        Err(e) => fatal("restore backup", &e),
+    }
+}
+
+/// Drop the backups and history archives the retention policy no longer keeps
+///
+/// `keep` and `max_age` override the saved policy for this run, or replace it
+/// with `save`.
+pub fn prune(keep: Option<usize>, max_age: Option<u32>, dry_run: bool, save: bool) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
+
+    let saved = match engine.retention() {
+        Ok(retention) => retention,
+        Err(e) => return fatal("read retention policy", &e),
+    };
+    let retention = Retention {
+        keep: keep.unwrap_or(saved.keep),
+        max_age_days: max_age.or(saved.max_age_days),
+    };
+    if save {
+        if let Err(e) = engine.set_retention(&retention) {
+            return fatal("save retention policy", &e);
+        }
+        note!(
+            "Keeping {} version(s) of each path{}",
+            retention.keep,
+            retention
+                .max_age_days
+                .map(|days| format!(", none older than {} day(s)", days))
+                .unwrap_or_default()
+        );
+    }
+
+    match engine.prune(&retention, dry_run) {
+        Ok(report) => {
+            render::print_report(&report);
+            render::print_summary("Prune", &report);
+            report_exit(&report)
+        }
+        Err(e) => fatal("prune backups", &e),
+    }
+}
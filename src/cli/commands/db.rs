@@ -0,0 +1,116 @@
+// Database schema commands
+use super::super::render;
+use super::fatal;
+use forge_rs::config::Config;
+use forge_rs::config::schema::SchemaStatus;
+use forge_rs::error::exit_code;
+use forge_rs::{Action, ErrorKind, Outcome, Report};
+use std::process::ExitCode;
+
+/// Open the database without applying migrations
+fn open_unmigrated() -> Result<Config, ExitCode> {
+    let mut config = Config::new();
+    config
+        .connect_unmigrated()
+        .map_err(|e| fatal("open forge database", &e.into()))?;
+    Ok(config)
+}
+
+/// Report a database written by a newer forge
+fn newer_error(status: &SchemaStatus) -> ExitCode {
+    render::error(
+        format!(
+            "Database schema version {} is newer than this forge supports ({}); upgrade forge",
+            status.current, status.latest
+        ),
+        Some(ErrorKind::Database),
+    );
+    ExitCode::from(exit_code::FATAL)
+}
+
+/// Show the schema version and any pending migrations
+pub fn check() -> ExitCode {
+    let config = match open_unmigrated() {
+        Ok(config) => config,
+        Err(code) => return code,
+    };
+
+    let status = match config.schema_status() {
+        Ok(status) => status,
+        Err(e) => return fatal("read schema version", &e.into()),
+    };
+
+    if status.is_newer() {
+        return newer_error(&status);
+    }
+
+    render::emit_one(&status);
+    note!("Database: {}", config.get_db_path().display());
+    note!(
+        "Schema version: {} (latest: {})",
+        status.current,
+        status.latest
+    );
+
+    if status.is_current() {
+        note!("Schema is up to date");
+        return ExitCode::SUCCESS;
+    }
+
+    note!("Pending migrations:");
+    for migration in &status.pending {
+        note!("  {}: {}", migration.version, migration.description);
+    }
+    note!("\nRun 'forge db migrate' to apply them.");
+    ExitCode::from(exit_code::DRIFT)
+}
+
+/// Apply pending schema migrations
+pub fn migrate(dry_run: bool) -> ExitCode {
+    let mut config = match open_unmigrated() {
+        Ok(config) => config,
+        Err(code) => return code,
+    };
+
+    let status = match config.schema_status() {
+        Ok(status) => status,
+        Err(e) => return fatal("read schema version", &e.into()),
+    };
+
+    if status.is_newer() {
+        return newer_error(&status);
+    }
+
+    let db_path = config.get_db_path().clone();
+    let mut report = Report::new();
+    if dry_run {
+        for migration in &status.pending {
+            report.push(
+                Outcome::planned(Action::Migrate, &db_path).with_message(format!(
+                    "version {}: {}",
+                    migration.version, migration.description
+                )),
+            );
+        }
+    } else {
+        match config.migrate() {
+            Ok(applied) => {
+                for migration in applied {
+                    report.push(
+                        Outcome::done(Action::Migrate, &db_path).with_message(format!(
+                            "version {}: {}",
+                            migration.version, migration.description
+                        )),
+                    );
+                }
+            }
+            Err(e) => return fatal("migrate database", &e.into()),
+        }
+    }
+
+    render::print_report(&report);
+    if report.is_empty() {
+        note!("Schema is up to date (version {})", status.latest);
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,40 @@
+// GNU Stow compatibility commands
+use super::super::render;
+use super::{fatal, open_engine, report_exit};
+use forge_rs::engine::{ImportOptions, StowOptions};
+use std::path::Path;
+use std::process::ExitCode;
+
+/// Adopt the links of an existing stow directory
+pub fn import(dir: &Path, options: &ImportOptions) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
+
+    match engine.import_stow(dir, options) {
+        Ok(report) => {
+            render::print_report(&report);
+            render::print_summary("Import", &report);
+            report_exit(&report)
+        }
+        Err(e) => fatal("import stow directory", &e),
+    }
+}
+
+/// Stow, unstow and restow packages
+pub fn stow(options: &StowOptions) -> ExitCode {
+    let engine = match open_engine() {
+        Ok(engine) => engine,
+        Err(code) => return code,
+    };
+
+    match engine.stow(options) {
+        Ok(report) => {
+            render::print_report(&report);
+            render::print_summary("Stow", &report);
+            report_exit(&report)
+        }
+        Err(e) => fatal("stow packages", &e),
+    }
+}
@@ -1,5 +1,11 @@
-pub mod commands;
-
-pub fn parse_args() {
-    // Will move CLI parsing here from main.rs
+/// Print progress prose, which is suppressed for structured output formats
+macro_rules! note {
+    ($($arg:tt)*) => {
+        if $crate::cli::render::is_text() {
+            println!($($arg)*);
+        }
+    };
 }
+
+pub mod commands;
+pub mod render;
@@ -0,0 +1,484 @@
+// Terminal rendering of engine results
+use clap::ValueEnum;
+use forge_rs::config::folder::DeployMode;
+use forge_rs::dotfile::DotFile;
+use forge_rs::engine::journal::Step;
+use forge_rs::engine::{Backup, Diagnosis, FileStatus, Health, Operation, Rendered};
+use forge_rs::{Action, ErrorKind, Outcome, Report, Status};
+use serde::Serialize;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// How command results are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Format {
+    /// Human-readable prose
+    #[default]
+    Text,
+    /// A single JSON document
+    Json,
+    /// One JSON record per line
+    Ndjson,
+}
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+
+/// Choose the output format for the rest of the process
+pub fn set_format(format: Format) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn format() -> Format {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Whether prose output should be printed
+pub fn is_text() -> bool {
+    format() == Format::Text
+}
+
+/// Write records as a JSON array or as newline-delimited JSON
+pub fn emit<T: Serialize>(records: &[T]) {
+    let result = match format() {
+        Format::Text => return,
+        Format::Json => serde_json::to_string_pretty(records).map(|json| println!("{}", json)),
+        Format::Ndjson => records
+            .iter()
+            .try_for_each(|record| serde_json::to_string(record).map(|json| println!("{}", json))),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Failed to serialize output: {}", e);
+    }
+}
+
+/// Write a single structured document
+pub fn emit_one<T: Serialize>(record: &T) {
+    let result = match format() {
+        Format::Text => return,
+        Format::Json => serde_json::to_string_pretty(record),
+        Format::Ndjson => serde_json::to_string(record),
+    };
+
+    match result {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("Failed to serialize output: {}", e),
+    }
+}
+
+/// An error that stopped a command before it produced any records
+#[derive(Serialize)]
+struct ErrorRecord<'a> {
+    status: Status,
+    error: Option<ErrorKind>,
+    message: &'a str,
+}
+
+/// Report an error that stopped a command
+///
+/// Text output goes to stderr; structured formats write an error record to stdout
+/// so scripts always receive valid JSON.
+pub fn error(message: impl Display, kind: Option<ErrorKind>) {
+    if is_text() {
+        eprintln!("{}", message);
+    } else {
+        emit_one(&ErrorRecord {
+            status: Status::Failed,
+            error: kind,
+            message: &message.to_string(),
+        });
+    }
+}
+
+/// Past-tense label used for completed actions
+///
+/// A link is labelled by how the file was deployed.
+fn done_label(action: Action, mode: Option<DeployMode>) -> &'static str {
+    match action {
+        Action::Init => "Initialized",
+        Action::Stage => "Staged",
+        Action::Link => match mode {
+            Some(DeployMode::Copy) => "Copied",
+            Some(DeployMode::Hardlink) => "Hard-linked",
+            Some(DeployMode::Template) => "Rendered",
+            Some(DeployMode::Symlink) | None => "Created symlink",
+        },
+        Action::Unfold => "Unfolded",
+        Action::Unlink => "Unlinked",
+        Action::Sync => "Synced",
+        Action::Render => "Rendered",
+        Action::Remove => "Removed from forge",
+        Action::Delete => "Deleted",
+        Action::Pack => "Packed",
+        Action::Unpack => "Removed from pack",
+        Action::Seal => "Sealed",
+        Action::Install => "Installed",
+        Action::Restore => "Restored",
+        Action::Prune => "Pruned",
+        Action::Repair => "Repaired",
+        Action::Reindex => "Indexed",
+        Action::Import => "Adopted",
+        Action::Stow => "Stowed",
+        Action::Unstow => "Unstowed",
+        Action::Migrate => "Migrated",
+        Action::Rollback => "Rolled back",
+        Action::Undo => "Reverted",
+    }
+}
+
+/// Render a single outcome as one line of text
+pub fn outcome_line(outcome: &Outcome) -> String {
+    let mut line = match outcome.status {
+        Status::Done => format!(
+            "{}: {}",
+            done_label(outcome.action, outcome.mode),
+            outcome.path.display()
+        ),
+        Status::Planned => format!(
+            "Would {}: {}",
+            outcome.action.as_str(),
+            outcome.path.display()
+        ),
+        Status::Skipped => format!("Skipped {}", outcome.path.display()),
+        Status::Failed => format!(
+            "Failed to {} {}",
+            outcome.action.as_str(),
+            outcome.path.display()
+        ),
+    };
+
+    if let Some(target) = &outcome.target
+        && matches!(outcome.status, Status::Done | Status::Planned)
+    {
+        line.push_str(&format!(" → {}", target.display()));
+    }
+
+    if let Some(message) = &outcome.message {
+        match outcome.status {
+            Status::Skipped | Status::Failed => line.push_str(&format!(": {}", message)),
+            Status::Done | Status::Planned => line.push_str(&format!(" ({})", message)),
+        }
+    }
+
+    line
+}
+
+/// Print every outcome in a report, sending failures to stderr
+pub fn print_report(report: &Report) {
+    if !is_text() {
+        emit(&report.outcomes);
+        return;
+    }
+
+    for outcome in &report.outcomes {
+        if outcome.is_failed() {
+            eprintln!("{}", outcome_line(outcome));
+        } else {
+            println!("{}", outcome_line(outcome));
+        }
+    }
+}
+
+/// Print a one-line summary of a report
+pub fn print_summary(what: &str, report: &Report) {
+    note!(
+        "\n{} completed: {} succeeded, {} skipped, {} failed",
+        what,
+        report.succeeded(),
+        report.count(Status::Skipped),
+        report.failed()
+    );
+}
+
+/// Print the health of tracked files followed by a drift count
+pub fn print_statuses(statuses: &[FileStatus]) {
+    if !is_text() {
+        emit(statuses);
+        return;
+    }
+
+    for status in statuses {
+        let mut line = format!(
+            "  [{}] {} → {}",
+            status.health.as_str(),
+            status.link.display(),
+            status.expected.display()
+        );
+        if let Some(actual) = &status.actual
+            && status.health != Health::Ok
+        {
+            line.push_str(&format!(" (points to {})", actual.display()));
+        }
+        println!("{}", line);
+    }
+
+    let drifted = statuses.iter().filter(|s| s.is_drifted()).count();
+    if drifted == 0 {
+        println!("No drift detected");
+    } else {
+        println!("\n{} drifted", drifted);
+    }
+}
+
+/// Print drifted files with the repair for each
+pub fn print_diagnoses(diagnoses: &[Diagnosis]) {
+    if !is_text() {
+        emit(diagnoses);
+        return;
+    }
+
+    if diagnoses.is_empty() {
+        println!("No drift detected");
+        return;
+    }
+
+    for diagnosis in diagnoses {
+        println!(
+            "  [{}] {} → {}: {}",
+            diagnosis.status.health.as_str(),
+            diagnosis.status.link.display(),
+            diagnosis.status.expected.display(),
+            diagnosis.repair.describe()
+        );
+    }
+}
+
+/// Print recorded operations, with the steps of each file when `verbose`
+pub fn print_history(operations: &[Operation], verbose: bool) {
+    if !is_text() {
+        emit(operations);
+        return;
+    }
+
+    if operations.is_empty() {
+        println!("No operations recorded");
+        return;
+    }
+
+    for operation in operations {
+        let mut line = format!(
+            "{}  {}  {} ({} file(s))",
+            operation.id,
+            operation.created_at,
+            operation.action.as_str(),
+            operation.entries.len()
+        );
+        if let Some(undoes) = &operation.undoes {
+            line.push_str(&format!(", undoes {}", undoes));
+        }
+        if let Some(undone_by) = &operation.undone_by {
+            line.push_str(&format!(", undone by {}", undone_by));
+        }
+        if operation.pruned_at.is_some() {
+            line.push_str(", pruned");
+        }
+        println!("{}", line);
+
+        if !verbose {
+            continue;
+        }
+        for entry in &operation.entries {
+            println!("    {}", entry.path.display());
+            for step in &entry.steps {
+                println!("      {}", step_line(step));
+            }
+        }
+    }
+}
+
+/// Print stored backup versions, newest first
+pub fn print_backups(backups: &[Backup]) {
+    if !is_text() {
+        emit(backups);
+        return;
+    }
+
+    if backups.is_empty() {
+        println!("No backups stored");
+        return;
+    }
+
+    for backup in backups {
+        println!(
+            "{}  {}  {} ({})",
+            backup.id,
+            backup.created_at.format("%Y-%m-%d %H:%M:%S"),
+            backup.path.display(),
+            size_line(backup)
+        );
+    }
+}
+
+/// Print one backup version with its content
+///
+/// Text files are shown in full and directories as the files they hold; other
+/// content is only described.
+pub fn print_backup(backup: &Backup, content: &Path) {
+    if !is_text() {
+        emit_one(backup);
+        return;
+    }
+
+    println!("Backup:  {}", backup.id);
+    println!("Path:    {}", backup.path.display());
+    println!(
+        "Created: {}",
+        backup.created_at.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    println!("Hash:    {}", backup.hash);
+    println!("Size:    {}", size_line(backup));
+    println!();
+
+    if backup.directory {
+        for entry in walkdir::WalkDir::new(content)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .flatten()
+        {
+            let relative = entry.path().strip_prefix(content).unwrap_or(entry.path());
+            println!("{}", relative.display());
+        }
+        return;
+    }
+    match fs::read(content).map(String::from_utf8) {
+        Ok(Ok(text)) => print!("{}", text),
+        Ok(Err(_)) => println!("(binary content not shown)"),
+        Err(e) => println!("(content unreadable: {})", e),
+    }
+}
+
+/// Print a rendered template as it would be deployed
+///
+/// The output is printed as it is, so it can be piped or compared; whether the
+/// deployed file is stale goes to stderr.
+pub fn print_rendered(rendered: &Rendered) {
+    if !is_text() {
+        emit_one(rendered);
+        return;
+    }
+
+    print!("{}", rendered.output);
+    if let Some(deployed) = &rendered.deployed
+        && rendered.stale
+    {
+        eprintln!(
+            "{} is stale; run `forge sync` to deploy this output",
+            deployed.display()
+        );
+    }
+}
+
+/// The size of a backup, with what kind of content it holds
+fn size_line(backup: &Backup) -> String {
+    if backup.directory {
+        format!("directory, {} bytes", backup.size)
+    } else {
+        format!("{} bytes", backup.size)
+    }
+}
+
+/// Describe one filesystem step of a recorded operation
+fn step_line(step: &Step) -> String {
+    match step {
+        Step::Copy { from, to } => format!("copy {} → {}", from.display(), to.display()),
+        Step::Symlink {
+            link,
+            dest,
+            relative,
+        } => format!(
+            "symlink {} → {}{}",
+            link.display(),
+            dest.display(),
+            if *relative { " (relative)" } else { "" }
+        ),
+        Step::Remove { path } => format!("remove {} (kept in history)", path.display()),
+        Step::Restore { to, .. } => format!("restore {}", to.display()),
+        Step::CreateDir { path } => format!("mkdir {}", path.display()),
+        Step::Backup { path, to } => format!("back up {} → {}", path.display(), to.display()),
+        Step::Trash { path, to } => format!("trash {} → {}", path.display(), to.display()),
+        Step::Untrash { from, to } => format!("untrash {} → {}", from.display(), to.display()),
+        Step::Purge { path } => format!("delete {} (not kept)", path.display()),
+        Step::Relink {
+            link,
+            dest,
+            relative,
+        } => format!(
+            "relink {} → {}{}",
+            link.display(),
+            dest.display(),
+            if *relative { " (relative)" } else { "" }
+        ),
+        Step::Replace { path, from } => {
+            format!("replace {} with copy of {}", path.display(), from.display())
+        }
+        Step::Hardlink { link, dest } => {
+            format!("hardlink {} → {}", link.display(), dest.display())
+        }
+    }
+}
+
+/// Print tracked dotfiles
+pub fn print_dotfiles(dotfiles: &[DotFile], profile: Option<&str>) {
+    if !is_text() {
+        emit(dotfiles);
+        return;
+    }
+
+    let profile_str = profile.unwrap_or("all profiles");
+    println!("\nDotfiles ({})", profile_str);
+
+    if dotfiles.is_empty() {
+        println!("  No dotfiles found");
+        return;
+    }
+
+    for dotfile in dotfiles {
+        let profile_info = match dotfile.profile {
+            Some(ref p) => format!(" (profile: {})", p),
+            None => String::new(),
+        };
+        let mode_info = match dotfile.mode {
+            DeployMode::Symlink => String::new(),
+            mode => format!(" (mode: {})", mode.as_str()),
+        };
+
+        let status = forge_rs::utils::ui::format_dotfile_status(dotfile.status);
+
+        println!(
+            "  [{}] {} → {}{}{}",
+            status,
+            dotfile.source.display(),
+            dotfile.target.display(),
+            profile_info,
+            mode_info
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_label_follows_deploy_mode() {
+        let path = Path::new("/home/user/.gitconfig");
+        let cases = [
+            (None, "Created symlink"),
+            (Some(DeployMode::Symlink), "Created symlink"),
+            (Some(DeployMode::Copy), "Copied"),
+            (Some(DeployMode::Hardlink), "Hard-linked"),
+            (Some(DeployMode::Template), "Rendered"),
+        ];
+        for (mode, label) in cases {
+            let mut outcome = Outcome::done(Action::Link, path);
+            outcome.mode = mode;
+            assert_eq!(
+                outcome_line(&outcome),
+                format!("{}: /home/user/.gitconfig", label)
+            );
+        }
+    }
+}
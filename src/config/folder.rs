@@ -0,0 +1,299 @@
+// Settings stored inside a managed folder, so they travel with it
+use crate::error::{ForgeError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const FOLDER_CONFIG_FILE: &str = ".forge/config.toml";
+
+/// Where staged files are placed inside a managed folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// Mirror the path relative to the home directory (`.config/git/config`)
+    #[default]
+    Home,
+    /// Mirror the home-relative path under a package directory, as GNU Stow does
+    Package,
+    /// Use the file name only, keeping the contents of staged directories together
+    Flat,
+}
+
+impl Layout {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Layout::Home => "home",
+            Layout::Package => "package",
+            Layout::Flat => "flat",
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "home" => Ok(Layout::Home),
+            "package" => Ok(Layout::Package),
+            "flat" => Ok(Layout::Flat),
+            _ => Err(format!(
+                "unknown layout '{}' (expected home, package or flat)",
+                s
+            )),
+        }
+    }
+}
+
+/// How symlinks to files in a managed folder are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// The absolute path of the managed copy
+    #[default]
+    Absolute,
+    /// The path from the symlink's directory to the managed copy
+    Relative,
+}
+
+impl LinkStyle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStyle::Absolute => "absolute",
+            LinkStyle::Relative => "relative",
+        }
+    }
+
+    pub fn is_relative(&self) -> bool {
+        *self == LinkStyle::Relative
+    }
+}
+
+/// How a linked file is put in place outside the managed folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployMode {
+    /// A symlink to the managed copy
+    #[default]
+    Symlink,
+    /// A hard link to the managed copy, which must be on the same filesystem
+    ///
+    /// For programs that refuse to follow symlinks but write their files in place.
+    /// Saving by renaming a new file over the old one breaks the link, which status
+    /// reports as `replaced_by_file`.
+    Hardlink,
+    /// A real copy, kept in step with the managed copy by `forge sync`
+    ///
+    /// For programs that replace symlinks or refuse to follow them.
+    Copy,
+    /// A file generated by rendering the managed copy as a template
+    Template,
+}
+
+impl DeployMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeployMode::Symlink => "symlink",
+            DeployMode::Hardlink => "hardlink",
+            DeployMode::Copy => "copy",
+            DeployMode::Template => "template",
+        }
+    }
+}
+
+impl FromStr for DeployMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "symlink" => Ok(DeployMode::Symlink),
+            "hardlink" => Ok(DeployMode::Hardlink),
+            "copy" => Ok(DeployMode::Copy),
+            "template" => Ok(DeployMode::Template),
+            _ => Err(format!(
+                "unknown deployment mode '{}' (expected symlink, hardlink, copy or template)",
+                s
+            )),
+        }
+    }
+}
+
+/// Settings for one file or directory in a managed folder
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileSettings {
+    /// How the file is deployed once linked, recorded when it is staged
+    pub mode: DeployMode,
+}
+
+/// What to do when a file forge places is blocked by a different file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Keep a copy of the existing file as `<name>.bak`, then replace it
+    Backup,
+    /// Leave the existing file alone and report it as skipped
+    Skip,
+    /// Replace the existing file; the batch history still holds it for undo
+    Overwrite,
+    /// Pull the existing file's content into the managed copy, then replace it
+    Adopt,
+    /// Ask for each conflict which of the other policies to use
+    Prompt,
+}
+
+impl ConflictPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Backup => "backup",
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::Overwrite => "overwrite",
+            ConflictPolicy::Adopt => "adopt",
+            ConflictPolicy::Prompt => "prompt",
+        }
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "backup" => Ok(ConflictPolicy::Backup),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "adopt" => Ok(ConflictPolicy::Adopt),
+            "prompt" => Ok(ConflictPolicy::Prompt),
+            _ => Err(format!(
+                "unknown conflict policy '{}' (expected backup, skip, overwrite, adopt or prompt)",
+                s
+            )),
+        }
+    }
+}
+
+/// The settings in a managed folder's `.forge/config.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FolderConfig {
+    /// How newly staged files are laid out
+    pub layout: Layout,
+    /// Package directory used by the package layout when none is given
+    pub package: Option<String>,
+    /// How `link` writes symlinks into the home directory
+    pub links: LinkStyle,
+    /// How link, profile switch, install and restore resolve conflicts when no
+    /// policy is given; unset means conflicts are reported as failures
+    pub on_conflict: Option<ConflictPolicy>,
+    /// Settings for particular files, keyed by their path in the managed folder; a
+    /// directory's settings apply to the files inside it
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, FileSettings>,
+    /// Values for templates to refer to, in addition to or in place of those
+    /// describing this machine
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+}
+
+impl FolderConfig {
+    pub fn path(folder: &Path) -> PathBuf {
+        folder.join(FOLDER_CONFIG_FILE)
+    }
+
+    /// How the managed copy at `path` in `folder` is deployed
+    pub fn mode_for(&self, folder: &Path, path: &Path) -> DeployMode {
+        let Ok(relative) = path.strip_prefix(folder) else {
+            return DeployMode::default();
+        };
+        relative
+            .ancestors()
+            .find_map(|ancestor| {
+                self.files
+                    .iter()
+                    .find(|(key, _)| Path::new(key) == ancestor)
+            })
+            .map(|(_, settings)| settings.mode)
+            .unwrap_or_default()
+    }
+
+    /// Read the settings for `folder`, using the default for a missing file or key
+    pub fn load(folder: &Path) -> Result<Self> {
+        let path = Self::path(folder);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(ForgeError::io(
+                    format!("failed to read {}", path.display()),
+                    e,
+                ));
+            }
+        };
+        toml::from_str(&text)
+            .map_err(|e| ForgeError::InvalidInput(format!("invalid {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, folder: &Path) -> Result<()> {
+        let path = Self::path(folder);
+        let text = toml::to_string(self)
+            .map_err(|e| ForgeError::InvalidInput(format!("failed to encode settings: {}", e)))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| ForgeError::io(format!("failed to create {}", parent.display()), e))?;
+        }
+        fs::write(&path, text)
+            .map_err(|e| ForgeError::io(format!("failed to write {}", path.display()), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips_and_defaults() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        assert_eq!(
+            FolderConfig::load(temp.path()).unwrap(),
+            FolderConfig::default()
+        );
+
+        let config = FolderConfig {
+            layout: Layout::Package,
+            package: Some("shell".to_string()),
+            links: LinkStyle::Relative,
+            on_conflict: Some(ConflictPolicy::Backup),
+            files: BTreeMap::from([(
+                ".ssh/config".to_string(),
+                FileSettings {
+                    mode: DeployMode::Copy,
+                },
+            )]),
+            variables: BTreeMap::from([("email".to_string(), "ada@example.com".to_string())]),
+        };
+        config.save(temp.path()).unwrap();
+        assert_eq!(FolderConfig::load(temp.path()).unwrap(), config);
+
+        fs::write(FolderConfig::path(temp.path()), "on_conflict = \"adopt\"\n").unwrap();
+        assert_eq!(
+            FolderConfig::load(temp.path()).unwrap().on_conflict,
+            Some(ConflictPolicy::Adopt)
+        );
+
+        fs::write(
+            FolderConfig::path(temp.path()),
+            "[files.\".config/Code/User\"]\nmode = \"copy\"\n",
+        )
+        .unwrap();
+        let config = FolderConfig::load(temp.path()).unwrap();
+        let mode = |path: &str| config.mode_for(temp.path(), &temp.path().join(path));
+        assert_eq!(mode(".config/Code/User/settings.json"), DeployMode::Copy);
+        assert_eq!(mode(".config/Code/keybindings.json"), DeployMode::Symlink);
+
+        fs::write(FolderConfig::path(temp.path()), "layout = \"sideways\"\n").unwrap();
+        assert!(FolderConfig::load(temp.path()).is_err());
+    }
+}
@@ -3,6 +3,10 @@ use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 
+pub mod folder;
+pub mod paths;
+pub mod schema;
+
 // Constants for configuration file paths
 const DEFAULT_CONFIG_DIR: &str = ".forge";
 const DEFAULT_PATH_FILE: &str = "default_path";
@@ -11,6 +15,32 @@ const IGNORED_PATHS_FILE: &str = "ignored_paths";
 const MANAGED_FOLDERS_FILE: &str = "managed_folders";
 const DEFAULT_MANAGED_FOLDER: &str = "default";
 
+/// A row of the operation history, with its entries still encoded as JSON
+#[derive(Debug, Clone)]
+pub struct HistoryRecord {
+    pub id: String,
+    pub action: String,
+    pub entries: String,
+    pub undoes: Option<String>,
+    pub undone_by: Option<String>,
+    pub pruned_at: Option<String>,
+    pub created_at: String,
+}
+
+impl HistoryRecord {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            action: row.get(1)?,
+            entries: row.get(2)?,
+            undoes: row.get(3)?,
+            undone_by: row.get(4)?,
+            pruned_at: row.get(5)?,
+            created_at: row.get(6)?,
+        })
+    }
+}
+
 pub struct Config {
     db_path: PathBuf,
     connection: Option<Connection>,
@@ -20,6 +50,16 @@ pub struct Config {
     filetypes_file: PathBuf,
     ignored_paths_file: PathBuf,
     managed_folders_file: PathBuf,
+    // Home directory that tracked paths are laid out relative to
+    home_dir: PathBuf,
+    // Data directory overriding $XDG_DATA_HOME, where the trash lives
+    data_home: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Config {
@@ -28,11 +68,21 @@ impl Config {
             .unwrap_or_else(|| PathBuf::from("."))
             .join(DEFAULT_CONFIG_DIR);
         
+        // Initialize db_path 
+        let mut db_path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+        db_path.push("forge");
+        db_path.push("forge.db");
+        
+        Self::with_dirs(config_dir, db_path)
+    }
+    
+    /// Create a configuration rooted at explicit locations instead of the user's home
+    pub fn with_dirs(config_dir: PathBuf, db_path: PathBuf) -> Self {
         // Create config directory if it doesn't exist
-        if !config_dir.exists() {
-            if let Err(e) = fs::create_dir_all(&config_dir) {
-                eprintln!("Failed to create config directory: {}", e);
-            }
+        if !config_dir.exists()
+            && let Err(e) = fs::create_dir_all(&config_dir)
+        {
+            eprintln!("Failed to create config directory: {}", e);
         }
         
         // Generate file paths
@@ -41,18 +91,12 @@ impl Config {
         let ignored_paths_file = config_dir.join(IGNORED_PATHS_FILE);
         let managed_folders_file = config_dir.join(MANAGED_FOLDERS_FILE);
         
-        // Initialize db_path 
-        let mut db_path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
-        db_path.push("forge");
-        db_path.push("forge.db");
-        
         // Ensure config directory for db exists
-        if let Some(parent) = db_path.parent() {
-            if !parent.exists() {
-                if let Err(e) = fs::create_dir_all(parent) {
-                    eprintln!("Failed to create database directory: {}", e);
-                }
-            }
+        if let Some(parent) = db_path.parent()
+            && !parent.exists()
+            && let Err(e) = fs::create_dir_all(parent)
+        {
+            eprintln!("Failed to create database directory: {}", e);
         }
         
         Self {
@@ -63,45 +107,82 @@ impl Config {
             filetypes_file,
             ignored_paths_file,
             managed_folders_file,
+            home_dir: dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")),
+            data_home: None,
         }
     }
+    
+    /// Use `home` instead of the user's home directory when laying out tracked paths
+    pub fn with_home_dir(mut self, home: PathBuf) -> Self {
+        self.home_dir = home;
+        self
+    }
+    
+    /// Use `data_home` instead of `$XDG_DATA_HOME` for user data such as the trash
+    pub fn with_data_home(mut self, data_home: PathBuf) -> Self {
+        self.data_home = Some(data_home);
+        self
+    }
 
+    /// Open the database, creating it if it is new, and refuse an out-of-date schema
+    ///
+    /// An existing database is only migrated by `forge db migrate`.
     pub fn connect(&mut self) -> rusqlite::Result<()> {
         // Connect to the database
-        self.connection = Some(Connection::open(&self.db_path)?);
+        let conn = Connection::open(&self.db_path)?;
+        let is_new = !has_tables(&conn)?;
+        if !is_new {
+            schema::ensure_current(&conn)?;
+        }
+        self.connection = Some(conn);
         
-        // Initialize database if needed
+        if is_new {
+            self.migrate()?;
+        }
         self.init_database()?;
         
         Ok(())
     }
     
-    // Initialize database tables if they don't exist
+    /// Open the database without migrating it, for inspecting its schema version
+    pub fn connect_unmigrated(&mut self) -> rusqlite::Result<()> {
+        self.connection = Some(Connection::open(&self.db_path)?);
+        Ok(())
+    }
+    
+    // Compare the database schema with the version this build writes
+    pub fn schema_status(&self) -> rusqlite::Result<schema::SchemaStatus> {
+        match &self.connection {
+            Some(conn) => schema::status(conn),
+            None => Err(rusqlite::Error::InvalidQuery),
+        }
+    }
+    
+    /// Apply pending schema migrations, returning those applied
+    ///
+    /// An existing database is copied to `<db>.v<N>.bak` before it is changed.
+    pub fn migrate(&mut self) -> rusqlite::Result<Vec<schema::Migration>> {
+        let tokens = self.path_tokens();
+        let Some(conn) = &mut self.connection else {
+            return Err(rusqlite::Error::InvalidQuery);
+        };
+        
+        let status = schema::status(conn)?;
+        
+        // Keep a copy of the old schema in case a migration misbehaves
+        let backup = self.db_path.with_extension(format!("db.v{}.bak", status.current));
+        if !status.pending.is_empty() && !status.is_newer() && has_tables(conn)? && !backup.exists() {
+            // SQLite takes the file name as text, and a lossy one would name another file
+            let name = backup.to_str().ok_or_else(|| rusqlite::Error::InvalidPath(backup.clone()))?;
+            conn.execute("VACUUM INTO ?", [name])?;
+        }
+        
+        schema::migrate(conn, &tokens)
+    }
+    
+    // Seed default settings and imported lists into a migrated database
     fn init_database(&self) -> rusqlite::Result<()> {
         if let Some(conn) = &self.connection {
-            // Create dotfiles table
-            conn.execute(
-                "CREATE TABLE IF NOT EXISTS dotfiles (
-                    id INTEGER PRIMARY KEY,
-                    source TEXT NOT NULL,
-                    target TEXT NOT NULL,
-                    profile TEXT,
-                    status TEXT NOT NULL DEFAULT 'staged',
-                    active BOOLEAN NOT NULL DEFAULT 1,
-                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-                )",
-                [],
-            )?;
-            
-            // Create settings table
-            conn.execute(
-                "CREATE TABLE IF NOT EXISTS settings (
-                    key TEXT PRIMARY KEY,
-                    value TEXT NOT NULL
-                )",
-                [],
-            )?;
-            
             // Insert default settings if they don't exist
             let default_path = self.read_default_path();
             let count: i64 = conn.query_row(
@@ -117,26 +198,6 @@ impl Config {
                 )?;
             }
             
-            // Create filetypes table
-            conn.execute(
-                "CREATE TABLE IF NOT EXISTS filetypes (
-                    extension TEXT PRIMARY KEY,
-                    active BOOLEAN NOT NULL DEFAULT 1,
-                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-                )",
-                [],
-            )?;
-            
-            // Create ignored_paths table
-            conn.execute(
-                "CREATE TABLE IF NOT EXISTS ignored_paths (
-                    path TEXT PRIMARY KEY,
-                    active BOOLEAN NOT NULL DEFAULT 1,
-                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-                )",
-                [],
-            )?;
-            
             // Initialize with default filetypes if table is empty
             let count: i64 = conn.query_row(
                 "SELECT COUNT(*) FROM filetypes",
@@ -188,6 +249,37 @@ impl Config {
         Ok(())
     }
     
+    // Get config directory
+    pub fn get_config_dir(&self) -> &PathBuf {
+        &self.config_dir
+    }
+    
+    // Get the home directory tracked paths are laid out relative to
+    pub fn get_home_dir(&self) -> &PathBuf {
+        &self.home_dir
+    }
+    
+    // Get the user data directory: $XDG_DATA_HOME, or ~/.local/share when it is unset
+    pub fn get_data_home(&self) -> PathBuf {
+        if let Some(data_home) = &self.data_home {
+            return data_home.clone();
+        }
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .unwrap_or_else(|| self.home_dir.join(".local").join("share"))
+    }
+    
+    // Get database path
+    pub fn get_db_path(&self) -> &PathBuf {
+        &self.db_path
+    }
+    
+    // Check whether the database connection has been opened
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+    
     // Get default path file
     pub fn get_default_path_file(&self) -> &PathBuf {
         &self.default_path_file
@@ -326,6 +418,20 @@ impl Config {
             .map(|(_, p)| p.clone()))
     }
     
+    // Name of the managed folder containing `path`, or an empty string if none does
+    pub fn folder_for(&self, path: &Path) -> String {
+        self.path_tokens().folder_for(path)
+    }
+    
+    // Roots that tracked paths are stored relative to in the database
+    pub fn path_tokens(&self) -> paths::PathTokens {
+        paths::PathTokens::new(
+            self.home_dir.clone(),
+            std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+            self.get_managed_folders().unwrap_or_default(),
+        )
+    }
+    
     // Get the current active managed folder (for now, just get the default or first)
     pub fn get_active_managed_folder(&self) -> io::Result<Option<(String, PathBuf)>> {
         let managed_folders = self.get_managed_folders()?;
@@ -353,14 +459,13 @@ impl Config {
         let file_path = file_path.as_ref();
         
         // Create parent directories if they don't exist
-        if let Some(parent) = file_path.parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent)?;
-            }
+        if let Some(parent) = file_path.parent()
+            && !parent.exists()
+        {
+            fs::create_dir_all(parent)?;
         }
         
         let mut file = OpenOptions::new()
-            .write(true)
             .append(true)
             .create(true)
             .open(file_path)?;
@@ -434,17 +539,165 @@ impl Config {
         Ok(())
     }
     
+    // ---- Transactions ----
+    
+    // Run a statement on the open connection
+    fn execute_batch(&self, sql: &str) -> rusqlite::Result<()> {
+        match &self.connection {
+            Some(conn) => conn.execute_batch(sql),
+            None => Err(rusqlite::Error::InvalidQuery),
+        }
+    }
+    
+    // Start a transaction covering every following database change
+    pub fn begin_transaction(&self) -> rusqlite::Result<()> {
+        self.execute_batch("BEGIN IMMEDIATE")
+    }
+    
+    pub fn commit_transaction(&self) -> rusqlite::Result<()> {
+        self.execute_batch("COMMIT")
+    }
+    
+    pub fn rollback_transaction(&self) -> rusqlite::Result<()> {
+        self.execute_batch("ROLLBACK")
+    }
+    
+    // ---- Settings ----
+    
+    // Read a value from the settings table
+    pub fn get_setting(&self, key: &str) -> rusqlite::Result<Option<String>> {
+        if let Some(conn) = &self.connection {
+            let result = conn.query_row(
+                "SELECT value FROM settings WHERE key = ?",
+                [key],
+                |row| row.get(0),
+            );
+            
+            match result {
+                Ok(value) => Ok(Some(value)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+    
+    // Store a value in the settings table, or clear it when `value` is None
+    pub fn set_setting(&self, key: &str, value: Option<&str>) -> rusqlite::Result<()> {
+        if let Some(conn) = &self.connection {
+            match value {
+                Some(value) => conn.execute(
+                    "INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?)",
+                    [key, value],
+                )?,
+                None => conn.execute("DELETE FROM settings WHERE key = ?", [key])?,
+            };
+            Ok(())
+        } else {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        }
+    }
+    
+    // ---- Operation history ----
+    
+    // Record a journaled batch in the history, inside the batch's transaction
+    pub fn record_history(&self, id: &str, action: &str, entries: &str, undoes: Option<&str>) -> rusqlite::Result<()> {
+        if let Some(conn) = &self.connection {
+            conn.execute(
+                "INSERT INTO history (id, action, entries, undoes) VALUES (?, ?, ?, ?)",
+                rusqlite::params![id, action, entries, undoes],
+            )?;
+            if let Some(undone) = undoes {
+                conn.execute(
+                    "UPDATE history SET undone_by = ? WHERE id = ?",
+                    [id, undone],
+                )?;
+            }
+            Ok(())
+        } else {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        }
+    }
+    
+    // Check whether a journaled batch reached its commit
+    pub fn is_batch_committed(&self, id: &str) -> rusqlite::Result<bool> {
+        if let Some(conn) = &self.connection {
+            conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM history WHERE id = ?)",
+                [id],
+                |row| row.get(0),
+            )
+        } else {
+            Ok(false)
+        }
+    }
+    
+    // Get recorded operations, newest first
+    pub fn get_history(&self, limit: Option<usize>) -> rusqlite::Result<Vec<HistoryRecord>> {
+        let mut records = Vec::new();
+        
+        if let Some(conn) = &self.connection {
+            let mut stmt = conn.prepare(
+                "SELECT id, action, entries, undoes, undone_by, pruned_at, created_at FROM history
+                 ORDER BY created_at DESC, id DESC LIMIT ?",
+            )?;
+            let limit = limit.map_or(-1, |n| n as i64);
+            let rows = stmt.query_map([limit], HistoryRecord::from_row)?;
+            for row in rows {
+                records.push(row?);
+            }
+        }
+        
+        Ok(records)
+    }
+    
+    // Note that the files an operation needs to be undone were pruned
+    pub fn mark_history_pruned(&self, id: &str) -> rusqlite::Result<()> {
+        if let Some(conn) = &self.connection {
+            conn.execute(
+                "UPDATE history SET pruned_at = CURRENT_TIMESTAMP WHERE id = ? AND pruned_at IS NULL",
+                [id],
+            )?;
+            Ok(())
+        } else {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        }
+    }
+    
+    // Find a recorded operation by id
+    pub fn find_history(&self, id: &str) -> rusqlite::Result<Option<HistoryRecord>> {
+        if let Some(conn) = &self.connection {
+            let result = conn.query_row(
+                "SELECT id, action, entries, undoes, undone_by, pruned_at, created_at FROM history WHERE id = ?",
+                [id],
+                HistoryRecord::from_row,
+            );
+            
+            match result {
+                Ok(record) => Ok(Some(record)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+    
     // ---- Database operations for dotfiles ----
     
-    // Stage a dotfile in the database
+    // Stage a dotfile in the database, replacing any record for the same target
     pub fn stage_dotfile(&self, source: &Path, target: &Path, profile: Option<&str>) -> rusqlite::Result<()> {
         if let Some(conn) = &self.connection {
-            let source_str = source.to_string_lossy().to_string();
-            let target_str = target.to_string_lossy().to_string();
+            let tokens = self.path_tokens();
+            let source_str = tokens.encode(source);
+            let target_str = tokens.encode(target);
             
             conn.execute(
-                "INSERT INTO dotfiles (source, target, profile, status) VALUES (?, ?, ?, 'staged')",
-                rusqlite::params![source_str, target_str, profile],
+                "INSERT INTO dotfiles (source, target, profile, status, folder) VALUES (?, ?, ?, 'staged', ?)
+                 ON CONFLICT (folder, target) DO UPDATE SET
+                    source = excluded.source, profile = excluded.profile, status = 'staged', active = 1, hash = NULL, mode = 'symlink'",
+                rusqlite::params![source_str, target_str, profile, self.folder_for(target)],
             )?;
             
             Ok(())
@@ -456,8 +709,9 @@ impl Config {
     // Update a dotfile status to linked
     pub fn link_dotfile(&self, source: &Path, target: &Path) -> rusqlite::Result<()> {
         if let Some(conn) = &self.connection {
-            let source_str = source.to_string_lossy().to_string();
-            let target_str = target.to_string_lossy().to_string();
+            let tokens = self.path_tokens();
+            let source_str = tokens.encode(source);
+            let target_str = tokens.encode(target);
             
             conn.execute(
                 "UPDATE dotfiles SET status = 'linked' WHERE source = ? AND target = ? AND active = 1",
@@ -470,15 +724,18 @@ impl Config {
         }
     }
     
-    // Add a dotfile directly with linked status (for legacy compatibility)
+    // Add a dotfile directly with linked status, replacing any record for the same target
     pub fn add_dotfile(&self, source: &Path, target: &Path, profile: Option<&str>) -> rusqlite::Result<()> {
         if let Some(conn) = &self.connection {
-            let source_str = source.to_string_lossy().to_string();
-            let target_str = target.to_string_lossy().to_string();
+            let tokens = self.path_tokens();
+            let source_str = tokens.encode(source);
+            let target_str = tokens.encode(target);
             
             conn.execute(
-                "INSERT INTO dotfiles (source, target, profile, status) VALUES (?, ?, ?, 'linked')",
-                rusqlite::params![source_str, target_str, profile],
+                "INSERT INTO dotfiles (source, target, profile, status, folder) VALUES (?, ?, ?, 'linked', ?)
+                 ON CONFLICT (folder, target) DO UPDATE SET
+                    source = excluded.source, profile = excluded.profile, status = 'linked', active = 1, hash = NULL, mode = 'symlink'",
+                rusqlite::params![source_str, target_str, profile, self.folder_for(target)],
             )?;
             
             Ok(())
@@ -492,12 +749,13 @@ impl Config {
         let mut dotfiles = Vec::new();
         
         if let Some(conn) = &self.connection {
+            let tokens = self.path_tokens();
             let query = match profile {
                 Some(_) => {
-                    "SELECT source, target, profile, status FROM dotfiles WHERE active = 1 AND profile = ?1"
+                    "SELECT source, target, profile, status, hash, mode FROM dotfiles WHERE active = 1 AND profile = ?1"
                 },
                 None => {
-                    "SELECT source, target, profile, status FROM dotfiles WHERE active = 1"
+                    "SELECT source, target, profile, status, hash, mode FROM dotfiles WHERE active = 1"
                 },
             };
             
@@ -505,32 +763,15 @@ impl Config {
             
             // This closure is used to extract the dotfile data from a row
             let map_row = |row: &rusqlite::Row| -> rusqlite::Result<crate::dotfile::DotFile> {
-                let source: String = row.get(0)?;
-                let target: String = row.get(1)?;
-                let profile: Option<String> = row.get(2)?;
-                let status_str: String = row.get(3)?;
-                
-                let status = match status_str.as_str() {
-                    "staged" => crate::dotfile::DotFileStatus::Staged,
-                    "linked" => crate::dotfile::DotFileStatus::Linked,
-                    "unlinked" => crate::dotfile::DotFileStatus::Unlinked,
-                    _ => crate::dotfile::DotFileStatus::Staged,
-                };
-                
-                Ok(crate::dotfile::DotFile::with_status(
-                    PathBuf::from(source),
-                    PathBuf::from(target),
-                    profile,
-                    status,
-                ))
+                crate::dotfile::DotFile::from_row(row, &tokens)
             };
             
-            let mut rows = match profile {
+            let rows = match profile {
                 Some(p) => stmt.query_map([p], map_row)?,
                 None => stmt.query_map([], map_row)?,
             };
             
-            while let Some(dotfile_result) = rows.next() {
+            for dotfile_result in rows {
                 dotfiles.push(dotfile_result?);
             }
         }
@@ -543,12 +784,13 @@ impl Config {
         let mut dotfiles = Vec::new();
         
         if let Some(conn) = &self.connection {
+            let tokens = self.path_tokens();
             let query = match profile {
                 Some(_) => {
-                    "SELECT source, target, profile, status FROM dotfiles WHERE status = 'staged' AND active = 1 AND profile = ?1"
+                    "SELECT source, target, profile, status, hash, mode FROM dotfiles WHERE status = 'staged' AND active = 1 AND profile = ?1"
                 },
                 None => {
-                    "SELECT source, target, profile, status FROM dotfiles WHERE status = 'staged' AND active = 1"
+                    "SELECT source, target, profile, status, hash, mode FROM dotfiles WHERE status = 'staged' AND active = 1"
                 },
             };
             
@@ -556,32 +798,15 @@ impl Config {
             
             // This closure is used to extract the dotfile data from a row
             let map_row = |row: &rusqlite::Row| -> rusqlite::Result<crate::dotfile::DotFile> {
-                let source: String = row.get(0)?;
-                let target: String = row.get(1)?;
-                let profile: Option<String> = row.get(2)?;
-                let status_str: String = row.get(3)?;
-                
-                let status = match status_str.as_str() {
-                    "staged" => crate::dotfile::DotFileStatus::Staged,
-                    "linked" => crate::dotfile::DotFileStatus::Linked,
-                    "unlinked" => crate::dotfile::DotFileStatus::Unlinked,
-                    _ => crate::dotfile::DotFileStatus::Staged,
-                };
-                
-                Ok(crate::dotfile::DotFile::with_status(
-                    PathBuf::from(source),
-                    PathBuf::from(target),
-                    profile,
-                    status,
-                ))
+                crate::dotfile::DotFile::from_row(row, &tokens)
             };
             
-            let mut rows = match profile {
+            let rows = match profile {
                 Some(p) => stmt.query_map([p], map_row)?,
                 None => stmt.query_map([], map_row)?,
             };
             
-            while let Some(dotfile_result) = rows.next() {
+            for dotfile_result in rows {
                 dotfiles.push(dotfile_result?);
             }
         }
@@ -592,7 +817,8 @@ impl Config {
     // Deactivate (mark as inactive) a dotfile by target path
     pub fn deactivate_dotfile(&self, target: &Path) -> rusqlite::Result<bool> {
         if let Some(conn) = &self.connection {
-            let target_str = target.to_string_lossy().to_string();
+            let tokens = self.path_tokens();
+            let target_str = tokens.encode(target);
             
             let affected = conn.execute(
                 "UPDATE dotfiles SET active = 0 WHERE target = ?",
@@ -605,10 +831,57 @@ impl Config {
         }
     }
     
+    // Insert a record exactly as given, used when replaying or reverting journaled changes
+    pub fn insert_dotfile_record(&self, dotfile: &crate::dotfile::DotFile, active: bool) -> rusqlite::Result<()> {
+        if let Some(conn) = &self.connection {
+            let tokens = self.path_tokens();
+            conn.execute(
+                "INSERT INTO dotfiles (source, target, profile, status, active, folder, hash, mode) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                rusqlite::params![
+                    tokens.encode(&dotfile.source),
+                    tokens.encode(&dotfile.target),
+                    dotfile.profile,
+                    dotfile.status.as_str(),
+                    active,
+                    self.folder_for(&dotfile.target),
+                    dotfile.hash,
+                    dotfile.mode.as_str(),
+                ],
+            )?;
+            
+            Ok(())
+        } else {
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        }
+    }
+    
+    // Delete the newest record matching a dotfile exactly
+    pub fn delete_dotfile_record(&self, dotfile: &crate::dotfile::DotFile, active: bool) -> rusqlite::Result<bool> {
+        if let Some(conn) = &self.connection {
+            let tokens = self.path_tokens();
+            let affected = conn.execute(
+                "DELETE FROM dotfiles WHERE id = (
+                    SELECT id FROM dotfiles WHERE source = ? AND target = ? AND active = ?
+                    ORDER BY id DESC LIMIT 1
+                )",
+                rusqlite::params![
+                    tokens.encode(&dotfile.source),
+                    tokens.encode(&dotfile.target),
+                    active,
+                ],
+            )?;
+            
+            Ok(affected > 0)
+        } else {
+            Ok(false)
+        }
+    }
+    
     // Completely remove a dotfile from the database
     pub fn remove_dotfile(&self, target: &Path) -> rusqlite::Result<bool> {
         if let Some(conn) = &self.connection {
-            let target_str = target.to_string_lossy().to_string();
+            let tokens = self.path_tokens();
+            let target_str = tokens.encode(target);
             
             let affected = conn.execute(
                 "DELETE FROM dotfiles WHERE target = ?",
@@ -624,30 +897,14 @@ impl Config {
     // Find a dotfile by target path
     pub fn find_dotfile_by_target(&self, target: &Path) -> rusqlite::Result<Option<crate::dotfile::DotFile>> {
         if let Some(conn) = &self.connection {
-            let target_str = target.to_string_lossy().to_string();
+            let tokens = self.path_tokens();
+            let target_str = tokens.encode(target);
             
             let result = conn.query_row(
-                "SELECT source, target, profile, status FROM dotfiles WHERE target = ? AND active = 1",
+                "SELECT source, target, profile, status, hash, mode FROM dotfiles WHERE target = ? AND active = 1",
                 [target_str],
                 |row| {
-                    let source: String = row.get(0)?;
-                    let target: String = row.get(1)?;
-                    let profile: Option<String> = row.get(2)?;
-                    let status_str: String = row.get(3)?;
-                    
-                    let status = match status_str.as_str() {
-                        "staged" => crate::dotfile::DotFileStatus::Staged,
-                        "linked" => crate::dotfile::DotFileStatus::Linked,
-                        "unlinked" => crate::dotfile::DotFileStatus::Unlinked,
-                        _ => crate::dotfile::DotFileStatus::Staged,
-                    };
-                    
-                    Ok(crate::dotfile::DotFile::with_status(
-                        PathBuf::from(source),
-                        PathBuf::from(target),
-                        profile,
-                        status,
-                    ))
+                    crate::dotfile::DotFile::from_row(row, &tokens)
                 },
             );
             
@@ -661,33 +918,39 @@ impl Config {
         }
     }
     
+    // Find the record for a target whether or not it is active, with its active flag
+    pub fn find_record_by_target(&self, target: &Path) -> rusqlite::Result<Option<(crate::dotfile::DotFile, bool)>> {
+        if let Some(conn) = &self.connection {
+            let tokens = self.path_tokens();
+            let result = conn.query_row(
+                "SELECT source, target, profile, status, hash, mode, active FROM dotfiles WHERE folder = ? AND target = ?",
+                rusqlite::params![self.folder_for(target), tokens.encode(target)],
+                |row| {
+                    Ok((crate::dotfile::DotFile::from_row(row, &tokens)?, row.get(6)?))
+                },
+            );
+            
+            match result {
+                Ok(record) => Ok(Some(record)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+    
     // Find a dotfile by source path
     pub fn find_dotfile_by_source(&self, source: &Path) -> rusqlite::Result<Option<crate::dotfile::DotFile>> {
         if let Some(conn) = &self.connection {
-            let source_str = source.to_string_lossy().to_string();
+            let tokens = self.path_tokens();
+            let source_str = tokens.encode(source);
             
             let result = conn.query_row(
-                "SELECT source, target, profile, status FROM dotfiles WHERE source = ? AND active = 1",
+                "SELECT source, target, profile, status, hash, mode FROM dotfiles WHERE source = ? AND active = 1",
                 [source_str],
                 |row| {
-                    let source: String = row.get(0)?;
-                    let target: String = row.get(1)?;
-                    let profile: Option<String> = row.get(2)?;
-                    let status_str: String = row.get(3)?;
-                    
-                    let status = match status_str.as_str() {
-                        "staged" => crate::dotfile::DotFileStatus::Staged,
-                        "linked" => crate::dotfile::DotFileStatus::Linked,
-                        "unlinked" => crate::dotfile::DotFileStatus::Unlinked,
-                        _ => crate::dotfile::DotFileStatus::Staged,
-                    };
-                    
-                    Ok(crate::dotfile::DotFile::with_status(
-                        PathBuf::from(source),
-                        PathBuf::from(target),
-                        profile,
-                        status,
-                    ))
+                    crate::dotfile::DotFile::from_row(row, &tokens)
                 },
             );
             
@@ -702,6 +965,15 @@ impl Config {
     }
 }
 
+// Whether the database holds any tables, which a newly created one does not
+fn has_tables(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table')",
+        [],
+        |row| row.get(0),
+    )
+}
+
 // Static helper functions to use when a Config instance is not available
 
 // Get default configuration instance
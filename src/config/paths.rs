@@ -0,0 +1,219 @@
+// Portable storage of tracked paths
+use crate::utils::path_utils;
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+
+const HOME_TOKEN: &str = "$HOME";
+const XDG_CONFIG_TOKEN: &str = "$XDG_CONFIG_HOME";
+const FOLDER_SIGIL: char = '@';
+
+/// The roots tracked paths are stored relative to
+///
+/// These are `@name/...` for a path inside the managed folder registered as `name`,
+/// then `$XDG_CONFIG_HOME/...` and `$HOME/...`; paths under none of them stay
+/// absolute. Stored paths are resolved against the current machine when rows are read,
+/// so the database survives a new username, a different `$HOME`, or a managed folder
+/// synced elsewhere.
+#[derive(Debug, Clone, Default)]
+pub struct PathTokens {
+    home: PathBuf,
+    xdg_config: PathBuf,
+    folders: Vec<(String, PathBuf)>,
+}
+
+impl PathTokens {
+    pub fn new(
+        home: PathBuf,
+        xdg_config: Option<PathBuf>,
+        folders: Vec<(String, PathBuf)>,
+    ) -> Self {
+        let xdg_config = xdg_config
+            .filter(|path| path.is_absolute())
+            .unwrap_or_else(|| home.join(".config"));
+        Self {
+            home,
+            xdg_config,
+            folders,
+        }
+    }
+
+    /// The roots, each with the token it is stored as
+    fn roots(&self) -> impl Iterator<Item = (String, &Path)> {
+        self.folders
+            .iter()
+            .map(|(name, path)| (format!("{}{}", FOLDER_SIGIL, name), path.as_path()))
+            .chain([
+                (XDG_CONFIG_TOKEN.to_string(), self.xdg_config.as_path()),
+                (HOME_TOKEN.to_string(), self.home.as_path()),
+            ])
+            .filter(|(_, root)| root.is_absolute())
+    }
+
+    /// The stored form of `path`, relative to the innermost root containing it
+    ///
+    /// The form is raw bytes, so names that are not valid UTF-8 survive unchanged.
+    pub fn encode(&self, path: &Path) -> Vec<u8> {
+        let innermost = self
+            .roots()
+            .filter_map(|(token, root)| Some((token, root, path.strip_prefix(root).ok()?)))
+            .max_by_key(|(_, root, _)| root.components().count());
+
+        match innermost {
+            Some((token, _, relative)) if relative.as_os_str().is_empty() => token.into_bytes(),
+            Some((token, _, relative)) => {
+                let mut stored = token.into_bytes();
+                stored.push(b'/');
+                stored.extend(path_utils::to_bytes(relative));
+                stored
+            }
+            None => path_utils::to_bytes(path),
+        }
+    }
+
+    /// Resolve a stored path on this machine
+    ///
+    /// A path in a managed folder that is no longer registered is returned unresolved.
+    pub fn decode(&self, stored: &[u8]) -> PathBuf {
+        let (token, relative) = match stored.iter().position(|&byte| byte == b'/') {
+            Some(slash) => (&stored[..slash], &stored[slash + 1..]),
+            None => (stored, &[][..]),
+        };
+        let root = match std::str::from_utf8(token) {
+            Ok(HOME_TOKEN) => Some(self.home.as_path()),
+            Ok(XDG_CONFIG_TOKEN) => Some(self.xdg_config.as_path()),
+            Ok(token) => token.strip_prefix(FOLDER_SIGIL).and_then(|name| {
+                self.folders
+                    .iter()
+                    .find(|(folder, _)| folder == name)
+                    .map(|(_, path)| path.as_path())
+            }),
+            Err(_) => None,
+        };
+
+        match root {
+            Some(root) if relative.is_empty() => root.to_path_buf(),
+            Some(root) => root.join(path_utils::from_bytes(relative.to_vec())),
+            None => path_utils::from_bytes(stored.to_vec()),
+        }
+    }
+
+    /// Name of the managed folder containing `path`, or an empty string if none does
+    pub fn folder_for(&self, path: &Path) -> String {
+        self.folders
+            .iter()
+            .filter(|(_, folder)| path.starts_with(folder))
+            .max_by_key(|(_, folder)| folder.components().count())
+            .map(|(name, _)| name.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Read a stored path column
+///
+/// Columns hold BLOBs, but databases from before they did hold text, which is read as
+/// its UTF-8 bytes.
+pub fn column_bytes(row: &rusqlite::Row, index: usize) -> rusqlite::Result<Vec<u8>> {
+    let value = row.get_ref(index)?;
+    value.as_bytes().map(<[u8]>::to_vec).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(index, value.data_type(), Box::new(e))
+    })
+}
+
+/// Re-encode stored dotfile paths against `tokens`
+///
+/// Rows written as absolute paths, or before the managed folder holding them was
+/// registered, are rewritten into their current stored form and attributed to that
+/// folder. A row that would collide with an existing record for the same target is left
+/// as is.
+pub fn relocate_dotfiles(conn: &Connection, tokens: &PathTokens) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("SELECT id, source, target, folder FROM dotfiles")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                column_bytes(row, 1)?,
+                column_bytes(row, 2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for (id, source, target, folder) in rows {
+        let target_path = tokens.decode(&target);
+        let relocated = (
+            tokens.encode(&tokens.decode(&source)),
+            tokens.encode(&target_path),
+            match tokens.folder_for(&target_path) {
+                name if name.is_empty() => folder.clone(),
+                name => name,
+            },
+        );
+        if relocated != (source, target, folder) {
+            conn.execute(
+                "UPDATE OR IGNORE dotfiles SET source = ?, target = ?, folder = ? WHERE id = ?",
+                rusqlite::params![relocated.0, relocated.1, relocated.2, id],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encodes_against_innermost_root() {
+        let tokens = PathTokens::new(
+            PathBuf::from("/home/ada"),
+            None,
+            vec![("dots".to_string(), PathBuf::from("/home/ada/dotfiles"))],
+        );
+        let cases = [
+            ("/home/ada/.bashrc", "$HOME/.bashrc"),
+            (
+                "/home/ada/.config/git/config",
+                "$XDG_CONFIG_HOME/git/config",
+            ),
+            ("/home/ada/dotfiles/.bashrc", "@dots/.bashrc"),
+            ("/home/ada", "$HOME"),
+            ("/etc/hosts", "/etc/hosts"),
+        ];
+        for (path, stored) in cases {
+            assert_eq!(tokens.encode(Path::new(path)), stored.as_bytes());
+            assert_eq!(tokens.decode(stored.as_bytes()), PathBuf::from(path));
+        }
+
+        // The same rows resolve under another user's home and folder location
+        let elsewhere = PathTokens::new(
+            PathBuf::from("/Users/grace"),
+            Some(PathBuf::from("/Users/grace/cfg")),
+            vec![("dots".to_string(), PathBuf::from("/srv/dots"))],
+        );
+        assert_eq!(
+            elsewhere.decode(b"$XDG_CONFIG_HOME/git/config"),
+            PathBuf::from("/Users/grace/cfg/git/config")
+        );
+        assert_eq!(
+            elsewhere.decode(b"@dots/.bashrc"),
+            PathBuf::from("/srv/dots/.bashrc")
+        );
+        assert_eq!(
+            elsewhere.decode(b"@gone/.bashrc"),
+            PathBuf::from("@gone/.bashrc")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keeps_invalid_utf8_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let tokens = PathTokens::new(PathBuf::from("/home/ada"), None, Vec::new());
+        let path = Path::new("/home/ada").join(OsStr::from_bytes(b"caf\xe9.conf"));
+        assert_eq!(tokens.encode(&path), b"$HOME/caf\xe9.conf");
+        assert_eq!(tokens.decode(&tokens.encode(&path)), path);
+    }
+}
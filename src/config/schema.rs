@@ -0,0 +1,425 @@
+// Versioned database schema and the migrations between versions
+use super::paths::{self, PathTokens};
+use rusqlite::{Connection, ffi};
+use serde::Serialize;
+
+/// One step from the previous schema version to `version`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    #[serde(skip)]
+    pub sql: &'static str,
+    /// Rewrites rows whose new form depends on this machine, run after `sql`
+    #[serde(skip)]
+    pub rewrite: Option<fn(&Connection, &PathTokens) -> rusqlite::Result<()>>,
+}
+
+/// Every migration, in the order it must be applied
+///
+/// Databases created before versioning report version 0 and already contain the
+/// version 1 tables, which is why the first migration only creates tables that are
+/// missing.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create dotfiles, settings, filetypes and ignored_paths tables",
+        sql: "
+        CREATE TABLE IF NOT EXISTS dotfiles (
+            id INTEGER PRIMARY KEY,
+            source TEXT NOT NULL,
+            target TEXT NOT NULL,
+            profile TEXT,
+            status TEXT NOT NULL DEFAULT 'staged',
+            active BOOLEAN NOT NULL DEFAULT 1,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS filetypes (
+            extension TEXT PRIMARY KEY,
+            active BOOLEAN NOT NULL DEFAULT 1,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS ignored_paths (
+            path TEXT PRIMARY KEY,
+            active BOOLEAN NOT NULL DEFAULT 1,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+    ",
+        rewrite: None,
+    },
+    Migration {
+        version: 2,
+        description: "record an undoable history of file operations",
+        sql: "
+        CREATE TABLE history (
+            id TEXT PRIMARY KEY,
+            action TEXT NOT NULL,
+            entries TEXT NOT NULL DEFAULT '[]',
+            undoes TEXT,
+            undone_by TEXT,
+            pruned_at TIMESTAMP,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+    ",
+        rewrite: None,
+    },
+    Migration {
+        version: 3,
+        description: "remove duplicate dotfile records and make (folder, target) unique",
+        sql: "
+        ALTER TABLE dotfiles ADD COLUMN folder TEXT NOT NULL DEFAULT '';
+        -- Keep the newest active record for each target, or the newest if none is active
+        DELETE FROM dotfiles WHERE EXISTS (
+            SELECT 1 FROM dotfiles AS other
+            WHERE other.target = dotfiles.target
+              AND (other.active > dotfiles.active
+                   OR (other.active = dotfiles.active AND other.id > dotfiles.id))
+        );
+        CREATE UNIQUE INDEX dotfiles_folder_target ON dotfiles (folder, target);
+    ",
+        rewrite: None,
+    },
+    Migration {
+        version: 4,
+        description: "store dotfile paths relative to $HOME, $XDG_CONFIG_HOME or their managed folder",
+        sql: "",
+        rewrite: Some(paths::relocate_dotfiles),
+    },
+    Migration {
+        version: 5,
+        description: "store dotfile paths as raw bytes so non-UTF-8 names survive",
+        sql: "
+        CREATE TABLE dotfiles_v5 (
+            id INTEGER PRIMARY KEY,
+            source BLOB NOT NULL,
+            target BLOB NOT NULL,
+            profile TEXT,
+            status TEXT NOT NULL DEFAULT 'staged',
+            active BOOLEAN NOT NULL DEFAULT 1,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            folder TEXT NOT NULL DEFAULT ''
+        );
+        INSERT INTO dotfiles_v5 (id, source, target, profile, status, active, created_at, folder)
+            SELECT id, CAST(source AS BLOB), CAST(target AS BLOB), profile, status, active,
+                   created_at, folder
+            FROM dotfiles;
+        DROP TABLE dotfiles;
+        ALTER TABLE dotfiles_v5 RENAME TO dotfiles;
+        CREATE UNIQUE INDEX dotfiles_folder_target ON dotfiles (folder, target);
+    ",
+        rewrite: None,
+    },
+    Migration {
+        version: 6,
+        description: "record the content hash of dotfiles deployed as copies",
+        sql: "
+        ALTER TABLE dotfiles ADD COLUMN hash TEXT;
+    ",
+        rewrite: None,
+    },
+    Migration {
+        version: 7,
+        description: "record how each dotfile is deployed: symlink, hardlink, copy or template",
+        sql: "
+        ALTER TABLE dotfiles ADD COLUMN mode TEXT NOT NULL DEFAULT 'symlink';
+        -- Only copies had a hash recorded before
+        UPDATE dotfiles SET mode = 'copy' WHERE hash IS NOT NULL;
+    ",
+        rewrite: None,
+    },
+];
+
+/// The schema version this build of forge writes
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// How a database's schema compares with this build of forge
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaStatus {
+    pub current: u32,
+    pub latest: u32,
+    pub pending: Vec<Migration>,
+}
+
+impl SchemaStatus {
+    pub fn is_current(&self) -> bool {
+        self.current == self.latest
+    }
+
+    /// The database was written by a newer forge than this one
+    pub fn is_newer(&self) -> bool {
+        self.current > self.latest
+    }
+}
+
+/// The schema version stored in `PRAGMA user_version`
+pub fn current_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Compare a database's schema version with the migrations this build knows
+pub fn status(conn: &Connection) -> rusqlite::Result<SchemaStatus> {
+    let current = current_version(conn)?;
+    Ok(SchemaStatus {
+        current,
+        latest: latest_version(),
+        pending: MIGRATIONS
+            .iter()
+            .filter(|m| m.version > current)
+            .copied()
+            .collect(),
+    })
+}
+
+/// Refuse a database whose schema is not the version this build writes
+///
+/// Migrating changes the user's database, so it only happens when they ask for it.
+pub fn ensure_current(conn: &Connection) -> rusqlite::Result<()> {
+    let status = status(conn)?;
+    if status.is_newer() {
+        return Err(newer_error(&status));
+    }
+    if !status.is_current() {
+        return Err(schema_error(format!(
+            "database schema version {} is older than this forge writes ({}); run `forge db migrate`",
+            status.current, status.latest
+        )));
+    }
+    Ok(())
+}
+
+fn newer_error(status: &SchemaStatus) -> rusqlite::Error {
+    schema_error(format!(
+        "database schema version {} is newer than this forge supports ({}); upgrade forge",
+        status.current, status.latest
+    ))
+}
+
+fn schema_error(message: String) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_ERROR), Some(message))
+}
+
+/// Apply pending migrations, each in its own transaction, returning those applied
+///
+/// `tokens` describes this machine for migrations that rewrite stored paths. A database
+/// newer than this build is refused rather than modified.
+pub fn migrate(conn: &mut Connection, tokens: &PathTokens) -> rusqlite::Result<Vec<Migration>> {
+    let status = status(conn)?;
+    if status.is_newer() {
+        return Err(newer_error(&status));
+    }
+
+    for migration in &status.pending {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql)?;
+        if let Some(rewrite) = migration.rewrite {
+            rewrite(&tx, tokens)?;
+        }
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(status.pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::folder::DeployMode;
+    use crate::dotfile::DotFile;
+    use std::path::Path;
+
+    #[test]
+    fn test_migrates_fresh_and_legacy_databases() {
+        let mut fresh = Connection::open_in_memory().unwrap();
+        assert_eq!(
+            migrate(&mut fresh, &PathTokens::default()).unwrap().len(),
+            MIGRATIONS.len()
+        );
+        assert!(status(&fresh).unwrap().is_current());
+        assert!(
+            migrate(&mut fresh, &PathTokens::default())
+                .unwrap()
+                .is_empty()
+        );
+
+        // Databases from before versioning already have the tables, with data
+        let mut legacy = Connection::open_in_memory().unwrap();
+        legacy.execute_batch(MIGRATIONS[0].sql).unwrap();
+        legacy
+            .execute(
+                "INSERT INTO dotfiles (source, target) VALUES ('/home/.bashrc', '/forge/.bashrc')",
+                [],
+            )
+            .unwrap();
+        assert_eq!(status(&legacy).unwrap().current, 0);
+        migrate(&mut legacy, &PathTokens::default()).unwrap();
+        let count: i64 = legacy
+            .query_row("SELECT COUNT(*) FROM dotfiles", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(current_version(&legacy).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn test_deduplicates_dotfiles() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        conn.execute_batch(
+            "INSERT INTO dotfiles (source, target, active) VALUES ('/home/.zshrc', '/forge/.zshrc', 1);
+             INSERT INTO dotfiles (source, target, active) VALUES ('/home/.zshrc', '/forge/.zshrc', 0);
+             INSERT INTO dotfiles (source, target, active) VALUES ('/home/.vimrc', '/forge/.vimrc', 0);
+             INSERT INTO dotfiles (source, target, active) VALUES ('/home/.vimrc', '/forge/.vimrc', 0);",
+        )
+        .unwrap();
+        migrate(&mut conn, &PathTokens::default()).unwrap();
+
+        let kept: Vec<(i64, bool)> = conn
+            .prepare("SELECT id, active FROM dotfiles ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(kept, vec![(1, true), (4, false)]);
+
+        let duplicate = conn.execute(
+            "INSERT INTO dotfiles (source, target) VALUES (CAST('/elsewhere/.zshrc' AS BLOB), CAST('/forge/.zshrc' AS BLOB))",
+            [],
+        );
+        assert!(duplicate.is_err());
+    }
+
+    #[test]
+    fn test_relocates_absolute_paths() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        conn.execute_batch(
+            "INSERT INTO dotfiles (source, target) VALUES ('/home/ada/.bashrc', '/home/ada/dots/.bashrc');
+             INSERT INTO dotfiles (source, target) VALUES ('/etc/hosts', '/home/ada/dots/hosts');",
+        )
+        .unwrap();
+        let tokens = PathTokens::new(
+            "/home/ada".into(),
+            None,
+            vec![("dots".to_string(), "/home/ada/dots".into())],
+        );
+        migrate(&mut conn, &tokens).unwrap();
+
+        let rows: Vec<(Vec<u8>, Vec<u8>, String)> = conn
+            .prepare("SELECT source, target, folder FROM dotfiles ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        let expected = [
+            ("$HOME/.bashrc", "@dots/.bashrc", "dots"),
+            ("/etc/hosts", "@dots/hosts", "dots"),
+        ];
+        assert_eq!(
+            rows,
+            expected.map(|(s, t, f)| (s.into(), t.into(), f.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_config_migrates_only_when_asked_and_backs_up_first() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let db_path = temp.path().join("forge.db");
+        Connection::open(&db_path)
+            .unwrap()
+            .execute_batch(MIGRATIONS[0].sql)
+            .unwrap();
+
+        // Opening the database leaves it as it is until it is migrated
+        let mut config = super::super::Config::with_dirs(temp.path().join("config"), db_path);
+        let error = config.connect().unwrap_err();
+        assert!(error.to_string().contains("forge db migrate"), "{}", error);
+        assert!(!temp.path().join("forge.db.v0.bak").exists());
+
+        config.connect_unmigrated().unwrap();
+        assert_eq!(config.migrate().unwrap().len(), MIGRATIONS.len());
+        assert!(temp.path().join("forge.db.v0.bak").exists());
+        config.connect().unwrap();
+        assert!(config.schema_status().unwrap().is_current());
+    }
+
+    #[test]
+    fn test_refuses_to_back_up_to_a_name_that_is_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let dir = temp.path().join(OsStr::from_bytes(b"caf\xe9"));
+        std::fs::create_dir(&dir).unwrap();
+        let db_path = dir.join("forge.db");
+        Connection::open(&db_path)
+            .unwrap()
+            .execute_batch(MIGRATIONS[0].sql)
+            .unwrap();
+
+        let mut config = super::super::Config::with_dirs(temp.path().join("config"), db_path);
+        config.connect_unmigrated().unwrap();
+        assert!(matches!(
+            config.migrate(),
+            Err(rusqlite::Error::InvalidPath(_))
+        ));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        assert_eq!(config.schema_status().unwrap().current, 0);
+    }
+
+    #[test]
+    fn test_reads_modes_and_refuses_unknown_ones() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let tokens = PathTokens::default();
+        migrate(&mut conn, &tokens).unwrap();
+        let insert = |target: &str, mode: &str| {
+            conn.execute(
+                "INSERT INTO dotfiles (source, target, status, folder, mode) \
+                 VALUES (?, ?, 'linked', 'default', ?)",
+                rusqlite::params![
+                    tokens.encode(Path::new("/home/ada/.gitconfig")),
+                    tokens.encode(Path::new(target)),
+                    mode
+                ],
+            )
+            .unwrap();
+        };
+        insert("/forge/.gitconfig", "copy");
+        insert("/forge/.ssh/config", "teleport");
+
+        let read = |target: &str| {
+            conn.query_row(
+                "SELECT source, target, profile, status, hash, mode FROM dotfiles WHERE target = ?",
+                [tokens.encode(Path::new(target))],
+                |row| DotFile::from_row(row, &tokens),
+            )
+        };
+        assert_eq!(read("/forge/.gitconfig").unwrap().mode, DeployMode::Copy);
+        let err = read("/forge/.ssh/config").unwrap_err();
+        assert!(matches!(
+            err,
+            rusqlite::Error::FromSqlConversionFailure(5, ..)
+        ));
+        assert!(
+            err.to_string()
+                .contains("unknown deployment mode 'teleport'")
+        );
+    }
+
+    #[test]
+    fn test_refuses_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+        let err = migrate(&mut conn, &PathTokens::default()).unwrap_err();
+        assert!(err.to_string().contains("newer than this forge supports"));
+        assert_eq!(current_version(&conn).unwrap(), latest_version() + 1);
+    }
+}
@@ -1,15 +1,15 @@
-use std::fs;
+use crate::config::Config;
+use crate::engine::backups;
 use std::io;
 use std::path::Path;
 
-pub fn backup_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
-    // Implementation of backup_file
+/// Store a copy of `path` as a new version in the backup store of `config`
+pub fn backup_file<P: AsRef<Path>>(path: P, config: &Config) -> io::Result<()> {
     let path = path.as_ref();
     if !path.exists() {
         return Ok(());
     }
-    
-    let backup_path = path.with_extension("bak");
-    fs::copy(path, backup_path)?;
-    Ok(())
+
+    let store = backups::store_dir(config.get_config_dir());
+    backups::store(path, &backups::version_dir(&store, path))
 }
@@ -1,28 +1,108 @@
+use crate::config::Config;
+use crate::config::folder::ConflictPolicy;
+use crate::engine::atomic;
 use crate::symlink;
+use std::fs;
 use std::io;
 use std::path::Path;
 
-pub fn link_file<P: AsRef<Path>, Q: AsRef<Path>>(source: P, target: Q) -> io::Result<()> {
-    // Implementation of link_file
+/// Link `target` to `source`, resolving a file already at `target` by `policy`, with
+/// the `backup` policy keeping it in the backup store of `config`
+///
+/// The symlink is created beside `target` under a temporary name and renamed over it,
+/// so `target` never goes missing. The prompt policy cannot ask from here, so it is
+/// refused; the engine resolves it before anything is linked.
+pub fn link_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    source: P,
+    target: Q,
+    policy: ConflictPolicy,
+    config: &Config,
+) -> io::Result<()> {
     let source = source.as_ref();
     let target = target.as_ref();
-    
-    if target.exists() {
-        if target.is_symlink() {
-            // If target is already a symlink, remove it
-            std::fs::remove_file(target)?;
-        } else {
-            // If target exists but is not a symlink, back it up
-            crate::dotfile::backup::backup_file(target)?;
-            std::fs::remove_file(target)?;
+
+    // A symlink holds nothing to lose
+    if !symlink::is_symlink(target) && target.exists() {
+        match policy {
+            ConflictPolicy::Skip => return Ok(()),
+            ConflictPolicy::Overwrite => {}
+            ConflictPolicy::Backup => crate::dotfile::backup::backup_file(target, config)?,
+            ConflictPolicy::Adopt => {
+                fs::copy(target, source)?;
+            }
+            ConflictPolicy::Prompt => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} already exists", target.display()),
+                ));
+            }
         }
     }
-    
+
     // Create parent directories if they don't exist
     if let Some(parent) = target.parent() {
-        std::fs::create_dir_all(parent)?;
+        fs::create_dir_all(parent)?;
+    }
+
+    let temp = atomic::temp_path(target);
+    atomic::discard_temp(target)?;
+    symlink::create_symlink(source, &temp)?;
+    atomic::rename_over(&temp, target).inspect_err(|_| {
+        let _ = atomic::discard_temp(target);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::backups;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    fn test_config(temp: &TempDir) -> Config {
+        Config::with_dirs(temp.path().join("config"), temp.path().join("forge.db"))
+    }
+
+    #[test]
+    fn test_link_file_keeps_the_old_link_when_replacing_fails() {
+        let temp = TempDir::new().unwrap();
+        let old = temp.child("old");
+        old.touch().unwrap();
+        let new = temp.child("new");
+        new.touch().unwrap();
+        let config = test_config(&temp);
+
+        // The temporary name is too long for the filesystem, so the new link is never made
+        let target = temp.path().join("a".repeat(250));
+        symlink::create_symlink(old.path(), &target).unwrap();
+        assert!(link_file(new.path(), &target, ConflictPolicy::Overwrite, &config).is_err());
+        assert_eq!(fs::read_link(&target).unwrap(), old.path());
+
+        let target = temp.path().join(".bashrc");
+        symlink::create_symlink(old.path(), &target).unwrap();
+        link_file(new.path(), &target, ConflictPolicy::Overwrite, &config).unwrap();
+        assert_eq!(fs::read_link(&target).unwrap(), new.path());
+        assert!(!atomic::temp_path(&target).exists());
+    }
+
+    #[test]
+    fn test_link_file_backs_up_into_the_config_store() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.child("forge/.bashrc");
+        source.write_str("managed\n").unwrap();
+        let target = temp.child("home/.bashrc");
+        target.write_str("local\n").unwrap();
+        let config = test_config(&temp);
+
+        link_file(
+            source.path(),
+            target.path(),
+            ConflictPolicy::Backup,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(fs::read_link(target.path()).unwrap(), source.path());
+        let store = backups::store_dir(config.get_config_dir());
+        assert_eq!(fs::read_dir(&store).unwrap().count(), 1);
     }
-    
-    symlink::create_symlink(source, target)?;
-    Ok(())
 }
@@ -3,22 +3,47 @@ pub mod link;
 pub mod list;
 pub mod unlink;
 
+use crate::config::folder::DeployMode;
+use crate::config::paths::{self, PathTokens};
+use crate::utils::path_utils::escaped;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DotFile {
+    #[serde(with = "escaped")]
     pub source: PathBuf,
+    #[serde(with = "escaped")]
     pub target: PathBuf,
     pub profile: Option<String>,
     pub status: DotFileStatus,
+    /// How the file is deployed once it is linked
+    #[serde(default)]
+    pub mode: DeployMode,
+    /// BLAKE3 hash of the content last synced, for files deployed as copies, or last
+    /// rendered, for templates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DotFileStatus {
     Staged,
     Linked,
     Unlinked,
 }
 
+impl DotFileStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DotFileStatus::Staged => "staged",
+            DotFileStatus::Linked => "linked",
+            DotFileStatus::Unlinked => "unlinked",
+        }
+    }
+}
+
 impl DotFile {
     pub fn new(source: PathBuf, target: PathBuf, profile: Option<String>) -> Self {
         Self {
@@ -26,6 +51,8 @@ impl DotFile {
             target,
             profile,
             status: DotFileStatus::Staged,
+            mode: DeployMode::Symlink,
+            hash: None,
         }
     }
     
@@ -35,9 +62,42 @@ impl DotFile {
             target,
             profile,
             status,
+            mode: DeployMode::Symlink,
+            hash: None,
         }
     }
     
+    /// Read a record from a row of `source, target, profile, status, hash, mode`
+    ///
+    /// An unknown mode is an error rather than a symlink, so a record written by a newer
+    /// release is never deployed the wrong way.
+    pub fn from_row(row: &rusqlite::Row, tokens: &PathTokens) -> rusqlite::Result<Self> {
+        let source = paths::column_bytes(row, 0)?;
+        let target = paths::column_bytes(row, 1)?;
+        let status = match row.get::<_, String>(3)?.as_str() {
+            "linked" => DotFileStatus::Linked,
+            "unlinked" => DotFileStatus::Unlinked,
+            _ => DotFileStatus::Staged,
+        };
+        let mode = row.get::<_, String>(5)?.parse::<DeployMode>().map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(5, rusqlite::types::Type::Text, e.into())
+        })?;
+        
+        Ok(Self::with_status(tokens.decode(&source), tokens.decode(&target), row.get(2)?, status)
+            .with_hash(row.get(4)?)
+            .with_mode(mode))
+    }
+    
+    pub fn with_hash(mut self, hash: Option<String>) -> Self {
+        self.hash = hash;
+        self
+    }
+    
+    pub fn with_mode(mut self, mode: DeployMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    
     pub fn set_status(&mut self, status: DotFileStatus) {
         self.status = status;
     }
@@ -53,4 +113,12 @@ impl DotFile {
     pub fn is_unlinked(&self) -> bool {
         self.status == DotFileStatus::Unlinked
     }
+    
+    pub fn is_copy(&self) -> bool {
+        self.is_linked() && self.mode == DeployMode::Copy
+    }
+    
+    pub fn is_template(&self) -> bool {
+        self.is_linked() && self.mode == DeployMode::Template
+    }
 }
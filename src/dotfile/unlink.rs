@@ -1,12 +1,16 @@
+use crate::engine::atomic;
 use std::fs;
 use std::io;
 use std::path::Path;
 
+/// Replace the symlink `target` with a copy of the file it points to
+///
+/// The copy is written beside the link and renamed over it, so `target` never goes
+/// missing, and it keeps the original's mode and modification time.
 pub fn unlink_file<P: AsRef<Path>>(target: P) -> io::Result<()> {
-    // Implementation of unlink_file
     let target = target.as_ref();
     
-    if !target.exists() {
+    if fs::symlink_metadata(target).is_err() {
         return Ok(());
     }
     
@@ -17,13 +21,6 @@ pub fn unlink_file<P: AsRef<Path>>(target: P) -> io::Result<()> {
         ));
     }
     
-    fs::remove_file(target)?;
-    
-    // Restore backup if it exists
-    let backup_path = target.with_extension("bak");
-    if backup_path.exists() {
-        fs::rename(backup_path, target)?;
-    }
-    
-    Ok(())
+    let original = fs::canonicalize(target)?;
+    atomic::replace_with_copy(target, &original)
 }
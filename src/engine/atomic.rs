@@ -0,0 +1,184 @@
+// Replacing a path in one rename, so it never goes missing
+use super::journal;
+use crate::symlink;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const TEMP_SUFFIX: &str = ".forge-tmp";
+
+/// The temporary name new content for `path` is written under, beside it
+///
+/// The name is fixed so that recovery in another process can find it.
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(TEMP_SUFFIX);
+    path.with_file_name(name)
+}
+
+/// Remove what is left at the temporary name of `path` by an interrupted replace
+pub(crate) fn discard_temp(path: &Path) -> io::Result<()> {
+    let temp = temp_path(path);
+    match fs::symlink_metadata(&temp) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&temp),
+        Ok(_) => fs::remove_file(&temp),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Copy `from` to the free path `to`, keeping its mode and modification time, and
+/// check the copy against the original's hash
+///
+/// Symlinks are copied as symlinks and directories with their contents.
+pub(crate) fn copy_verified(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.is_symlink() {
+        return symlink::create_symlink(fs::read_link(from)?, to);
+    }
+
+    if metadata.is_dir() {
+        journal::copy_tree(from, to)?;
+    } else {
+        copy_file(from, to)?;
+    }
+
+    if content_hash(from)? != content_hash(to)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the copy of {} at {} does not match it",
+                from.display(),
+                to.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Copy the file `from` to the free path `to`, keeping its mode and modification time
+pub(crate) fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(from)?;
+    // The mode is set last, as it may not allow writing the copy
+    let mut file = fs::File::create_new(to)?;
+    io::copy(&mut fs::File::open(from)?, &mut file)?;
+    file.set_modified(metadata.modified()?)?;
+    file.sync_all()?;
+    fs::set_permissions(to, metadata.permissions())
+}
+
+/// Replace `path` with a verified copy of `from`
+pub(crate) fn replace_with_copy(path: &Path, from: &Path) -> io::Result<()> {
+    let temp = temp_path(path);
+    discard_temp(path)?;
+    if let Err(e) = copy_verified(from, &temp) {
+        let _ = discard_temp(path);
+        return Err(e);
+    }
+    rename_over(&temp, path)
+}
+
+/// Rename `from` over `to`, in one step unless either is a directory
+///
+/// rename(2) swaps files and symlinks in one step, so a crash leaves either the old or
+/// the new content at `to`. A directory cannot be renamed over anything but an empty
+/// directory, so replacing one, or replacing something with one, removes the old path
+/// just before the rename.
+pub(crate) fn rename_over(from: &Path, to: &Path) -> io::Result<()> {
+    let is_dir = |path: &Path| fs::symlink_metadata(path).is_ok_and(|m| m.is_dir());
+    if is_dir(from) || is_dir(to) {
+        match fs::symlink_metadata(to) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(to)?,
+            Ok(_) => fs::remove_file(to)?,
+            Err(_) => {}
+        }
+    }
+    fs::rename(from, to)
+}
+
+/// Keep what is at `path` at `backup` as well, without moving it
+///
+/// Files are hard-linked where possible, so keeping them costs nothing. The copy
+/// only appears at `backup` once it is complete. A directory cannot be renamed over
+/// anyway, so it is moved to `backup` instead.
+pub(crate) fn keep(path: &Path, backup: &Path) -> io::Result<()> {
+    if let Some(parent) = backup.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        return journal::move_path(path, backup);
+    }
+    let temp = temp_path(backup);
+    discard_temp(backup)?;
+    if metadata.is_symlink() {
+        symlink::create_symlink(fs::read_link(path)?, &temp)?;
+    } else if fs::hard_link(path, &temp).is_err() {
+        copy_file(path, &temp)?;
+    }
+    fs::rename(&temp, backup)
+}
+
+/// The BLAKE3 hash of a file, or of a directory's files and their paths
+pub(crate) fn content_hash(path: &Path) -> io::Result<blake3::Hash> {
+    if !path.is_dir() {
+        return Ok(blake3::hash(&fs::read(path)?));
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    for entry in walkdir::WalkDir::new(path).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::other)?;
+        let relative = entry.path().strip_prefix(path).map_err(io::Error::other)?;
+        hasher.update(relative.as_os_str().as_encoded_bytes());
+        hasher.update(&[0]);
+        if entry.file_type().is_file() {
+            hasher.update(blake3::hash(&fs::read(entry.path())?).as_bytes());
+        }
+    }
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_replace_with_copy_keeps_mode_and_mtime() {
+        let temp = TempDir::new().unwrap();
+        let original = temp.child("forge/.netrc");
+        original.write_str("machine example.com\n").unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::options()
+            .write(true)
+            .open(original.path())
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(original.path(), fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        let link = temp.child("home/.netrc");
+        fs::create_dir_all(temp.child("home").path()).unwrap();
+        symlink::create_symlink(original.path(), link.path()).unwrap();
+
+        replace_with_copy(link.path(), original.path()).unwrap();
+        let metadata = fs::symlink_metadata(link.path()).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.modified().unwrap(), mtime);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+        assert_eq!(
+            fs::read_to_string(link.path()).unwrap(),
+            "machine example.com\n"
+        );
+        assert!(!temp_path(link.path()).exists());
+    }
+}
@@ -0,0 +1,429 @@
+// A versioned store of the files forge replaces
+use super::conflict::Conflict;
+use super::journal::{self, Entry, Step};
+use super::{Action, Engine, Outcome, Report};
+use crate::config::folder::ConflictPolicy;
+use crate::error::{ForgeError, Result};
+use crate::utils::path_utils::{self, escaped};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const BACKUPS_DIR: &str = "backups";
+const CONTENT: &str = "content";
+const METADATA_FILE: &str = "backup.json";
+const KEEP_SETTING: &str = "backup_keep";
+const MAX_AGE_SETTING: &str = "backup_max_age_days";
+
+/// One stored version of a replaced file or directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Backup {
+    /// The version's directory name, `<timestamp>-<path hash>`
+    pub id: String,
+    /// Where the file was when it was backed up
+    #[serde(with = "escaped")]
+    pub path: PathBuf,
+    pub created_at: DateTime<Utc>,
+    /// BLAKE3 hash of the content; for a directory, of its file paths and their hashes
+    pub hash: String,
+    /// Size of the content in bytes, summed over a directory's files
+    pub size: u64,
+    pub directory: bool,
+}
+
+/// Which versions the store keeps, and which operations keep the files needed to undo
+/// them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Retention {
+    /// Versions kept of each path, newest first
+    pub keep: usize,
+    /// Versions older than this many days are dropped
+    pub max_age_days: Option<u32>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            keep: 10,
+            max_age_days: None,
+        }
+    }
+}
+
+impl Retention {
+    /// Whether the version at `index` among its path's versions, newest first, is
+    /// dropped; the newest version of every path is always kept
+    pub(crate) fn drops(
+        &self,
+        index: usize,
+        created_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        if index == 0 {
+            return false;
+        }
+        let expired = self
+            .max_age_days
+            .is_some_and(|days| created_at < now - Duration::days(i64::from(days)));
+        index >= self.keep || expired
+    }
+}
+
+/// The directory a new version of `path` is written to, under `store`
+///
+/// It is named after the time and a hash of `path`, so versions sort by age and never
+/// overwrite one another.
+pub(crate) fn version_dir(store: &Path, path: &Path) -> PathBuf {
+    let key = blake3::hash(path.as_os_str().as_encoded_bytes()).to_hex();
+    store.join(format!(
+        "{}-{}",
+        Utc::now().format("%Y%m%dT%H%M%S%.6fZ"),
+        &key[..8]
+    ))
+}
+
+/// The backups directory inside forge's configuration directory
+pub(crate) fn store_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join(BACKUPS_DIR)
+}
+
+/// Copy `path` into `version` and describe it there
+///
+/// `backup.json` records the original path and a BLAKE3 hash of the content. The
+/// journal's backup step calls this, so a batch that rolls back takes its backups
+/// with it.
+pub(crate) fn store(path: &Path, version: &Path) -> io::Result<()> {
+    let content = version.join(CONTENT);
+    fs::create_dir_all(version)?;
+    let directory = fs::metadata(path)?.is_dir();
+    if directory {
+        journal::copy_tree(path, &content)?;
+    } else {
+        fs::copy(path, &content)?;
+    }
+
+    let (hash, size) = content_hash(&content)?;
+    let backup = Backup {
+        id: version
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+        path: path.to_path_buf(),
+        created_at: Utc::now(),
+        hash,
+        size,
+        directory,
+    };
+    let json = serde_json::to_string_pretty(&backup).map_err(io::Error::other)?;
+    fs::write(version.join(METADATA_FILE), json)
+}
+
+/// Hash a file, or a directory's file paths and contents, returning it with the size
+fn content_hash(content: &Path) -> io::Result<(String, u64)> {
+    if !content.is_dir() {
+        let data = fs::read(content)?;
+        return Ok((blake3::hash(&data).to_hex().to_string(), data.len() as u64));
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    let mut size = 0;
+    for entry in walkdir::WalkDir::new(content).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::other)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(content)
+            .map_err(io::Error::other)?;
+        let data = fs::read(entry.path())?;
+        size += data.len() as u64;
+        hasher.update(relative.as_os_str().as_encoded_bytes());
+        hasher.update(&[0]);
+        hasher.update(blake3::hash(&data).as_bytes());
+    }
+    Ok((hasher.finalize().to_hex().to_string(), size))
+}
+
+/// Read a version's description, skipping directories that are not versions
+fn load(version: &Path) -> Option<Backup> {
+    let json = fs::read_to_string(version.join(METADATA_FILE)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+impl Engine {
+    fn backups_dir(&self) -> PathBuf {
+        store_dir(self.config.get_config_dir())
+    }
+
+    /// Stored backups, newest first, optionally only those of `path`
+    pub fn backups(&self, path: Option<&Path>) -> Result<Vec<Backup>> {
+        let dir = self.backups_dir();
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(ForgeError::io(
+                    format!("failed to read {}", dir.display()),
+                    e,
+                ));
+            }
+        };
+
+        let path = path.map(path_utils::normalize);
+        let mut backups: Vec<Backup> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| load(&entry.path()))
+            .filter(|backup| path.as_ref().is_none_or(|path| backup.path == *path))
+            .collect();
+        backups.sort_by(|a, b| b.id.cmp(&a.id));
+        Ok(backups)
+    }
+
+    /// Find a backup by its id, or by a prefix of the id that only one backup has
+    pub fn find_backup(&self, id: &str) -> Result<Backup> {
+        let mut matches: Vec<Backup> = self
+            .backups(None)?
+            .into_iter()
+            .filter(|backup| backup.id.starts_with(id))
+            .collect();
+        if let Some(exact) = matches.iter().position(|backup| backup.id == id) {
+            return Ok(matches.swap_remove(exact));
+        }
+        match matches.len() {
+            1 => Ok(matches.remove(0)),
+            0 => Err(ForgeError::InvalidInput(format!(
+                "no backup with id {}",
+                id
+            ))),
+            n => Err(ForgeError::InvalidInput(format!(
+                "{} backups have ids starting with {}",
+                n, id
+            ))),
+        }
+    }
+
+    /// Where a backup's copy of the file is kept
+    pub fn backup_content(&self, backup: &Backup) -> PathBuf {
+        self.backups_dir().join(&backup.id).join(CONTENT)
+    }
+
+    /// Put a backup's content back at its original path, or at `to`
+    ///
+    /// A different file already there is resolved by `on_conflict`, or else the
+    /// managed folder's configured policy. The copy runs as a batch, so it can be
+    /// undone.
+    pub fn restore_backup(
+        &self,
+        id: &str,
+        to: Option<&Path>,
+        on_conflict: Option<ConflictPolicy>,
+    ) -> Result<Report> {
+        let backup = self.find_backup(id)?;
+        let content = self.backup_content(&backup);
+        if !content.exists() {
+            return Err(ForgeError::NotFound(content));
+        }
+        let dest = to.map_or_else(|| backup.path.clone(), path_utils::normalize);
+        let resolver = self.resolver(on_conflict)?;
+        self.ensure_no_pending_batch()?;
+
+        let mut entry = Entry::new(&dest).with_target(&content);
+        let occupied = fs::symlink_metadata(&dest).is_ok();
+        if super::conflict::same_content(&dest, &content) {
+            let outcome = Outcome::skipped(Action::Restore, &dest, "already matches the backup");
+            return Ok(std::iter::once(outcome.with_target(&content)).collect());
+        }
+        if occupied {
+            let conflict = Conflict::new(Action::Restore, &dest, &content);
+            match resolver.clear_unmanaged(entry, &conflict) {
+                Ok(cleared) => entry = cleared,
+                Err(outcome) => return Ok(std::iter::once(outcome).collect()),
+            }
+        }
+        let step = if occupied {
+            Step::Replace {
+                path: dest,
+                from: content,
+            }
+        } else {
+            Step::Copy {
+                from: content,
+                to: dest,
+            }
+        };
+        let entry = entry
+            .step(step)
+            .with_message(format!("from backup {}", backup.id));
+        self.run_batch(Action::Restore, vec![entry])
+    }
+
+    /// Drop the backups and history archives `retention` no longer keeps, or list them
+    /// with `dry_run`
+    ///
+    /// Both go under one policy because undoing an operation needs the backups it made
+    /// as well as its archive.
+    pub fn prune(&self, retention: &Retention, dry_run: bool) -> Result<Report> {
+        let mut report = self.prune_backups(retention, dry_run)?;
+        report.extend(self.prune_history(retention, dry_run)?);
+        Ok(report)
+    }
+
+    /// Drop the versions `retention` no longer keeps, or list them with `dry_run`
+    fn prune_backups(&self, retention: &Retention, dry_run: bool) -> Result<Report> {
+        let now = Utc::now();
+        let mut seen: Vec<PathBuf> = Vec::new();
+        let mut report = Report::new();
+
+        // Backups come newest first, so counting a path's earlier versions gives the
+        // version's place among them
+        for backup in self.backups(None)? {
+            let index = seen.iter().filter(|path| **path == backup.path).count();
+            seen.push(backup.path.clone());
+            if !retention.drops(index, backup.created_at, now) {
+                continue;
+            }
+
+            let version = self.backups_dir().join(&backup.id);
+            let outcome = if dry_run {
+                Outcome::planned(Action::Prune, &backup.path)
+            } else {
+                match fs::remove_dir_all(&version) {
+                    Ok(()) => Outcome::done(Action::Prune, &backup.path),
+                    Err(e) => Outcome::failed(
+                        Action::Prune,
+                        &backup.path,
+                        ForgeError::io(format!("failed to remove {}", version.display()), e),
+                    ),
+                }
+            };
+            report.push(
+                outcome
+                    .with_target(&version)
+                    .with_message(format!("backup {}", backup.id)),
+            );
+        }
+        Ok(report)
+    }
+
+    /// The retention policy applied after each batch that keeps files
+    pub fn retention(&self) -> Result<Retention> {
+        let mut retention = Retention::default();
+        let invalid = |key: &str, value: &str| {
+            ForgeError::InvalidInput(format!("invalid setting {} = {}", key, value))
+        };
+        if let Some(value) = self.config.get_setting(KEEP_SETTING)? {
+            retention.keep = value.parse().map_err(|_| invalid(KEEP_SETTING, &value))?;
+        }
+        if let Some(value) = self.config.get_setting(MAX_AGE_SETTING)? {
+            retention.max_age_days = Some(
+                value
+                    .parse()
+                    .map_err(|_| invalid(MAX_AGE_SETTING, &value))?,
+            );
+        }
+        Ok(retention)
+    }
+
+    /// Store the retention policy applied after each batch that keeps files
+    pub fn set_retention(&self, retention: &Retention) -> Result<()> {
+        self.config
+            .set_setting(KEEP_SETTING, Some(&retention.keep.to_string()))?;
+        self.config.set_setting(
+            MAX_AGE_SETTING,
+            retention
+                .max_age_days
+                .map(|days| days.to_string())
+                .as_deref(),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::test_engine;
+    use crate::engine::{LinkOptions, StageOptions, Status};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_conflicts_are_backed_up_as_versions() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let file = temp.child("home/config.toml");
+        file.write_str("home\n").unwrap();
+        engine
+            .stage(&[file.to_path_buf()], &StageOptions::default())
+            .unwrap();
+        let forge = temp.child("forge/config.toml");
+        fs::remove_file(forge.path()).unwrap();
+        forge.write_str("forge\n").unwrap();
+
+        // The replaced file goes to the store instead of a sibling file
+        let options = LinkOptions {
+            on_conflict: Some(ConflictPolicy::Backup),
+            ..LinkOptions::default()
+        };
+        assert_eq!(engine.link(&[], &options).unwrap().succeeded(), 1);
+        assert!(!temp.child("home/config.toml.bak").exists());
+        assert!(!temp.child("home/config.bak").exists());
+        let backups = engine.backups(Some(file.path())).unwrap();
+        assert_eq!(backups.len(), 1);
+        let backup = &backups[0];
+        assert_eq!(backup.hash, blake3::hash(b"home\n").to_hex().to_string());
+        assert_eq!(
+            fs::read_to_string(engine.backup_content(backup)).unwrap(),
+            "home\n"
+        );
+        assert_eq!(engine.find_backup(&backup.id[..10]).unwrap(), *backup);
+
+        // Restoring replaces the symlink with the backed up content
+        let report = engine
+            .restore_backup(&backup.id, None, Some(ConflictPolicy::Overwrite))
+            .unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert!(!file.path().is_symlink());
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "home\n");
+        let report = engine.restore_backup(&backup.id, None, None).unwrap();
+        assert_eq!(report.count(Status::Skipped), 1);
+
+        // Only the newest versions the retention policy keeps survive a prune
+        for _ in 0..3 {
+            store(
+                file.path(),
+                &version_dir(&engine.backups_dir(), file.path()),
+            )
+            .unwrap();
+        }
+        let retention = Retention {
+            keep: 2,
+            max_age_days: Some(30),
+        };
+        let report = engine.prune(&retention, true).unwrap();
+        assert_eq!(report.count(Status::Planned), 2);
+        assert_eq!(engine.backups(None).unwrap().len(), 4);
+        engine.prune(&retention, false).unwrap();
+        let kept = engine.backups(None).unwrap();
+        assert_eq!(kept.len(), 2);
+        assert!(!kept.contains(backup));
+
+        // The link that made the dropped backup can no longer be undone
+        let link = engine
+            .history(None)
+            .unwrap()
+            .into_iter()
+            .find(|op| op.action == Action::Link)
+            .unwrap();
+        assert!(link.pruned_at.is_some());
+        assert!(engine.undo_candidate(Some(&link.id)).is_err());
+
+        assert_eq!(engine.retention().unwrap(), Retention::default());
+        engine.set_retention(&retention).unwrap();
+        assert_eq!(engine.retention().unwrap(), retention);
+    }
+}
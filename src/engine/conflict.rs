@@ -0,0 +1,206 @@
+// Resolving files that stand where forge is about to place one
+use super::journal::{Entry, Step};
+use super::{Action, Engine, Outcome, backups};
+use crate::config::folder::{ConflictPolicy, FolderConfig};
+use crate::error::{ForgeError, Result};
+use crate::symlink;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A file in the way of one forge is about to place
+///
+/// Sync reports a copy whose two sides both changed as the deployed copy standing in
+/// the way of the managed one.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub action: Action,
+    /// The occupied path
+    pub path: PathBuf,
+    /// The managed copy that is to take its place
+    pub managed: PathBuf,
+}
+
+impl Conflict {
+    pub fn new(action: Action, path: &Path, managed: &Path) -> Self {
+        Self {
+            action,
+            path: path.to_path_buf(),
+            managed: managed.to_path_buf(),
+        }
+    }
+}
+
+/// Asks how to resolve a conflict whose policy is `prompt`
+pub type Prompt = Box<dyn Fn(&Conflict) -> ConflictPolicy>;
+
+/// The conflict policy for one operation, with the prompt to use for `prompt`
+pub(crate) struct Resolver<'a> {
+    policy: Option<ConflictPolicy>,
+    prompt: Option<&'a Prompt>,
+    /// The backup store the `backup` policy keeps conflicting files in
+    backups: PathBuf,
+}
+
+impl Resolver<'_> {
+    /// Decide how to resolve `conflict`, asking if the policy is to prompt
+    ///
+    /// Without a policy, or without a way to ask, the conflict is a failed outcome.
+    pub(crate) fn resolve(
+        &self,
+        conflict: &Conflict,
+    ) -> std::result::Result<ConflictPolicy, Outcome> {
+        let failed = |message: &str| {
+            Outcome::failed(
+                conflict.action,
+                &conflict.path,
+                ForgeError::Conflict(conflict.path.clone()),
+            )
+            .with_target(&conflict.managed)
+            .with_message(message)
+        };
+        let problem = match conflict.action {
+            Action::Sync => "changed on both sides since the last sync",
+            Action::Render => "was edited since it was rendered",
+            _ => "already exists",
+        };
+        match (self.policy, self.prompt) {
+            (None, _) => Err(failed(&format!(
+                "{} (use --on-conflict backup, skip, overwrite or adopt)",
+                problem
+            ))),
+            (Some(ConflictPolicy::Prompt), None) => Err(failed(&format!(
+                "{} and the prompt policy cannot ask here (use another --on-conflict)",
+                problem
+            ))),
+            (Some(ConflictPolicy::Prompt), Some(prompt)) => match prompt(conflict) {
+                ConflictPolicy::Prompt => Ok(ConflictPolicy::Skip),
+                answer => Ok(answer),
+            },
+            (Some(policy), _) => Ok(policy),
+        }
+    }
+
+    /// Plan what happens to the conflicting path before the caller's step replaces it
+    ///
+    /// Every policy is planned as journal steps, so a batch that fails part way is
+    /// rolled back and a finished one can be undone. The path itself is left in place
+    /// for the caller's step to replace in one rename, so it never goes missing. A
+    /// skipped conflict is returned as the skipped outcome.
+    pub(crate) fn clear(
+        &self,
+        entry: Entry,
+        conflict: &Conflict,
+    ) -> std::result::Result<Entry, Outcome> {
+        let policy = self.resolve(conflict)?;
+        self.apply(entry, policy, conflict)
+    }
+
+    /// Like [`Resolver::clear`], for content that has no managed copy to adopt into,
+    /// such as a pack's files or a stored backup
+    pub(crate) fn clear_unmanaged(
+        &self,
+        entry: Entry,
+        conflict: &Conflict,
+    ) -> std::result::Result<Entry, Outcome> {
+        match self.resolve(conflict)? {
+            ConflictPolicy::Adopt => Err(Outcome::skipped(
+                conflict.action,
+                &conflict.path,
+                "already exists; kept it, as there is no managed copy to adopt it into",
+            )
+            .with_target(&conflict.managed)),
+            policy => self.apply(entry, policy, conflict),
+        }
+    }
+
+    /// Add the steps that deal with `conflict.path` under `policy`, short of replacing it
+    ///
+    /// Callers that need to know the policy, to tell which content wins, resolve it
+    /// first with [`Resolver::resolve`].
+    pub(crate) fn apply(
+        &self,
+        entry: Entry,
+        policy: ConflictPolicy,
+        conflict: &Conflict,
+    ) -> std::result::Result<Entry, Outcome> {
+        let path = &conflict.path;
+        match policy {
+            ConflictPolicy::Skip | ConflictPolicy::Prompt => Err(Outcome::skipped(
+                conflict.action,
+                path,
+                "already exists; kept the existing file",
+            )
+            .with_target(&conflict.managed)),
+            ConflictPolicy::Overwrite => Ok(entry.with_message("replaced the existing file")),
+            ConflictPolicy::Backup => {
+                let version = backups::version_dir(&self.backups, path);
+                let message = format!(
+                    "backed up the existing file as {}",
+                    version.file_name().unwrap_or_default().to_string_lossy()
+                );
+                Ok(entry
+                    .step(Step::Backup {
+                        path: path.clone(),
+                        to: version,
+                    })
+                    .with_message(message))
+            }
+            ConflictPolicy::Adopt => {
+                let managed = &conflict.managed;
+                let mut entry = entry;
+                if fs::symlink_metadata(managed).is_ok() {
+                    entry = entry.step(Step::Remove {
+                        path: managed.clone(),
+                    });
+                }
+                Ok(entry
+                    .step(Step::Copy {
+                        from: path.clone(),
+                        to: managed.clone(),
+                    })
+                    .with_message("adopted the existing file into the managed copy"))
+            }
+        }
+    }
+}
+
+/// Whether two regular files hold the same bytes, so replacing one loses nothing
+pub(crate) fn same_content(a: &Path, b: &Path) -> bool {
+    if symlink::is_symlink(a) || symlink::is_symlink(b) || !a.is_file() || !b.is_file() {
+        return false;
+    }
+    match (fs::read(a), fs::read(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+impl Engine {
+    /// Ask `prompt` how to resolve conflicts whose policy is `prompt`
+    ///
+    /// Without one, such conflicts are reported as failures.
+    pub fn with_prompt<F>(mut self, prompt: F) -> Self
+    where
+        F: Fn(&Conflict) -> ConflictPolicy + 'static,
+    {
+        self.prompt = Some(Box::new(prompt));
+        self
+    }
+
+    /// The conflict policy for an operation: `chosen`, or else the active managed
+    /// folder's setting
+    pub(crate) fn resolver(&self, chosen: Option<ConflictPolicy>) -> Result<Resolver<'_>> {
+        let policy = match chosen {
+            Some(policy) => Some(policy),
+            None => match self.config.get_active_managed_folder() {
+                Ok(Some((_, folder))) => FolderConfig::load(&folder)?.on_conflict,
+                _ => None,
+            },
+        };
+        Ok(Resolver {
+            policy,
+            prompt: self.prompt.as_ref(),
+            backups: backups::store_dir(self.config.get_config_dir()),
+        })
+    }
+}
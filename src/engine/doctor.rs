@@ -0,0 +1,300 @@
+// Repairs for files that drifted from their tracking records
+use super::journal::{Entry, RecordChange, Step};
+use super::status::{FileStatus, Health};
+use super::{Action, Engine, Outcome, Report, backups, sync};
+use crate::config::folder::{DeployMode, LinkStyle};
+use crate::dotfile::DotFileStatus;
+use crate::error::Result;
+use crate::symlink;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// What `forge doctor --fix` does about a drifted file
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Repair {
+    /// Create the missing symlink
+    Relink,
+    /// Point the symlink back at the expected file
+    Repoint,
+    /// Move a file that replaced the symlink into the managed folder and link it again
+    Readopt,
+    /// Drop the tracking record because the file it describes is gone
+    Forget,
+    /// Copy the changed side of a file deployed as a copy to the other, or render a
+    /// template again, as `forge sync` does
+    Sync,
+    /// Nothing can be done automatically
+    Manual,
+}
+
+impl Repair {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Repair::Relink => "recreate the symlink",
+            Repair::Repoint => "point the symlink back at the managed copy",
+            Repair::Readopt => "move the replacement file into the managed folder and relink it",
+            Repair::Forget => "remove the tracking record",
+            Repair::Sync => "sync the copy with its managed copy, or render it again",
+            Repair::Manual => "needs manual attention",
+        }
+    }
+}
+
+/// A drifted file and the repair that would fix it
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnosis {
+    pub status: FileStatus,
+    pub repair: Repair,
+}
+
+/// Choose the repair for a file, or `None` if it is healthy
+pub fn repair_for(status: &FileStatus) -> Option<Repair> {
+    let expected_exists = status.expected.exists();
+    let repair = match status.health {
+        Health::Ok => return None,
+        // A hand-edited generated file would be lost by rendering it again
+        Health::CopyChanged if status.dotfile.mode == DeployMode::Template => Repair::Manual,
+        Health::Stale => Repair::Sync,
+        Health::CopyChanged | Health::ForgeCopyChanged => Repair::Sync,
+        Health::BothChanged => Repair::Manual,
+        // A missing copy is deployed again rather than replaced by a symlink
+        Health::MissingLink
+            if (status.dotfile.is_copy() || status.dotfile.is_template()) && expected_exists =>
+        {
+            Repair::Sync
+        }
+        Health::MissingLink if expected_exists => Repair::Relink,
+        Health::WrongTarget | Health::Broken if expected_exists => Repair::Repoint,
+        // Only linked files keep their content in the managed folder
+        Health::ReplacedByFile
+            if status.dotfile.status != DotFileStatus::Staged && status.link.is_file() =>
+        {
+            Repair::Readopt
+        }
+        Health::ReplacedByFile => Repair::Manual,
+        // The file the record describes is gone
+        Health::MissingLink | Health::WrongTarget | Health::Broken | Health::ForgeCopyMissing => {
+            Repair::Forget
+        }
+    };
+    Some(repair)
+}
+
+impl Engine {
+    /// Find drifted files and the repair for each
+    pub fn diagnose(&self, profile: Option<&str>) -> Result<Vec<Diagnosis>> {
+        Ok(self
+            .status(profile)?
+            .into_iter()
+            .filter_map(|status| repair_for(&status).map(|repair| Diagnosis { status, repair }))
+            .collect())
+    }
+
+    /// Apply the repairs for drifted files as one batch
+    ///
+    /// Manual repairs are reported as skipped. The rest are planned as journal steps,
+    /// so a batch that fails part way is rolled back and a finished one can be undone;
+    /// a managed copy that a readopted file replaces is kept in the backup store too.
+    pub fn repair(&self, diagnoses: &[Diagnosis], dry_run: bool) -> Result<Report> {
+        let mut report = Report::new();
+        if !dry_run {
+            self.ensure_no_pending_batch()?;
+        }
+        let needs_sync = diagnoses.iter().any(|d| d.repair == Repair::Sync);
+        let syncing = if needs_sync && !dry_run {
+            Some((self.resolver(None)?, self.renderer()?))
+        } else {
+            None
+        };
+        let needs_style = diagnoses
+            .iter()
+            .any(|d| !matches!(d.repair, Repair::Manual | Repair::Sync | Repair::Forget));
+        let style = if needs_style && !dry_run {
+            self.link_style()?
+        } else {
+            LinkStyle::default()
+        };
+        let backups = backups::store_dir(self.config.get_config_dir());
+
+        let mut entries = Vec::new();
+        for diagnosis in diagnoses {
+            let status = &diagnosis.status;
+            let link = &status.link;
+            let expected = &status.expected;
+            let planned = match (diagnosis.repair, &syncing) {
+                (Repair::Manual, _) => Err(Outcome::skipped(
+                    Action::Repair,
+                    link,
+                    diagnosis.repair.describe(),
+                )),
+                _ if dry_run => Err(Outcome::planned(Action::Repair, link)
+                    .with_target(expected)
+                    .with_message(diagnosis.repair.describe())),
+                (Repair::Sync, Some((resolver, renderer))) => {
+                    sync::plan(&status.dotfile, resolver, renderer).map_err(|outcome| Outcome {
+                        action: Action::Repair,
+                        ..outcome
+                    })
+                }
+                (repair, _) => Ok(plan_repair(status, repair, style, &backups)),
+            };
+            match planned {
+                Ok(entry) => entries.push(entry),
+                Err(outcome) => report.push(outcome),
+            }
+        }
+
+        report.extend(self.run_batch(Action::Repair, entries)?);
+        Ok(report)
+    }
+}
+
+/// Plan the steps of a repair that puts a link back in place or drops a record
+fn plan_repair(status: &FileStatus, repair: Repair, style: LinkStyle, backups: &Path) -> Entry {
+    let link = &status.link;
+    let expected = &status.expected;
+    let mode = status.dotfile.mode;
+    let entry = Entry::new(link)
+        .with_target(expected)
+        .with_message(repair.describe());
+    let relative = style == LinkStyle::Relative;
+    // Replaces whatever is at `link` in one rename
+    let replace_link = || match mode {
+        DeployMode::Hardlink => Step::Hardlink {
+            link: link.clone(),
+            dest: expected.clone(),
+        },
+        _ => Step::Relink {
+            link: link.clone(),
+            dest: expected.clone(),
+            relative,
+        },
+    };
+
+    match repair {
+        // A hard link is made by replacing a copy, which also creates the parent
+        Repair::Relink if mode == DeployMode::Hardlink => entry
+            .step(Step::Copy {
+                from: expected.clone(),
+                to: link.clone(),
+            })
+            .step(replace_link()),
+        Repair::Relink => entry.step(Step::Symlink {
+            link: link.clone(),
+            dest: expected.clone(),
+            relative,
+        }),
+        Repair::Repoint => entry.step(replace_link()),
+        Repair::Readopt => {
+            let entry = match fs::symlink_metadata(expected) {
+                Ok(_) => entry
+                    .step(Step::Backup {
+                        path: expected.clone(),
+                        to: backups::version_dir(backups, expected),
+                    })
+                    .step(Step::Replace {
+                        path: expected.clone(),
+                        from: link.clone(),
+                    }),
+                Err(_) => entry.step(Step::Copy {
+                    from: link.clone(),
+                    to: expected.clone(),
+                }),
+            };
+            entry.step(replace_link())
+        }
+        Repair::Forget => {
+            let entry = entry.with_record(RecordChange::forget(&status.dotfile));
+            // A symlink still pointing at the missing file would only dangle
+            if symlink::points_to(link, expected) {
+                entry.step(Step::Remove { path: link.clone() })
+            } else {
+                entry
+            }
+        }
+        Repair::Sync | Repair::Manual => {
+            unreachable!("syncs and manual repairs are not planned here")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::test_engine;
+    use crate::engine::{LinkOptions, StageOptions, Status};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_repairs_drifted_links() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let bashrc = temp.child("home/.bashrc");
+        bashrc.write_str("old\n").unwrap();
+        let vimrc = temp.child("home/.vimrc");
+        vimrc.touch().unwrap();
+        let files = vec![bashrc.path().to_path_buf(), vimrc.path().to_path_buf()];
+        engine.stage(&files, &StageOptions::default()).unwrap();
+        engine.link(&[], &LinkOptions::default()).unwrap();
+
+        // An editor replaced one symlink and the other was deleted
+        fs::remove_file(bashrc.path()).unwrap();
+        bashrc.write_str("new\n").unwrap();
+        fs::remove_file(vimrc.path()).unwrap();
+
+        let diagnoses = engine.diagnose(None).unwrap();
+        let repairs: Vec<_> = diagnoses.iter().map(|d| d.repair).collect();
+        assert_eq!(repairs, vec![Repair::Readopt, Repair::Relink]);
+
+        // A dry run changes nothing
+        let report = engine.repair(&diagnoses, true).unwrap();
+        assert!(report.outcomes.iter().all(|o| o.status == Status::Planned));
+        assert!(!vimrc.path().is_symlink());
+
+        let report = engine.repair(&diagnoses, false).unwrap();
+        assert_eq!(report.succeeded(), 2, "{:?}", report);
+        assert!(engine.diagnose(None).unwrap().is_empty());
+        let forge_copy = temp.path().join("forge/.bashrc");
+        assert_eq!(fs::read_to_string(&forge_copy).unwrap(), "new\n");
+        assert!(bashrc.path().is_symlink());
+        // The managed copy the readopted file replaced was backed up first
+        let backups = engine.backups(None).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].path, forge_copy);
+
+        // The repairs are one operation in the history, and undoing it puts the
+        // replacement file and the old managed copy back
+        let operation = engine.undo_candidate(None).unwrap();
+        assert_eq!(operation.action, Action::Repair);
+        engine.undo(&operation).unwrap();
+        assert!(!bashrc.path().is_symlink());
+        assert_eq!(fs::read_to_string(bashrc.path()).unwrap(), "new\n");
+        assert_eq!(fs::read_to_string(&forge_copy).unwrap(), "old\n");
+        assert!(fs::symlink_metadata(vimrc.path()).is_err());
+    }
+
+    #[test]
+    fn test_forgets_records_without_forge_copy() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let file = temp.child("home/.profile");
+        file.touch().unwrap();
+        engine
+            .stage(&[file.path().to_path_buf()], &StageOptions::default())
+            .unwrap();
+        engine.link(&[], &LinkOptions::default()).unwrap();
+        fs::remove_file(temp.path().join("forge/.profile")).unwrap();
+
+        let diagnoses = engine.diagnose(None).unwrap();
+        assert_eq!(diagnoses[0].status.health, Health::ForgeCopyMissing);
+        assert_eq!(diagnoses[0].repair, Repair::Forget);
+        let report = engine.repair(&diagnoses, false).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+
+        assert!(engine.list(None).unwrap().is_empty());
+        assert!(!file.path().is_symlink());
+    }
+}
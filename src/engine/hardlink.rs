@@ -0,0 +1,187 @@
+// Deploying files as hard links to their managed copies
+use super::conflict::{self, Conflict, Resolver};
+use super::journal::{Entry, RecordChange, Step};
+use super::{Action, Outcome};
+use crate::config::folder::DeployMode;
+use crate::dotfile::{DotFile, DotFileStatus};
+use crate::error::ForgeError;
+use crate::symlink;
+use std::fs;
+use std::path::Path;
+
+/// Whether `a` and `b` are names for the same file
+pub(crate) fn same_file(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+            (Ok(a), Ok(b)) => a.is_file() && a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        conflict::same_content(a, b)
+    }
+}
+
+/// Whether `a` and `b` are, or would be created, on the same filesystem, judged by
+/// their nearest existing ancestors
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let device = |path: &Path| {
+            path.ancestors()
+                .find_map(|ancestor| fs::metadata(ancestor).ok())
+                .map(|metadata| metadata.dev())
+        };
+        device(a) == device(b)
+    }
+    // Elsewhere the link step itself reports a cross-device link
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        true
+    }
+}
+
+/// Plan deploying a staged file as a hard link to its managed copy
+///
+/// Both names then lead to the same data, so there is nothing to sync, but they must
+/// be on the same filesystem. If the forge folder already holds a different file at
+/// the target, `resolver` decides which of the two the link ends up sharing.
+pub(crate) fn plan_hardlink(
+    dotfile: &DotFile,
+    resolver: &Resolver,
+) -> std::result::Result<Entry, Outcome> {
+    let source = &dotfile.source;
+    let target = &dotfile.target;
+    let record = RecordChange::update(
+        dotfile,
+        DotFile::with_status(
+            source.clone(),
+            target.clone(),
+            dotfile.profile.clone(),
+            DotFileStatus::Linked,
+        )
+        .with_mode(DeployMode::Hardlink),
+    );
+
+    if !source.exists() {
+        return Err(Outcome::failed(
+            Action::Link,
+            source,
+            ForgeError::NotFound(source.clone()),
+        ));
+    }
+    if !source.is_file() || symlink::is_symlink(source) {
+        return Err(Outcome::failed(
+            Action::Link,
+            source,
+            ForgeError::InvalidInput(format!(
+                "{} is not a regular file; only files can be hard-linked",
+                source.display()
+            )),
+        ));
+    }
+    if !same_filesystem(source, target) {
+        return Err(Outcome::failed(
+            Action::Link,
+            source,
+            ForgeError::InvalidInput(format!(
+                "{} is on a different filesystem from the managed folder; hard links \
+                 need both on the same one",
+                source.display()
+            )),
+        )
+        .with_target(target));
+    }
+
+    let link = Step::Hardlink {
+        link: source.clone(),
+        dest: target.clone(),
+    };
+    let mut entry = Entry::new(source).with_target(target);
+    if symlink::is_symlink(target) || conflict::same_content(source, target) {
+        entry = entry.step(Step::Remove {
+            path: target.clone(),
+        });
+    } else if fs::symlink_metadata(target).is_ok() {
+        let conflict = Conflict::new(Action::Link, source, target);
+        return Ok(resolver
+            .clear(entry, &conflict)?
+            .step(link)
+            .with_record(record));
+    }
+
+    Ok(entry
+        .step(Step::Copy {
+            from: source.clone(),
+            to: target.clone(),
+        })
+        .step(link)
+        .with_record(record)
+        .with_message("deployed as a hard link"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::test_engine;
+    use crate::engine::{Health, LinkOptions, StageOptions};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_hardlink_shares_content_until_unlinked() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let deployed = temp.child("home/.gitconfig");
+        deployed.write_str("[user]\n").unwrap();
+        let managed = temp.child("forge/.gitconfig");
+        let options = StageOptions {
+            mode: Some(DeployMode::Hardlink),
+            ..Default::default()
+        };
+        engine.stage(&[deployed.to_path_buf()], &options).unwrap();
+        let report = engine.link(&[], &LinkOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert_eq!(report.outcomes[0].mode, Some(DeployMode::Hardlink));
+
+        // Writing through either name changes both
+        assert!(!deployed.path().is_symlink());
+        assert!(same_file(deployed.path(), managed.path()));
+        fs::write(deployed.path(), "[user]\n  name = Ada\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(managed.path()).unwrap(),
+            "[user]\n  name = Ada\n"
+        );
+        let health = |engine: &crate::engine::Engine| engine.status(None).unwrap()[0].health;
+        assert_eq!(health(&engine), Health::Ok);
+
+        // Saving by renaming a new file into place breaks the link
+        fs::remove_file(deployed.path()).unwrap();
+        deployed.write_str("[user]\n  name = Grace\n").unwrap();
+        assert_eq!(health(&engine), Health::ReplacedByFile);
+        let diagnoses = engine.diagnose(None).unwrap();
+        let report = engine.repair(&diagnoses, false).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert!(same_file(deployed.path(), managed.path()));
+        assert_eq!(
+            fs::read_to_string(managed.path()).unwrap(),
+            "[user]\n  name = Grace\n"
+        );
+
+        // Unlinking leaves a separate file in place
+        let selections = engine.select(&[managed.to_path_buf()]).unwrap();
+        let report = engine.unlink(&selections).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert!(!same_file(deployed.path(), managed.path()));
+        fs::write(deployed.path(), "[core]\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(managed.path()).unwrap(),
+            "[user]\n  name = Grace\n"
+        );
+    }
+}
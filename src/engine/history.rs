@@ -0,0 +1,329 @@
+// The operation history and undoing recorded operations
+use super::journal::{Entry, Step};
+use super::{Action, Engine, Outcome, Report, Retention};
+use crate::config::HistoryRecord;
+use crate::error::{ForgeError, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+/// A committed batch as recorded in the history
+#[derive(Debug, Clone, Serialize)]
+pub struct Operation {
+    pub id: String,
+    pub action: Action,
+    pub created_at: String,
+    /// Every file the operation touched, with its filesystem steps and record change
+    pub entries: Vec<Entry>,
+    /// The operation this one reversed
+    pub undoes: Option<String>,
+    /// The operation that reversed this one
+    pub undone_by: Option<String>,
+    /// When files needed to undo the operation were pruned
+    pub pruned_at: Option<String>,
+}
+
+impl Operation {
+    fn from_record(record: HistoryRecord) -> Result<Self> {
+        let corrupt = |e: serde_json::Error| {
+            ForgeError::InvalidInput(format!("corrupt history entry {}: {}", record.id, e))
+        };
+        let action = serde_json::from_value(serde_json::Value::String(record.action.clone()))
+            .map_err(corrupt)?;
+        let entries = serde_json::from_str(&record.entries).map_err(corrupt)?;
+
+        Ok(Self {
+            id: record.id,
+            action,
+            created_at: record.created_at,
+            entries,
+            undoes: record.undoes,
+            undone_by: record.undone_by,
+            pruned_at: record.pruned_at,
+        })
+    }
+
+    /// Whether `forge undo` without an id would pick this operation
+    fn is_undoable(&self) -> bool {
+        self.undone_by.is_none()
+            && self.undoes.is_none()
+            && self.pruned_at.is_none()
+            && !self.purged()
+    }
+
+    /// When the operation was recorded; the database keeps times in UTC
+    fn recorded_at(&self) -> Option<DateTime<Utc>> {
+        NaiveDateTime::parse_from_str(&self.created_at, "%Y-%m-%d %H:%M:%S")
+            .ok()
+            .map(|time| time.and_utc())
+    }
+
+    /// The backup versions the operation made, which undoing it removes again
+    fn backups(&self) -> impl Iterator<Item = &PathBuf> {
+        self.entries
+            .iter()
+            .flat_map(|entry| &entry.steps)
+            .filter_map(|step| match step {
+                Step::Backup { to, .. } => Some(to),
+                _ => None,
+            })
+    }
+
+    /// Whether the operation deleted files for good, so it cannot be undone
+    fn purged(&self) -> bool {
+        self.entries
+            .iter()
+            .flat_map(|entry| &entry.steps)
+            .any(|step| matches!(step, Step::Purge { .. }))
+    }
+}
+
+impl Engine {
+    /// Recorded operations, newest first
+    pub fn history(&self, limit: Option<usize>) -> Result<Vec<Operation>> {
+        self.config
+            .get_history(limit)?
+            .into_iter()
+            .map(Operation::from_record)
+            .collect()
+    }
+
+    /// The operation `forge undo` would reverse: the one named by `id`, or else the
+    /// most recent one that has not been undone
+    pub fn undo_candidate(&self, id: Option<&str>) -> Result<Operation> {
+        let operation = match id {
+            Some(id) => self
+                .config
+                .find_history(id)?
+                .map(Operation::from_record)
+                .transpose()?
+                .ok_or_else(|| ForgeError::InvalidInput(format!("no operation with id {}", id)))?,
+            None => self
+                .history(None)?
+                .into_iter()
+                .find(Operation::is_undoable)
+                .ok_or_else(|| ForgeError::InvalidInput("nothing to undo".to_string()))?,
+        };
+
+        if let Some(undone_by) = &operation.undone_by {
+            return Err(ForgeError::InvalidInput(format!(
+                "operation {} was already undone by {}",
+                operation.id, undone_by
+            )));
+        }
+        if let Some(pruned_at) = &operation.pruned_at {
+            return Err(ForgeError::InvalidInput(format!(
+                "operation {} cannot be undone: the files it needs were pruned at {}",
+                operation.id, pruned_at
+            )));
+        }
+        if operation.purged() {
+            return Err(ForgeError::InvalidInput(format!(
+                "operation {} deleted files permanently and cannot be undone",
+                operation.id
+            )));
+        }
+        Ok(operation)
+    }
+
+    /// Drop the archives of operations `retention` no longer keeps, or list them with
+    /// `dry_run`
+    ///
+    /// Operations that archived files are ranked newest first, like the versions of one
+    /// path. An operation whose archive is dropped, or whose backups were pruned, is
+    /// marked as pruned so `forge undo` refuses it instead of failing partway.
+    pub(crate) fn prune_history(&self, retention: &Retention, dry_run: bool) -> Result<Report> {
+        let now = Utc::now();
+        let mut index = 0;
+        let mut report = Report::new();
+
+        for operation in self.history(None)? {
+            let archive = self.history_dir().join(&operation.id);
+            let drops = archive.exists()
+                && retention.drops(index, operation.recorded_at().unwrap_or(now), now);
+            if archive.exists() {
+                index += 1;
+            }
+            if !drops {
+                let lost = operation.backups().any(|version| !version.exists());
+                if !dry_run && lost && operation.pruned_at.is_none() {
+                    self.config.mark_history_pruned(&operation.id)?;
+                }
+                continue;
+            }
+
+            // Mark the operation first, so a failed removal never leaves it undoable
+            // with a partial archive
+            let outcome = if dry_run {
+                Outcome::planned(Action::Prune, &archive)
+            } else {
+                match self
+                    .config
+                    .mark_history_pruned(&operation.id)
+                    .map_err(ForgeError::from)
+                    .and_then(|()| {
+                        fs::remove_dir_all(&archive).map_err(|e| {
+                            ForgeError::io(format!("failed to remove {}", archive.display()), e)
+                        })
+                    }) {
+                    Ok(()) => Outcome::done(Action::Prune, &archive),
+                    Err(e) => Outcome::failed(Action::Prune, &archive, e),
+                }
+            };
+            report.push(outcome.with_message(format!("history of {}", operation.id)));
+        }
+        Ok(report)
+    }
+
+    /// Reverse a recorded operation
+    ///
+    /// The reversal runs as a batch of its own: paths the operation created are moved
+    /// into the history, paths it removed are restored from it, and record changes
+    /// are swapped back.
+    pub fn undo(&self, operation: &Operation) -> Result<Report> {
+        self.ensure_no_pending_batch()?;
+
+        let archive = self.history_dir().join(&operation.id);
+        let mut index = 0;
+        let mut entries = Vec::new();
+        for entry in &operation.entries {
+            let mut inverse = Entry::new(&entry.path)
+                .with_message(format!("{} undone", operation.action.as_str()));
+            inverse.target = entry.target.clone();
+            inverse.record = entry.record.as_ref().map(|record| record.inverse());
+
+            let steps: Vec<Step> = entry
+                .steps
+                .iter()
+                .map(|step| {
+                    let inverse = step.inverse(&archive.join(index.to_string()));
+                    index += 1;
+                    inverse
+                })
+                .collect();
+            inverse.steps = steps.into_iter().rev().collect();
+            entries.push(inverse);
+        }
+        entries.reverse();
+
+        self.run_journaled(Action::Undo, entries, Some(operation.id.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::tests::test_engine;
+    use crate::engine::{Action, LinkOptions, Report, Retention, Selection, StageOptions, Status};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+    use std::fs;
+
+    #[test]
+    fn test_undo_delete_restores_file_and_record() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let file = temp.child("home/.bashrc");
+        file.write_str("alias ll='ls -l'\n").unwrap();
+        engine
+            .stage(&[file.path().to_path_buf()], &StageOptions::default())
+            .unwrap();
+        engine.link(&[], &LinkOptions::default()).unwrap();
+
+        let selections: Vec<_> = engine
+            .list(None)
+            .unwrap()
+            .into_iter()
+            .map(Selection::Tracked)
+            .collect();
+        engine.delete(&selections, false).unwrap();
+        assert!(!file.path().exists() && engine.list(None).unwrap().is_empty());
+
+        let report = engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert_eq!(report.count(Status::Done), 1, "{:?}", report);
+        assert!(file.path().is_symlink());
+        assert_eq!(
+            fs::read_to_string(file.path()).unwrap(),
+            "alias ll='ls -l'\n"
+        );
+        assert!(engine.list(None).unwrap()[0].is_linked());
+
+        let history = engine.history(None).unwrap();
+        let actions: Vec<_> = history.iter().map(|op| op.action).collect();
+        assert_eq!(
+            actions,
+            vec![Action::Undo, Action::Delete, Action::Link, Action::Stage]
+        );
+        assert_eq!(history[1].undone_by.as_ref(), Some(&history[0].id));
+
+        // The next undo skips what was already undone and reverses the link
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert!(file.path().is_file() && !file.path().is_symlink());
+        assert!(engine.list(None).unwrap()[0].is_staged());
+        assert!(engine.undo_candidate(Some(&history[1].id)).is_err());
+    }
+
+    #[test]
+    fn test_prune_drops_old_archives_and_refuses_their_undo() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let files = [temp.child("home/.bashrc"), temp.child("home/.vimrc")];
+        for file in &files {
+            file.write_str("set\n").unwrap();
+        }
+        let paths: Vec<_> = files.iter().map(|file| file.to_path_buf()).collect();
+        engine.stage(&paths, &StageOptions::default()).unwrap();
+        engine.link(&[], &LinkOptions::default()).unwrap();
+
+        // Each unlink archives the symlink it replaced
+        for path in &paths {
+            let selections = engine.select(std::slice::from_ref(path)).unwrap();
+            engine.unlink(&selections).unwrap();
+        }
+        let unlinks: Vec<_> = engine
+            .history(None)
+            .unwrap()
+            .into_iter()
+            .filter(|op| op.action == Action::Unlink)
+            .collect();
+        assert_eq!(unlinks.len(), 2);
+        let (newer, older) = (&unlinks[0], &unlinks[1]);
+        assert!(engine.history_dir().join(&older.id).exists());
+
+        let retention = Retention {
+            keep: 1,
+            max_age_days: None,
+        };
+        let planned = |report: &Report, id: &str| {
+            report
+                .outcomes
+                .iter()
+                .any(|outcome| outcome.path == engine.history_dir().join(id))
+        };
+        let report = engine.prune(&retention, true).unwrap();
+        assert!(planned(&report, &older.id) && !planned(&report, &newer.id));
+        assert_eq!(report.count(Status::Planned), report.outcomes.len());
+        assert!(engine.history_dir().join(&older.id).exists());
+
+        let report = engine.prune(&retention, false).unwrap();
+        assert!(!report.has_failures(), "{:?}", report);
+        assert!(!engine.history_dir().join(&older.id).exists());
+        assert!(engine.history_dir().join(&newer.id).exists());
+
+        // The pruned operation is refused up front and skipped by a plain undo
+        let error = engine.undo_candidate(Some(&older.id)).unwrap_err();
+        assert!(error.to_string().contains("pruned"), "{}", error);
+        assert_eq!(engine.undo_candidate(None).unwrap().id, newer.id);
+        let history = engine.history(None).unwrap();
+        assert!(
+            history
+                .iter()
+                .any(|op| op.id == older.id && op.pruned_at.is_some())
+        );
+        assert!(
+            history
+                .iter()
+                .any(|op| op.id == newer.id && op.pruned_at.is_none())
+        );
+    }
+}
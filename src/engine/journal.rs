@@ -0,0 +1,1054 @@
+// Journaled batches of file operations that can be rolled back or resumed
+use super::{Action, Engine, Outcome, Report, Status, atomic, backups, trash};
+use crate::dotfile::DotFile;
+use crate::error::{ForgeError, Result};
+use crate::symlink;
+use crate::utils::path_utils::{escaped, escaped_opt};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const JOURNAL_DIR: &str = "journal";
+const JOURNAL_FILE: &str = "journal.json";
+const BACKUP_DIR: &str = "files";
+const HISTORY_DIR: &str = "history";
+
+/// One reversible filesystem change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Step {
+    /// Copy a file, or a directory and its contents, to a path that is free when the
+    /// step runs, keeping modification times
+    Copy {
+        #[serde(with = "escaped")]
+        from: PathBuf,
+        #[serde(with = "escaped")]
+        to: PathBuf,
+    },
+    /// Create a symlink at `link` pointing to `dest`, written relative to the link's
+    /// directory if `relative` is set
+    Symlink {
+        #[serde(with = "escaped")]
+        link: PathBuf,
+        #[serde(with = "escaped")]
+        dest: PathBuf,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        relative: bool,
+    },
+    /// Move a path aside into the journal; it is archived once the batch commits
+    Remove {
+        #[serde(with = "escaped")]
+        path: PathBuf,
+    },
+    /// Copy an archived path back to a path that is free, keeping symlinks as symlinks
+    Restore {
+        #[serde(with = "escaped")]
+        from: PathBuf,
+        #[serde(with = "escaped")]
+        to: PathBuf,
+    },
+    /// Create a directory at a path that is free when the step runs
+    CreateDir {
+        #[serde(with = "escaped")]
+        path: PathBuf,
+    },
+    /// Store a copy of a file or directory as a new version in the backup store,
+    /// in the version directory `to`
+    Backup {
+        #[serde(with = "escaped")]
+        path: PathBuf,
+        #[serde(with = "escaped")]
+        to: PathBuf,
+    },
+    /// Move a path into the trash as `to`, recording where it came from
+    Trash {
+        #[serde(with = "escaped")]
+        path: PathBuf,
+        #[serde(with = "escaped")]
+        to: PathBuf,
+    },
+    /// Move a trashed path back out of the trash to a path that is free
+    Untrash {
+        #[serde(with = "escaped")]
+        from: PathBuf,
+        #[serde(with = "escaped")]
+        to: PathBuf,
+    },
+    /// Replace the existing `path` with a copy of `from` in one rename, keeping what it
+    /// replaced in the journal like `Remove`
+    ///
+    /// The copy keeps the mode and modification time of `from` and is checked against
+    /// its hash before it is renamed into place.
+    Replace {
+        #[serde(with = "escaped")]
+        path: PathBuf,
+        #[serde(with = "escaped")]
+        from: PathBuf,
+    },
+    /// Replace the existing `link` with a symlink to `dest` in one rename, keeping what
+    /// it replaced in the journal like `Remove`
+    ///
+    /// The symlink is created under a temporary name beside `link` first, so the path
+    /// leads to either the old or the new content throughout.
+    Relink {
+        #[serde(with = "escaped")]
+        link: PathBuf,
+        #[serde(with = "escaped")]
+        dest: PathBuf,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        relative: bool,
+    },
+    /// Replace the existing `link` with a hard link to `dest` in one rename, keeping
+    /// what it replaced in the journal like `Remove`
+    Hardlink {
+        #[serde(with = "escaped")]
+        link: PathBuf,
+        #[serde(with = "escaped")]
+        dest: PathBuf,
+    },
+    /// Move a path aside into the journal like `Remove`, but delete it for good
+    /// instead of archiving it once the batch commits
+    Purge {
+        #[serde(with = "escaped")]
+        path: PathBuf,
+    },
+}
+
+impl Step {
+    /// The step that reverses this one once its batch has committed
+    ///
+    /// `backup` is where the archived copy of a removed path is kept.
+    pub fn inverse(&self, backup: &Path) -> Step {
+        match self {
+            Step::Copy { to: path, .. }
+            | Step::Symlink { link: path, .. }
+            | Step::Restore { to: path, .. }
+            | Step::Backup { to: path, .. }
+            | Step::CreateDir { path } => Step::Remove { path: path.clone() },
+            // Nothing is archived for a purged path, so restoring it fails; the history
+            // refuses to undo operations that purged anything
+            Step::Remove { path } | Step::Purge { path } => Step::Restore {
+                from: backup.to_path_buf(),
+                to: path.clone(),
+            },
+            Step::Replace { path, .. }
+            | Step::Relink { link: path, .. }
+            | Step::Hardlink { link: path, .. } => Step::Replace {
+                path: path.clone(),
+                from: backup.to_path_buf(),
+            },
+            Step::Trash { path, to } => Step::Untrash {
+                from: to.clone(),
+                to: path.clone(),
+            },
+            Step::Untrash { from, to } => Step::Trash {
+                path: to.clone(),
+                to: from.clone(),
+            },
+        }
+    }
+}
+
+/// A tracking record as stored in the database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackedRecord {
+    #[serde(flatten)]
+    pub dotfile: DotFile,
+    pub active: bool,
+}
+
+/// The database change that accompanies an entry's steps, as before and after images
+///
+/// Keeping both sides makes every change reversible by swapping them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordChange {
+    pub before: Option<TrackedRecord>,
+    pub after: Option<TrackedRecord>,
+}
+
+impl RecordChange {
+    /// Start tracking a new record
+    pub fn insert(dotfile: DotFile) -> Self {
+        Self {
+            before: None,
+            after: Some(TrackedRecord {
+                dotfile,
+                active: true,
+            }),
+        }
+    }
+
+    /// Take the place of a record that is no longer needed
+    pub fn replacing(mut self, previous: Option<TrackedRecord>) -> Self {
+        self.before = previous;
+        self
+    }
+
+    /// Replace an active record with an updated copy
+    pub fn update(before: &DotFile, after: DotFile) -> Self {
+        Self {
+            before: Some(TrackedRecord {
+                dotfile: before.clone(),
+                active: true,
+            }),
+            after: Some(TrackedRecord {
+                dotfile: after,
+                active: true,
+            }),
+        }
+    }
+
+    /// Keep a record but mark it as no longer active
+    pub fn deactivate(dotfile: &DotFile) -> Self {
+        Self {
+            before: Some(TrackedRecord {
+                dotfile: dotfile.clone(),
+                active: true,
+            }),
+            after: Some(TrackedRecord {
+                dotfile: dotfile.clone(),
+                active: false,
+            }),
+        }
+    }
+
+    /// Delete an active record
+    pub fn forget(dotfile: &DotFile) -> Self {
+        Self {
+            before: Some(TrackedRecord {
+                dotfile: dotfile.clone(),
+                active: true,
+            }),
+            after: None,
+        }
+    }
+
+    /// The change that reverses this one
+    pub fn inverse(&self) -> Self {
+        Self {
+            before: self.after.clone(),
+            after: self.before.clone(),
+        }
+    }
+}
+
+/// The planned work for one file in a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// The user-facing path, as reported in outcomes
+    #[serde(with = "escaped")]
+    pub path: PathBuf,
+    #[serde(with = "escaped_opt")]
+    pub target: Option<PathBuf>,
+    pub steps: Vec<Step>,
+    pub record: Option<RecordChange>,
+    /// Detail reported once the entry completes
+    pub message: Option<String>,
+}
+
+impl Entry {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            target: None,
+            steps: Vec::new(),
+            record: None,
+            message: None,
+        }
+    }
+
+    pub fn with_target(mut self, target: &Path) -> Self {
+        self.target = Some(target.to_path_buf());
+        self
+    }
+
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn with_record(mut self, record: RecordChange) -> Self {
+        self.record = Some(record);
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// The outcome reported once this entry has been applied
+    fn outcome(&self, action: Action) -> Outcome {
+        let mut outcome = Outcome::done(action, &self.path);
+        outcome.target = self.target.clone();
+        outcome.message = self.message.clone();
+        outcome.mode = self
+            .record
+            .as_ref()
+            .and_then(|record| record.after.as_ref())
+            .map(|after| after.dotfile.mode);
+        outcome
+    }
+}
+
+/// An in-flight batch, persisted so an interrupted run can be recovered
+///
+/// The journal is written before anything is touched. Removed paths are moved aside
+/// into its directory rather than deleted, so every step can be undone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Journal {
+    pub id: String,
+    pub action: Action,
+    pub entries: Vec<Entry>,
+    /// Number of steps, counted across entries in order, that have completed
+    pub completed: usize,
+    /// The operation this batch reverses, when it was started by `forge undo`
+    #[serde(default)]
+    pub undoes: Option<String>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl Journal {
+    /// Load the pending journal in `dir`, if there is one
+    pub fn load(dir: &Path) -> Result<Option<Journal>> {
+        let path = dir.join(JOURNAL_FILE);
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(ForgeError::io(
+                    format!("failed to read journal {}", path.display()),
+                    e,
+                ));
+            }
+        };
+        let mut journal: Journal = serde_json::from_str(&json).map_err(|e| {
+            ForgeError::InvalidInput(format!("corrupt journal {}: {}", path.display(), e))
+        })?;
+        journal.dir = dir.to_path_buf();
+        Ok(Some(journal))
+    }
+
+    /// Write a new journal to `dir`, refusing if another batch is still pending
+    pub fn begin(
+        dir: &Path,
+        action: Action,
+        entries: Vec<Entry>,
+        undoes: Option<String>,
+    ) -> Result<Journal> {
+        if let Some(pending) = Journal::load(dir)? {
+            return Err(ForgeError::RecoveryNeeded(
+                pending.action.as_str().to_string(),
+            ));
+        }
+
+        fs::create_dir_all(dir.join(BACKUP_DIR)).map_err(|e| {
+            ForgeError::io(
+                format!("failed to create journal directory {}", dir.display()),
+                e,
+            )
+        })?;
+
+        let journal = Journal {
+            id: format!(
+                "{}-{}",
+                chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f"),
+                std::process::id()
+            ),
+            action,
+            entries,
+            completed: 0,
+            undoes,
+            dir: dir.to_path_buf(),
+        };
+        journal.save()?;
+        Ok(journal)
+    }
+
+    /// Persist the journal, replacing the previous copy atomically
+    fn save(&self) -> Result<()> {
+        let path = self.dir.join(JOURNAL_FILE);
+        let temp = self.dir.join(format!("{}.tmp", JOURNAL_FILE));
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| ForgeError::InvalidInput(format!("failed to encode journal: {}", e)))?;
+
+        let write = || -> io::Result<()> {
+            let mut file = fs::File::create(&temp)?;
+            io::Write::write_all(&mut file, json.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&temp, &path)
+        };
+        write()
+            .map_err(|e| ForgeError::io(format!("failed to write journal {}", path.display()), e))
+    }
+
+    /// Every step with its position in the batch and the entry it belongs to
+    pub(crate) fn steps(&self) -> impl Iterator<Item = (usize, usize, &Step)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .flat_map(|(entry, e)| e.steps.iter().map(move |step| (entry, step)))
+            .enumerate()
+            .map(|(index, (entry, step))| (index, entry, step))
+    }
+
+    /// Where a path removed by the step at `index` is kept until commit
+    fn backup_path(&self, index: usize) -> PathBuf {
+        self.dir.join(BACKUP_DIR).join(index.to_string())
+    }
+
+    /// Undo every step that may have run, newest first
+    ///
+    /// The step after the last completed one is included because it may have been
+    /// interrupted partway; undoing a step that never ran does nothing.
+    fn undo(&self) -> Result<()> {
+        let steps: Vec<_> = self.steps().take(self.completed + 1).collect();
+        for (index, _, step) in steps.into_iter().rev() {
+            undo_step(step, &self.backup_path(index)).map_err(|e| {
+                ForgeError::io(format!("failed to roll back {}", describe(step)), e)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Delete the journal and everything moved aside into it
+    fn finish(self) -> Result<()> {
+        fs::remove_dir_all(&self.dir).map_err(|e| {
+            ForgeError::io(
+                format!("failed to remove journal {}", self.dir.display()),
+                e,
+            )
+        })
+    }
+
+    /// Keep everything moved aside under `history/<id>`, where `forge undo` can put it
+    /// back, then delete the journal
+    ///
+    /// Purged paths are deleted here instead of being kept.
+    fn archive(self, history_dir: &Path) -> Result<()> {
+        for (index, _, step) in self.steps() {
+            let backup = self.backup_path(index);
+            if let Step::Purge { path } = step
+                && occupied(&backup)
+            {
+                remove_path(&backup).map_err(|e| {
+                    ForgeError::io(format!("failed to delete {}", path.display()), e)
+                })?;
+            }
+        }
+        let files = self.dir.join(BACKUP_DIR);
+        let kept = fs::read_dir(&files).is_ok_and(|mut entries| entries.next().is_some());
+        if kept {
+            let dest = history_dir.join(&self.id);
+            move_path(&files, &dest).map_err(|e| {
+                ForgeError::io(
+                    format!("failed to archive backups to {}", dest.display()),
+                    e,
+                )
+            })?;
+        }
+        self.finish()
+    }
+}
+
+impl Engine {
+    fn journal_dir(&self) -> PathBuf {
+        self.config.get_config_dir().join(JOURNAL_DIR)
+    }
+
+    /// Where committed batches keep the paths they removed
+    pub(crate) fn history_dir(&self) -> PathBuf {
+        self.config.get_config_dir().join(HISTORY_DIR)
+    }
+
+    /// The interrupted batch waiting for `forge recover`, if any
+    pub fn pending_journal(&self) -> Result<Option<Journal>> {
+        Journal::load(&self.journal_dir())
+    }
+
+    /// Fail if an interrupted batch has to be recovered before anything else runs
+    pub(crate) fn ensure_no_pending_batch(&self) -> Result<()> {
+        match self.pending_journal()? {
+            Some(pending) => Err(ForgeError::RecoveryNeeded(
+                pending.action.as_str().to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Run planned entries as one batch that either completes or is rolled back
+    ///
+    /// The database changes of the whole batch run in one transaction that also records
+    /// the batch in the operation history, which tells `forge recover` whether an
+    /// interrupted batch reached its commit.
+    pub(crate) fn run_batch(&self, action: Action, entries: Vec<Entry>) -> Result<Report> {
+        self.run_journaled(action, entries, None)
+    }
+
+    /// Run a batch, noting the operation it reverses in the history
+    pub(crate) fn run_journaled(
+        &self,
+        action: Action,
+        entries: Vec<Entry>,
+        undoes: Option<String>,
+    ) -> Result<Report> {
+        if entries.is_empty() {
+            return Ok(Report::new());
+        }
+
+        let makes_backups = entries.iter().any(|entry| {
+            entry
+                .steps
+                .iter()
+                .any(|step| matches!(step, Step::Backup { .. }))
+        });
+        let mut journal = Journal::begin(&self.journal_dir(), action, entries, undoes)?;
+        if let Err(e) = self.config.begin_transaction() {
+            journal.finish()?;
+            return Err(e.into());
+        }
+        let report = self.execute(&mut journal)?;
+
+        // Pruning is housekeeping: failing to prune must not fail a committed batch
+        let keeps_files = makes_backups || self.history_dir().join(&journal.id).exists();
+        if keeps_files && let Ok(retention) = self.retention() {
+            let _ = self.prune(&retention, false);
+        }
+        Ok(report)
+    }
+
+    /// Apply the journal's remaining steps and every record change, then commit
+    fn execute(&self, journal: &mut Journal) -> Result<Report> {
+        match self.apply(journal).and_then(|()| self.commit(journal)) {
+            Ok(()) => {
+                let report = journal
+                    .entries
+                    .iter()
+                    .map(|entry| entry.outcome(journal.action))
+                    .collect();
+                journal.clone().archive(&self.history_dir())?;
+                Ok(report)
+            }
+            Err((failed, error)) => Ok(self.roll_back(journal, failed, error)),
+        }
+    }
+
+    /// Apply steps from `journal.completed` onwards, plus every record change
+    ///
+    /// On failure, returns the index of the entry that failed.
+    fn apply(&self, journal: &mut Journal) -> std::result::Result<(), (Option<usize>, ForgeError)> {
+        let mut index = 0;
+        for entry_index in 0..journal.entries.len() {
+            let entry = journal.entries[entry_index].clone();
+            let fail = |e| (Some(entry_index), e);
+
+            for step in &entry.steps {
+                if index >= journal.completed {
+                    apply_step(step, &journal.backup_path(index)).map_err(|e| {
+                        fail(ForgeError::io(format!("failed to {}", describe(step)), e))
+                    })?;
+                    journal.completed = index + 1;
+                    journal.save().map_err(fail)?;
+                }
+                index += 1;
+            }
+
+            if let Some(record) = &entry.record {
+                self.apply_record(record).map_err(fail)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the record's before image with its after image
+    fn apply_record(&self, record: &RecordChange) -> Result<()> {
+        if let Some(before) = &record.before
+            && !self
+                .config
+                .delete_dotfile_record(&before.dotfile, before.active)?
+        {
+            return Err(ForgeError::NotTracked(before.dotfile.target.clone()));
+        }
+        if let Some(after) = &record.after {
+            self.config
+                .insert_dotfile_record(&after.dotfile, after.active)?;
+        }
+        Ok(())
+    }
+
+    /// Record the batch in the history and commit its transaction
+    fn commit(&self, journal: &Journal) -> std::result::Result<(), (Option<usize>, ForgeError)> {
+        let entries = serde_json::to_string(&journal.entries).map_err(|e| {
+            (
+                None,
+                ForgeError::InvalidInput(format!("failed to encode history: {}", e)),
+            )
+        })?;
+        self.config
+            .record_history(
+                &journal.id,
+                journal.action.as_str(),
+                &entries,
+                journal.undoes.as_deref(),
+            )
+            .and_then(|()| self.config.commit_transaction())
+            .map_err(|e| (None, e.into()))
+    }
+
+    /// Undo a failed batch, reporting the failure against the entry that caused it
+    fn roll_back(&self, journal: &Journal, failed: Option<usize>, error: ForgeError) -> Report {
+        let _ = self.config.rollback_transaction();
+        let undo = journal.undo();
+
+        let cause = failed
+            .map(|i| journal.entries[i].path.display().to_string())
+            .unwrap_or_else(|| "the database commit".to_string());
+        let mut report = Report::new();
+        for (index, entry) in journal.entries.iter().enumerate() {
+            let mut outcome = if failed.is_none_or(|i| i == index) {
+                let mut outcome = Outcome::new(journal.action, Status::Failed, &entry.path)
+                    .with_message(error.to_string());
+                outcome.error = Some(error.kind());
+                outcome
+            } else {
+                Outcome::skipped(
+                    journal.action,
+                    &entry.path,
+                    format!("rolled back because {} failed", cause),
+                )
+            };
+            outcome.target = entry.target.clone();
+            report.push(outcome);
+        }
+
+        match undo.and_then(|()| journal.clone().finish()) {
+            Ok(()) => report,
+            Err(e) => {
+                // Leave the journal in place so `forge recover` can finish the job
+                if let Some(outcome) = report.outcomes.iter_mut().find(|o| o.is_failed()) {
+                    outcome.message = Some(format!(
+                        "{}; rollback incomplete ({}), run 'forge recover'",
+                        outcome.message.as_deref().unwrap_or_default(),
+                        e
+                    ));
+                }
+                report
+            }
+        }
+    }
+
+    /// Finish or undo an interrupted batch
+    ///
+    /// A batch that reached its commit is completed. Otherwise its steps are rolled
+    /// back, or with `resume` the remaining steps are applied and the batch committed.
+    pub fn recover(&self, resume: bool) -> Result<Report> {
+        let Some(mut journal) = self.pending_journal()? else {
+            return Ok(Report::new());
+        };
+
+        if self.config.is_batch_committed(&journal.id)? {
+            let report = journal
+                .entries
+                .iter()
+                .map(|entry| {
+                    entry
+                        .outcome(journal.action)
+                        .with_message("completed before the interruption")
+                })
+                .collect();
+            journal.archive(&self.history_dir())?;
+            return Ok(report);
+        }
+
+        if resume {
+            // Clear away whatever the interrupted step left behind, then carry on
+            if let Some((index, _, step)) = journal.steps().nth(journal.completed) {
+                undo_step(step, &journal.backup_path(index)).map_err(|e| {
+                    ForgeError::io(format!("failed to roll back {}", describe(step)), e)
+                })?;
+            }
+            self.config.begin_transaction()?;
+            return self.execute(&mut journal);
+        }
+
+        journal.undo()?;
+        let report = journal
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut outcome = Outcome::done(Action::Rollback, &entry.path);
+                outcome.target = entry.target.clone();
+                outcome.with_message(format!("{} undone", journal.action.as_str()))
+            })
+            .collect();
+        journal.finish()?;
+        Ok(report)
+    }
+}
+
+/// Short description of a step for error messages
+fn describe(step: &Step) -> String {
+    match step {
+        Step::Copy { from, to } => format!("copy {} to {}", from.display(), to.display()),
+        Step::Symlink { link, dest, .. } => {
+            format!("create symlink {} → {}", link.display(), dest.display())
+        }
+        Step::Remove { path } => format!("remove {}", path.display()),
+        Step::Restore { from, to } => format!("restore {} from {}", to.display(), from.display()),
+        Step::CreateDir { path } => format!("create directory {}", path.display()),
+        Step::Backup { path, to } => format!("back up {} to {}", path.display(), to.display()),
+        Step::Trash { path, to } => {
+            format!("move {} to the trash as {}", path.display(), to.display())
+        }
+        Step::Untrash { from, to } => format!(
+            "take {} out of the trash to {}",
+            from.display(),
+            to.display()
+        ),
+        Step::Purge { path } => format!("delete {}", path.display()),
+        Step::Replace { path, from } => format!(
+            "replace {} with a copy of {}",
+            path.display(),
+            from.display()
+        ),
+        Step::Relink { link, dest, .. } => format!(
+            "replace {} with a symlink to {}",
+            link.display(),
+            dest.display()
+        ),
+        Step::Hardlink { link, dest } => format!(
+            "replace {} with a hard link to {}",
+            link.display(),
+            dest.display()
+        ),
+    }
+}
+
+/// Whether anything, including a dangling symlink, exists at `path`
+fn occupied(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+fn create_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// Fail unless `path` is free, so a step never overwrites anything
+fn ensure_free(path: &Path) -> io::Result<()> {
+    if occupied(path) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+fn apply_step(step: &Step, backup: &Path) -> io::Result<()> {
+    match step {
+        Step::Copy { from, to } => {
+            ensure_free(to)?;
+            create_parent(to)?;
+            if from.is_dir() {
+                copy_tree(from, to)
+            } else {
+                atomic::copy_file(from, to)
+            }
+        }
+        Step::Symlink {
+            link,
+            dest,
+            relative,
+        } => {
+            create_parent(link)?;
+            if *relative {
+                symlink::create_relative_symlink(dest, link)
+            } else {
+                symlink::create_symlink(dest, link)
+            }
+        }
+        Step::Remove { path } | Step::Purge { path } => move_path(path, backup),
+        Step::Restore { from, to } => {
+            ensure_free(to)?;
+            create_parent(to)?;
+            let file_type = fs::symlink_metadata(from)?.file_type();
+            if file_type.is_symlink() {
+                symlink::create_symlink(fs::read_link(from)?, to)
+            } else if file_type.is_dir() {
+                copy_tree(from, to)
+            } else {
+                atomic::copy_file(from, to)
+            }
+        }
+        Step::CreateDir { path } => {
+            ensure_free(path)?;
+            fs::create_dir_all(path)
+        }
+        Step::Backup { path, to } => {
+            ensure_free(to)?;
+            backups::store(path, to)
+        }
+        Step::Trash { path, to } => {
+            ensure_free(to)?;
+            trash::trash(path, to)
+        }
+        Step::Replace { path, from } => {
+            let temp = atomic::temp_path(path);
+            atomic::discard_temp(path)?;
+            atomic::copy_verified(from, &temp)?;
+            // A resumed step may already have kept the path
+            if !occupied(backup) {
+                atomic::keep(path, backup)?;
+            }
+            atomic::rename_over(&temp, path)
+        }
+        Step::Relink {
+            link,
+            dest,
+            relative,
+        } => {
+            let temp = atomic::temp_path(link);
+            atomic::discard_temp(link)?;
+            if *relative {
+                symlink::create_relative_symlink(dest, &temp)?;
+            } else {
+                symlink::create_symlink(dest, &temp)?;
+            }
+            if !occupied(backup) {
+                atomic::keep(link, backup)?;
+            }
+            atomic::rename_over(&temp, link)
+        }
+        Step::Hardlink { link, dest } => {
+            let temp = atomic::temp_path(link);
+            atomic::discard_temp(link)?;
+            fs::hard_link(dest, &temp)?;
+            if !occupied(backup) {
+                atomic::keep(link, backup)?;
+            }
+            atomic::rename_over(&temp, link)?;
+            // Renaming over another name for the same file does nothing, which leaves
+            // the temporary name behind when a resumed step already linked `link`
+            atomic::discard_temp(link)
+        }
+        Step::Untrash { from, to } => {
+            ensure_free(to)?;
+            trash::restore(from, to)
+        }
+    }
+}
+
+/// Reverse a step; safe to call on a step that never ran or only partly ran
+fn undo_step(step: &Step, backup: &Path) -> io::Result<()> {
+    match step {
+        Step::Copy { to, .. } | Step::Restore { to, .. } | Step::Backup { to, .. }
+            if occupied(to) =>
+        {
+            remove_path(to)
+        }
+        Step::Symlink { link, dest, .. } if symlink::points_to(link, dest) => fs::remove_file(link),
+        Step::Remove { path } | Step::Purge { path } if occupied(backup) && !occupied(path) => {
+            move_path(backup, path)
+        }
+        Step::CreateDir { path } if path.is_dir() => fs::remove_dir(path),
+        // Putting the kept path back is harmless if the rename never happened
+        Step::Replace { path, .. }
+        | Step::Relink { link: path, .. }
+        | Step::Hardlink { link: path, .. } => {
+            atomic::discard_temp(path)?;
+            if occupied(backup) {
+                atomic::rename_over(backup, path)?;
+            }
+            Ok(())
+        }
+        Step::Trash { path, to } if occupied(to) && !occupied(path) => trash::restore(to, path),
+        // The info file may have been written before the move failed
+        Step::Trash { to, .. } if !occupied(to) => trash::forget(to),
+        Step::Untrash { from, to } if occupied(to) && !occupied(from) => trash::trash(to, from),
+        _ => Ok(()),
+    }
+}
+
+/// Move a file, directory or symlink, copying when a rename would cross filesystems
+pub(super) fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    create_parent(to)?;
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let file_type = fs::symlink_metadata(from)?.file_type();
+            if file_type.is_symlink() {
+                symlink::create_symlink(fs::read_link(from)?, to)?;
+            } else if file_type.is_dir() {
+                copy_tree(from, to)?;
+            } else if file_type.is_file() {
+                atomic::copy_file(from, to)?;
+            } else {
+                return Err(e);
+            }
+            remove_path(from)
+        }
+        result => result,
+    }
+}
+
+/// Copy a directory and its contents, keeping symlinks inside it as symlinks and the
+/// modification times of its files
+pub(super) fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in walkdir::WalkDir::new(from) {
+        let entry = entry.map_err(io::Error::other)?;
+        let path = to.join(entry.path().strip_prefix(from).map_err(io::Error::other)?);
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            fs::create_dir(&path)?;
+        } else if file_type.is_symlink() {
+            symlink::create_symlink(fs::read_link(entry.path())?, &path)?;
+        } else {
+            atomic::copy_file(entry.path(), &path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove a file, symlink or directory with its contents
+fn remove_path(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::folder::LinkStyle;
+    use crate::engine::tests::test_engine;
+    use crate::engine::{LinkOptions, StageOptions};
+    use crate::error::ErrorKind;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    /// Stage two files and return the engine with their paths
+    fn staged_pair(temp: &TempDir) -> (Engine, PathBuf, PathBuf) {
+        let engine = test_engine(temp);
+        let bashrc = temp.child("home/.bashrc");
+        bashrc.write_str("bash\n").unwrap();
+        let vimrc = temp.child("home/.vimrc");
+        vimrc.write_str("vim\n").unwrap();
+        let files = vec![bashrc.path().to_path_buf(), vimrc.path().to_path_buf()];
+        engine.stage(&files, &StageOptions::default()).unwrap();
+        (engine, files[0].clone(), files[1].clone())
+    }
+
+    fn assert_still_staged(engine: &Engine, files: &[&Path]) {
+        for file in files {
+            assert!(file.is_file() && !file.is_symlink(), "{}", file.display());
+        }
+        assert!(engine.list(None).unwrap().iter().all(|d| d.is_staged()));
+        assert!(engine.pending_journal().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_failed_batch_rolls_back() {
+        let temp = TempDir::new().unwrap();
+        let (engine, bashrc, vimrc) = staged_pair(&temp);
+
+        // The second file disappears after planning, so its copy step fails
+        let resolver = engine.resolver(None).unwrap();
+        let entries: Vec<_> = engine
+            .list(None)
+            .unwrap()
+            .iter()
+            .map(|d| super::super::plan_link(d, LinkStyle::Absolute, &resolver).unwrap())
+            .collect();
+        fs::remove_file(&vimrc).unwrap();
+        engine.config.begin_transaction().unwrap();
+        let mut journal =
+            Journal::begin(&engine.journal_dir(), Action::Link, entries, None).unwrap();
+        let report = engine.execute(&mut journal).unwrap();
+
+        assert_eq!(report.failed(), 1);
+        assert_eq!(report.count(Status::Skipped), 1);
+        assert_still_staged(&engine, &[&bashrc]);
+        assert_eq!(fs::read_to_string(&bashrc).unwrap(), "bash\n");
+        assert!(temp.path().join("forge/.bashrc").is_symlink());
+    }
+
+    /// Start a link batch and stop it after `steps` steps, as a crash would
+    fn interrupt_link(engine: &Engine, steps: usize) {
+        let resolver = engine.resolver(None).unwrap();
+        let entries: Vec<_> = engine
+            .list(None)
+            .unwrap()
+            .iter()
+            .map(|d| super::super::plan_link(d, LinkStyle::Absolute, &resolver).unwrap())
+            .collect();
+        let mut journal =
+            Journal::begin(&engine.journal_dir(), Action::Link, entries, None).unwrap();
+        let planned: Vec<_> = journal
+            .steps()
+            .take(steps)
+            .map(|(index, _, step)| (journal.backup_path(index), step.clone()))
+            .collect();
+        for (backup, step) in planned {
+            apply_step(&step, &backup).unwrap();
+            journal.completed += 1;
+        }
+        journal.save().unwrap();
+    }
+
+    #[test]
+    fn test_recover_rolls_back_interrupted_batch() {
+        let temp = TempDir::new().unwrap();
+        let (engine, bashrc, vimrc) = staged_pair(&temp);
+        interrupt_link(&engine, 5);
+
+        let err = engine.link(&[], &LinkOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::RecoveryNeeded);
+
+        let report = engine.recover(false).unwrap();
+        assert_eq!(report.count(Status::Done), 2);
+        assert_still_staged(&engine, &[&bashrc, &vimrc]);
+        assert_eq!(fs::read_to_string(&vimrc).unwrap(), "vim\n");
+        assert!(engine.recover(false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recover_resumes_interrupted_batch() {
+        let temp = TempDir::new().unwrap();
+        let (engine, bashrc, vimrc) = staged_pair(&temp);
+        interrupt_link(&engine, 4);
+
+        let report = engine.recover(true).unwrap();
+        assert_eq!(report.count(Status::Done), 2, "{:?}", report);
+        assert!(bashrc.is_symlink() && vimrc.is_symlink());
+        assert_eq!(fs::read_to_string(&vimrc).unwrap(), "vim\n");
+        assert!(engine.list(None).unwrap().iter().all(|d| d.is_linked()));
+        assert!(engine.pending_journal().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_relinking_never_leaves_the_path_missing() {
+        let temp = TempDir::new().unwrap();
+        let (engine, bashrc, _) = staged_pair(&temp);
+
+        // Right after the first file's relink, the path already leads to the forge copy
+        interrupt_link(&engine, 3);
+        assert!(symlink::points_to(
+            &bashrc,
+            temp.path().join("forge/.bashrc")
+        ));
+
+        // A relink interrupted before its rename leaves only its temporary symlink
+        let leftover = atomic::temp_path(&bashrc);
+        symlink::create_symlink(temp.path().join("forge/.bashrc"), &leftover).unwrap();
+        engine.recover(false).unwrap();
+        assert!(!bashrc.is_symlink());
+        assert_eq!(fs::read_to_string(&bashrc).unwrap(), "bash\n");
+        assert!(fs::symlink_metadata(&leftover).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+// Where staged files are placed inside a managed folder
+pub use crate::config::folder::Layout;
+use crate::error::{ForgeError, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// Maps the files being staged to their place in a managed folder
+#[derive(Debug, Clone)]
+pub struct Placer<'a> {
+    layout: Layout,
+    package: Option<&'a str>,
+    home: &'a Path,
+    folder: &'a Path,
+}
+
+impl<'a> Placer<'a> {
+    /// Check that the layout has what it needs, such as a valid package name
+    pub fn new(
+        layout: Layout,
+        package: Option<&'a str>,
+        home: &'a Path,
+        folder: &'a Path,
+    ) -> Result<Self> {
+        if layout == Layout::Package {
+            let Some(name) = package else {
+                return Err(ForgeError::InvalidInput(
+                    "the package layout needs a package name (--package NAME)".to_string(),
+                ));
+            };
+            let mut components = Path::new(name).components();
+            if !matches!(
+                (components.next(), components.next()),
+                (Some(Component::Normal(_)), None)
+            ) {
+                return Err(ForgeError::InvalidInput(format!(
+                    "invalid package name '{}'",
+                    name
+                )));
+            }
+        }
+
+        Ok(Self {
+            layout,
+            package,
+            home,
+            folder,
+        })
+    }
+
+    /// The target in the managed folder for `source`
+    ///
+    /// `root` is the directory being staged recursively that `source` was found in;
+    /// the flat layout keeps a staged directory's contents together under its name.
+    pub fn target(&self, source: &Path, root: Option<&Path>) -> Result<PathBuf> {
+        match self.layout {
+            Layout::Flat => {
+                let relative = match root {
+                    Some(root) => {
+                        let name = root.file_name().unwrap_or_default();
+                        Path::new(name).join(source.strip_prefix(root).unwrap_or(source))
+                    }
+                    None => PathBuf::from(source.file_name().ok_or_else(|| {
+                        ForgeError::InvalidInput(format!("invalid file path: {}", source.display()))
+                    })?),
+                };
+                Ok(self.folder.join(relative))
+            }
+            Layout::Home => Ok(self.folder.join(self.home_relative(source)?)),
+            Layout::Package => Ok(self
+                .folder
+                .join(self.package.unwrap_or_default())
+                .join(self.home_relative(source)?)),
+        }
+    }
+
+    fn home_relative(&self, source: &Path) -> Result<PathBuf> {
+        match source.strip_prefix(self.home) {
+            Ok(relative) if relative.components().next().is_some() => Ok(relative.to_path_buf()),
+            _ => Err(ForgeError::InvalidInput(format!(
+                "{} is outside the home directory {}; stage it with --layout flat",
+                source.display(),
+                self.home.display()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_places_files_by_layout() {
+        let home = Path::new("/home/ada");
+        let folder = Path::new("/home/ada/dotfiles");
+        let git = home.join(".config/git/config");
+        let ssh = home.join(".ssh/config");
+
+        let placer = Placer::new(Layout::Home, None, home, folder).unwrap();
+        assert_eq!(
+            placer.target(&git, None).unwrap(),
+            folder.join(".config/git/config")
+        );
+        assert_eq!(
+            placer.target(&ssh, None).unwrap(),
+            folder.join(".ssh/config")
+        );
+        assert!(placer.target(Path::new("/etc/hosts"), None).is_err());
+
+        let placer = Placer::new(Layout::Package, Some("git"), home, folder).unwrap();
+        assert_eq!(
+            placer.target(&git, None).unwrap(),
+            folder.join("git/.config/git/config")
+        );
+
+        let placer = Placer::new(Layout::Flat, None, home, folder).unwrap();
+        assert_eq!(placer.target(&git, None).unwrap(), folder.join("config"));
+        assert_eq!(
+            placer.target(&git, Some(&home.join(".config"))).unwrap(),
+            folder.join(".config/git/config")
+        );
+
+        assert!(Placer::new(Layout::Package, None, home, folder).is_err());
+        assert!(Placer::new(Layout::Package, Some("../up"), home, folder).is_err());
+    }
+}
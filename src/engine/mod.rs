@@ -0,0 +1,1953 @@
+// Typed engine API over the forge database and managed folders
+pub mod atomic;
+pub mod backups;
+pub mod conflict;
+pub mod doctor;
+pub mod hardlink;
+pub mod history;
+pub mod journal;
+pub mod layout;
+pub mod reindex;
+pub mod report;
+pub mod status;
+pub mod stow;
+pub mod sync;
+pub mod template;
+pub mod trash;
+
+pub use backups::{Backup, Retention};
+pub use conflict::Conflict;
+pub use doctor::{Diagnosis, Repair};
+pub use history::Operation;
+pub use journal::Journal;
+pub use layout::{Layout, Placer};
+pub use report::{Action, Outcome, Report, Status};
+pub use status::{FileStatus, Health};
+pub use stow::{IgnoreList, ImportOptions, StowOptions};
+pub use template::Rendered;
+
+use crate::config::Config;
+use crate::config::folder::{ConflictPolicy, DeployMode, FolderConfig, LinkStyle};
+use crate::dotfile::{DotFile, DotFileStatus};
+use crate::error::{ForgeError, Result};
+use crate::pack;
+use crate::symlink;
+use crate::utils::path_utils;
+use conflict::Resolver;
+use journal::{Entry, RecordChange, Step, TrackedRecord};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options for staging files and directories
+#[derive(Debug, Clone, Default)]
+pub struct StageOptions {
+    /// Process directories recursively (all levels)
+    pub recursive: bool,
+    /// Maximum recursion depth for directories (overrides `recursive`)
+    pub depth: Option<usize>,
+    /// Layout to place files with instead of the managed folder's configured one
+    pub layout: Option<Layout>,
+    /// Package directory for the package layout; implies it when no layout is given
+    pub package: Option<String>,
+    /// How to deploy the files once they are linked, instead of the managed folder's
+    /// settings for them
+    pub mode: Option<DeployMode>,
+}
+
+/// Options for linking staged files
+#[derive(Debug, Clone, Default)]
+pub struct LinkOptions {
+    /// How to write the symlinks instead of the managed folder's configured style
+    pub style: Option<LinkStyle>,
+    /// How to resolve a managed copy that differs from the file being linked,
+    /// instead of the managed folder's configured policy
+    pub on_conflict: Option<ConflictPolicy>,
+}
+
+/// A path named on the command line, resolved against the database
+#[derive(Debug, Clone)]
+pub enum Selection {
+    /// The path has a tracking record
+    Tracked(DotFile),
+    /// No tracking record exists for the path
+    Untracked(PathBuf),
+}
+
+/// Drives forge operations and reports their results instead of printing them
+pub struct Engine {
+    config: Config,
+    prompt: Option<conflict::Prompt>,
+}
+
+impl Engine {
+    /// Open the engine with the default configuration locations
+    pub fn open() -> Result<Self> {
+        Self::with_config(Config::new())
+    }
+
+    /// Open the engine with an explicit configuration, connecting it if needed
+    pub fn with_config(mut config: Config) -> Result<Self> {
+        if !config.is_connected() {
+            config.connect()?;
+        }
+        Ok(Self {
+            config,
+            prompt: None,
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Get the active managed folder, failing if none has been initialized
+    pub fn active_folder(&self) -> Result<(String, PathBuf)> {
+        self.config
+            .get_active_managed_folder()
+            .map_err(|e| ForgeError::io("failed to read managed folders", e))?
+            .ok_or(ForgeError::NoManagedFolder)
+    }
+
+    /// List tracked dotfiles, optionally restricted to a profile
+    pub fn list(&self, profile: Option<&str>) -> Result<Vec<DotFile>> {
+        Ok(self.config.get_dotfiles(profile)?)
+    }
+
+    /// Stage files or directories for tracking
+    ///
+    /// Files are placed in the managed folder according to the layout in `options`,
+    /// or else the folder's configured layout, and recorded with the deployment mode in
+    /// `options`, or else the folder's settings for their target. Every target is worked out before
+    /// anything is written, so collisions are reported up front. A symlink that already
+    /// leads into a managed folder is adopted as linked instead. The files are then
+    /// staged as one batch: if any of them fails, all are rolled back.
+    pub fn stage(&self, files: &[PathBuf], options: &StageOptions) -> Result<Report> {
+        let (_, forge_path) = self.active_folder()?;
+        self.ensure_no_pending_batch()?;
+
+        let settings = FolderConfig::load(&forge_path)?;
+        let layout = match (options.layout, &options.package) {
+            (Some(layout), _) => layout,
+            (None, Some(_)) => Layout::Package,
+            (None, None) => settings.layout,
+        };
+        let package = options.package.as_deref().or(settings.package.as_deref());
+        let placer = Placer::new(layout, package, self.config.get_home_dir(), &forge_path)?;
+
+        if !forge_path.exists() {
+            fs::create_dir_all(&forge_path).map_err(|e| {
+                ForgeError::io(
+                    format!("failed to create forge directory {}", forge_path.display()),
+                    e,
+                )
+            })?;
+        }
+
+        // Outcomes in the order the files were named; `None` marks a batch entry
+        let mut outcomes = Vec::new();
+        let mut entries = Vec::new();
+        // Targets claimed by earlier entries in this batch, with the file claiming each
+        let mut claimed: HashMap<PathBuf, PathBuf> = HashMap::new();
+
+        let folders: Vec<PathBuf> = self
+            .config
+            .get_managed_folders()
+            .map_err(|e| ForgeError::io("failed to read managed folders", e))?
+            .into_iter()
+            .map(|(_, path)| path_utils::clean(&path))
+            .collect();
+
+        // Process each file or directory
+        for file in files {
+            // Normalize path
+            let abs_source = path_utils::normalize(file);
+
+            // A symlink is adopted if it already leads into a managed folder, and is
+            // never staged behind another symlink
+            if symlink::is_symlink(&abs_source) {
+                let planned = match symlink::follow_chain(&abs_source) {
+                    Ok((_, end)) if claimed.contains_key(&end) => {
+                        Err(collision(&abs_source, &end, &claimed[&end]))
+                    }
+                    Ok((hops, end)) => {
+                        let existing = self.config.find_record_by_target(&end)?;
+                        let planned = plan_adopt(
+                            &abs_source,
+                            hops.len(),
+                            &end,
+                            &folders,
+                            settings.links,
+                            existing,
+                        );
+                        if planned.is_ok() {
+                            claimed.insert(end, abs_source.clone());
+                        }
+                        planned
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::InvalidData => Err(Outcome::failed(
+                        Action::Stage,
+                        &abs_source,
+                        ForgeError::InvalidInput(e.to_string()),
+                    )),
+                    Err(e) => Err(Outcome::failed(
+                        Action::Stage,
+                        &abs_source,
+                        ForgeError::io("failed to follow symlink", e),
+                    )),
+                };
+                match planned {
+                    Ok(entry) => {
+                        entries.push(entry);
+                        outcomes.push(None);
+                    }
+                    Err(outcome) => outcomes.push(Some(outcome)),
+                }
+                continue;
+            }
+
+            if !abs_source.exists() {
+                outcomes.push(Some(Outcome::failed(
+                    Action::Stage,
+                    &abs_source,
+                    ForgeError::NotFound(abs_source.clone()),
+                )));
+                continue;
+            }
+
+            // Each file to stage, with its target and whether it is a directory
+            let mut planned = Vec::new();
+            if abs_source.is_dir() && (options.recursive || options.depth.is_some()) {
+                // Unlimited depth for recursive mode
+                let walkdir_depth = options.depth.unwrap_or(usize::MAX);
+
+                // Use walkdir to recursively process directory
+                for entry in walkdir::WalkDir::new(&abs_source)
+                    .min_depth(1) // Skip the root dir itself
+                    .max_depth(walkdir_depth)
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.path().is_file())
+                {
+                    let target = placer.target(entry.path(), Some(&abs_source));
+                    planned.push((entry.path().to_path_buf(), target, false));
+                }
+            } else {
+                let target = placer.target(&abs_source, None);
+                planned.push((abs_source.clone(), target, abs_source.is_dir()));
+            }
+
+            for (source, target, directory) in planned {
+                let target = match target {
+                    Ok(target) => target,
+                    Err(e) => {
+                        outcomes.push(Some(Outcome::failed(Action::Stage, &source, e)));
+                        continue;
+                    }
+                };
+                // A template left by another machine makes the file a template here too
+                let mode = options.mode.unwrap_or_else(|| {
+                    match template::template_path(&target).is_file() {
+                        true => DeployMode::Template,
+                        false => settings.mode_for(&forge_path, &target),
+                    }
+                });
+                let target = match mode {
+                    DeployMode::Template => template::template_path(&target),
+                    _ => target,
+                };
+
+                if let Some(other) = claimed.get(&target) {
+                    outcomes.push(Some(collision(&source, &target, other)));
+                    continue;
+                }
+
+                if let Some(folded) = self.folded_ancestor(&source)? {
+                    outcomes.push(Some(
+                        Outcome::skipped(
+                            Action::Stage,
+                            &source,
+                            format!(
+                                "already managed as part of folded directory {}",
+                                folded.source.display()
+                            ),
+                        )
+                        .with_target(&folded.target),
+                    ));
+                    continue;
+                }
+
+                let existing = self.config.find_record_by_target(&target)?;
+                match plan_stage(&source, &target, directory, mode, existing) {
+                    Ok(entry) => {
+                        claimed.insert(target, source);
+                        entries.push(entry);
+                        outcomes.push(None);
+                    }
+                    Err(outcome) => outcomes.push(Some(outcome)),
+                }
+            }
+        }
+
+        // Fill the batch results back in where their files were named
+        let mut staged = self.run_batch(Action::Stage, entries)?.outcomes.into_iter();
+        Ok(outcomes
+            .into_iter()
+            .filter_map(|outcome| outcome.or_else(|| staged.next()))
+            .collect())
+    }
+
+    /// Deploy the named staged files, or all staged files if none are named
+    ///
+    /// Each file is deployed by the mode recorded when it was staged. Symlinks are
+    /// absolute or relative according to `options`, or else the folder's configured
+    /// style. A staged directory is folded into one symlink, and a folded directory that
+    /// a file is linked into is unfolded first. Hard links, copies and rendered templates
+    /// are real files in place of the original. The files are linked as one batch: if
+    /// any of them fails, all are rolled back.
+    pub fn link(&self, files: &[PathBuf], options: &LinkOptions) -> Result<Report> {
+        let (_, forge_path) = self.active_folder()?;
+        let settings = FolderConfig::load(&forge_path)?;
+        let style = options.style.unwrap_or(settings.links);
+        let resolver = self.resolver(options.on_conflict)?;
+        self.ensure_no_pending_batch()?;
+        let mut report = Report::new();
+
+        let dotfiles = if files.is_empty() {
+            self.config.get_staged_dotfiles(None)?
+        } else {
+            self.find_staged(files, &mut report)?
+        };
+
+        let tracked: Vec<PathBuf> = self.list(None)?.into_iter().map(|d| d.source).collect();
+        let renderer = self.renderer()?;
+        let mut entries = Vec::new();
+        let mut unfolded = Vec::new();
+        for dotfile in dotfiles {
+            let planned = match dotfile.mode {
+                DeployMode::Symlink
+                    if dotfile.source.is_dir() && !symlink::is_symlink(&dotfile.source) =>
+                {
+                    plan_link_directory(&dotfile, style, &tracked, &resolver)
+                }
+                DeployMode::Symlink => plan_link(&dotfile, style, &resolver).map(|e| vec![e]),
+                DeployMode::Hardlink => {
+                    hardlink::plan_hardlink(&dotfile, &resolver).map(|e| vec![e])
+                }
+                DeployMode::Copy => plan_copy(&dotfile, &resolver).map(|e| vec![e]),
+                DeployMode::Template => {
+                    template::plan_template(&dotfile, &resolver, &renderer).map(|e| vec![e])
+                }
+            };
+            match planned {
+                Ok(planned) => {
+                    entries.extend(self.unfold_around(&dotfile.source, &mut unfolded)?);
+                    entries.extend(planned);
+                }
+                Err(outcome) => report.push(outcome),
+            }
+        }
+
+        report.extend(self.run_batch(Action::Link, entries)?);
+        Ok(report)
+    }
+
+    /// Replace folded directories with real directories holding one symlink per file
+    ///
+    /// Each file in the folded directory gets its own tracking record, so files that
+    /// are not managed by forge can then be kept alongside them.
+    pub fn unfold(&self, dirs: &[PathBuf]) -> Result<Report> {
+        let style = self.link_style()?;
+        self.ensure_no_pending_batch()?;
+        let mut report = Report::new();
+        let mut entries = Vec::new();
+
+        for dir in dirs {
+            let dir = path_utils::normalize(dir);
+            match self.config.find_dotfile_by_source(&dir)? {
+                Some(dotfile) if is_folded(&dotfile) => {
+                    entries.extend(self.plan_unfold(&dotfile, style, true)?);
+                }
+                Some(_) => report.push(Outcome::skipped(
+                    Action::Unfold,
+                    &dir,
+                    "not a folded directory",
+                )),
+                None => report.push(Outcome::failed(
+                    Action::Unfold,
+                    &dir,
+                    ForgeError::NotTracked(dir.clone()),
+                )),
+            }
+        }
+
+        report.extend(self.run_batch(Action::Unfold, entries)?);
+        Ok(report)
+    }
+
+    /// The folded directory record whose symlink `path` lies behind, if any
+    fn folded_ancestor(&self, path: &Path) -> Result<Option<DotFile>> {
+        for dir in path.ancestors().skip(1) {
+            if !symlink::is_symlink(dir) {
+                continue;
+            }
+            if let Some(dotfile) = self.config.find_dotfile_by_source(dir)?
+                && is_folded(&dotfile)
+            {
+                return Ok(Some(dotfile));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Plan unfolding the folded directory `path` lies in, unless it is in `unfolded`
+    fn unfold_around(&self, path: &Path, unfolded: &mut Vec<PathBuf>) -> Result<Vec<Entry>> {
+        match self.folded_ancestor(path)? {
+            Some(folded) if !unfolded.contains(&folded.source) => {
+                unfolded.push(folded.source.clone());
+                self.plan_unfold(&folded, self.link_style()?, true)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Plan replacing a folded directory's symlink with a directory of per-file symlinks
+    ///
+    /// The directory's record, if it is `tracked`, is replaced by one record per file,
+    /// each in its own entry.
+    fn plan_unfold(&self, folded: &DotFile, style: LinkStyle, tracked: bool) -> Result<Vec<Entry>> {
+        let home = &folded.source;
+        let forge = &folded.target;
+        let mut first = Entry::new(home)
+            .with_target(forge)
+            .step(Step::Remove { path: home.clone() })
+            .step(Step::CreateDir { path: home.clone() })
+            .with_message("unfolded into a directory of symlinks");
+        if tracked {
+            first = first.with_record(RecordChange::forget(folded));
+        }
+        let mut files = Vec::new();
+
+        for entry in walkdir::WalkDir::new(forge)
+            .min_depth(1)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            let Ok(relative) = entry.path().strip_prefix(forge) else {
+                continue;
+            };
+            let link = home.join(relative);
+            if entry.file_type().is_dir() {
+                first = first.step(Step::CreateDir { path: link });
+                continue;
+            }
+
+            let target = entry.into_path();
+            let previous = match self.config.find_record_by_target(&target)? {
+                Some((dotfile, false)) => Some(TrackedRecord {
+                    dotfile,
+                    active: false,
+                }),
+                _ => None,
+            };
+            files.push(
+                Entry::new(&link)
+                    .with_target(&target)
+                    .step(Step::Symlink {
+                        link: link.clone(),
+                        dest: target.clone(),
+                        relative: style.is_relative(),
+                    })
+                    .with_record(
+                        RecordChange::insert(DotFile::with_status(
+                            link,
+                            target,
+                            folded.profile.clone(),
+                            DotFileStatus::Linked,
+                        ))
+                        .replacing(previous),
+                    )
+                    .with_message(format!("unfolded from {}", home.display())),
+            );
+        }
+
+        Ok(std::iter::once(first).chain(files).collect())
+    }
+
+    /// The active managed folder's configured symlink style
+    pub(crate) fn link_style(&self) -> Result<LinkStyle> {
+        let (_, forge_path) = self.active_folder()?;
+        Ok(FolderConfig::load(&forge_path)?.links)
+    }
+
+    /// Resolve the staged dotfiles named by `files`, recording lookups that fail
+    fn find_staged(&self, files: &[PathBuf], report: &mut Report) -> Result<Vec<DotFile>> {
+        let mut result = Vec::new();
+
+        for file in files {
+            let abs_path = path_utils::normalize(file);
+
+            // Check if it's a specific file or a directory name
+            if abs_path.is_dir() {
+                let found_before = result.len();
+                let staged = self.config.get_staged_dotfiles(None)?;
+
+                // Find all staged files under that directory
+                for df in staged {
+                    if df.source.starts_with(&abs_path) {
+                        result.push(df);
+                    }
+                }
+
+                // Also check if the directory itself is staged
+                if let Some(df) = self.config.find_dotfile_by_source(&abs_path)?
+                    && df.is_staged()
+                    && !result.iter().any(|r| r.source == df.source)
+                {
+                    result.push(df);
+                }
+
+                if result.len() == found_before {
+                    report.push(Outcome::failed(
+                        Action::Link,
+                        &abs_path,
+                        ForgeError::NotTracked(abs_path.clone()),
+                    ));
+                }
+                continue;
+            }
+
+            let found = match self.config.find_dotfile_by_target(&abs_path)? {
+                Some(df) => Some(df),
+                // Try looking it up by source instead
+                None => self.config.find_dotfile_by_source(&abs_path)?,
+            };
+
+            match found {
+                Some(df) if df.is_staged() => result.push(df),
+                Some(_) => report.push(Outcome::skipped(Action::Link, &abs_path, "already linked")),
+                None => report.push(Outcome::failed(
+                    Action::Link,
+                    &abs_path,
+                    ForgeError::NotTracked(abs_path.clone()),
+                )),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Resolve paths named on the command line to tracking records
+    ///
+    /// A path is looked up as a tracked file's home path first, then as its managed
+    /// copy. Relative paths are taken as names inside the home directory, placed by the
+    /// managed folder's layout, or as paths inside the managed folder itself.
+    pub fn select(&self, files: &[PathBuf]) -> Result<Vec<Selection>> {
+        let (_, forge_path) = self.active_folder()?;
+        let home = self.config.get_home_dir();
+        let layout = FolderConfig::load(&forge_path)?.layout;
+        // The package layout needs a package name, so its names cannot be placed here
+        let placer = Placer::new(layout, None, home, &forge_path).ok();
+        let mut selections = Vec::new();
+
+        for file in files {
+            let candidates = if file.is_absolute() || file.starts_with("~") {
+                vec![path_utils::normalize(file)]
+            } else if let Some(filename) = file.file_name() {
+                let source = home.join(file);
+                let placed = placer
+                    .as_ref()
+                    .and_then(|placer| placer.target(&source, None).ok());
+                std::iter::once(source)
+                    .chain(placed)
+                    .chain([forge_path.join(file), forge_path.join(filename)])
+                    .collect()
+            } else {
+                return Err(ForgeError::InvalidInput(format!(
+                    "invalid file path: {}",
+                    file.display()
+                )));
+            };
+
+            match self.find_tracked(&candidates)? {
+                Some(df) => selections.push(Selection::Tracked(df)),
+                None => selections.push(Selection::Untracked(file.clone())),
+            }
+        }
+
+        Ok(selections)
+    }
+
+    /// The first tracking record with one of `paths` as its home path or managed copy
+    fn find_tracked(&self, paths: &[PathBuf]) -> Result<Option<DotFile>> {
+        for path in paths {
+            let found = match self.config.find_dotfile_by_source(path)? {
+                Some(df) => Some(df),
+                None => self.config.find_dotfile_by_target(path)?,
+            };
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remove symlinks but keep files in the forge folder
+    pub fn unlink(&self, selections: &[Selection]) -> Result<Report> {
+        self.ensure_no_pending_batch()?;
+        let mut report = Report::new();
+        let mut entries = Vec::new();
+
+        for selection in selections {
+            let planned = match selection {
+                Selection::Tracked(dotfile) => plan_unlink(dotfile),
+                // Even if not tracked, remove a symlink found at the path
+                Selection::Untracked(path) if symlink::is_symlink(path) => Ok(Entry::new(path)
+                    .step(Step::Remove { path: path.clone() })
+                    .with_message("removed untracked symlink")),
+                Selection::Untracked(path) => Err(Outcome::skipped(
+                    Action::Unlink,
+                    path,
+                    "no tracking record; not a symlink or doesn't exist",
+                )),
+            };
+            match planned {
+                Ok(entry) => entries.push(entry),
+                Err(outcome) => report.push(outcome),
+            }
+        }
+
+        report.extend(self.run_batch(Action::Unlink, entries)?);
+        Ok(report)
+    }
+
+    /// Remove files from the forge folder and stop tracking them, keeping the originals
+    pub fn remove(&self, selections: &[Selection]) -> Result<Report> {
+        self.ensure_no_pending_batch()?;
+        let mut report = Report::new();
+        let mut entries = Vec::new();
+
+        for selection in selections {
+            let planned = match selection {
+                Selection::Tracked(dotfile) => plan_remove(dotfile),
+                Selection::Untracked(path) => Err(Outcome::skipped(
+                    Action::Remove,
+                    path,
+                    "no tracking record found",
+                )),
+            };
+            match planned {
+                Ok(entry) => entries.push(entry),
+                Err(outcome) => report.push(outcome),
+            }
+        }
+
+        report.extend(self.run_batch(Action::Remove, entries)?);
+        Ok(report)
+    }
+
+    /// Delete files: the original, the forge copy and the tracking record
+    ///
+    /// Files and directories are moved to the trash, and symlinks to them are removed.
+    /// With `purge`, everything is deleted permanently instead and the operation cannot
+    /// be undone.
+    pub fn delete(&self, selections: &[Selection], purge: bool) -> Result<Report> {
+        self.ensure_no_pending_batch()?;
+        let mut report = Report::new();
+        let mut entries = Vec::new();
+        let mut trash = self.trash();
+        let mut discard = |path: &Path| match fs::symlink_metadata(path) {
+            _ if purge => Step::Purge {
+                path: path.to_path_buf(),
+            },
+            Ok(metadata) if metadata.is_symlink() => Step::Remove {
+                path: path.to_path_buf(),
+            },
+            _ => trash.step(path),
+        };
+        let deleted = if purge {
+            "deleted permanently"
+        } else {
+            "moved to the trash"
+        };
+
+        for selection in selections {
+            let planned = match selection {
+                Selection::Tracked(dotfile) => {
+                    plan_delete(dotfile, &mut discard).map(|entry| match entry.message {
+                        Some(_) => entry,
+                        None => entry.with_message(deleted),
+                    })
+                }
+                Selection::Untracked(path) => removable(Action::Delete, path, false).map(|()| {
+                    Entry::new(path)
+                        .step(discard(path))
+                        .with_message(format!("no tracking record; {}", deleted))
+                }),
+            };
+            match planned {
+                Ok(entry) => entries.push(entry),
+                Err(outcome) => report.push(outcome),
+            }
+        }
+
+        report.extend(self.run_batch(Action::Delete, entries)?);
+        Ok(report)
+    }
+
+    /// Link a profile's files into `target_dir` and track them under the profile
+    ///
+    /// Symlinks in the way, such as those of the previous profile, are replaced. Other
+    /// files in the way are resolved by `on_conflict`, or else the managed folder's
+    /// configured policy. The symlinks and their records change as one batch.
+    pub fn switch_profile(
+        &self,
+        name: &str,
+        profile_dir: &Path,
+        target_dir: &Path,
+        on_conflict: Option<ConflictPolicy>,
+    ) -> Result<Report> {
+        let links = symlink::plan_symlinks(profile_dir, target_dir)
+            .map_err(|e| ForgeError::io(format!("failed to read profile '{}'", name), e))?;
+        let resolver = self.resolver(on_conflict)?;
+        self.ensure_no_pending_batch()?;
+        let mut report = Report::new();
+
+        let mut entries = Vec::new();
+        for (file, link) in links {
+            if symlink::points_to(&link, &file) {
+                report.push(
+                    Outcome::skipped(Action::Link, &link, "already linked").with_target(&file),
+                );
+                continue;
+            }
+
+            // The file is tracked by the entry that links it, so its record only
+            // changes with the link and is reverted with it
+            let previous = self
+                .config
+                .find_record_by_target(&link)?
+                .map(|(dotfile, active)| TrackedRecord { dotfile, active });
+            let record = DotFile::with_status(
+                file.clone(),
+                link.clone(),
+                Some(name.to_string()),
+                DotFileStatus::Linked,
+            );
+            let mut entry = Entry::new(&link)
+                .with_target(&file)
+                .with_record(RecordChange::insert(record).replacing(previous));
+            if fs::symlink_metadata(&link).is_err() {
+                entries.push(entry.step(Step::Symlink {
+                    link,
+                    dest: file,
+                    relative: false,
+                }));
+                continue;
+            }
+            if !symlink::is_symlink(&link) && !conflict::same_content(&link, &file) {
+                match resolver.clear(entry, &Conflict::new(Action::Link, &link, &file)) {
+                    Ok(cleared) => entry = cleared,
+                    Err(outcome) => {
+                        report.push(outcome);
+                        continue;
+                    }
+                }
+            }
+            entries.push(entry.step(Step::Relink {
+                link,
+                dest: file,
+                relative: false,
+            }));
+        }
+        report.extend(self.run_batch(Action::Link, entries)?);
+        Ok(report)
+    }
+
+    /// Create the staging area for a new pack
+    pub fn start_pack(&self, scope: &str) -> Result<PathBuf> {
+        let (_, forge_path) = self.active_folder()?;
+        pack::start(&forge_path, scope)
+    }
+
+    /// Add files to an existing pack
+    pub fn pack(
+        &self,
+        scope: &str,
+        files: &[PathBuf],
+        options: &pack::PackOptions,
+    ) -> Result<Report> {
+        let (_, forge_path) = self.active_folder()?;
+        pack::add_files(&forge_path, scope, files, options)
+    }
+
+    /// Refresh files already in a pack, or all of them if none are named
+    pub fn repack(&self, scope: &str, files: &[PathBuf]) -> Result<Report> {
+        let (_, forge_path) = self.active_folder()?;
+        pack::repack(&forge_path, scope, files)
+    }
+
+    /// Remove files from a pack
+    pub fn unpack(&self, scope: &str, files: &[PathBuf]) -> Result<Report> {
+        let (_, forge_path) = self.active_folder()?;
+        pack::unpack(&forge_path, scope, files)
+    }
+
+    /// Seal a pack into a portable archive, returning the archive path
+    pub fn seal(&self, scope: &str) -> Result<PathBuf> {
+        let (_, forge_path) = self.active_folder()?;
+        pack::seal(&forge_path, scope)
+    }
+
+    /// Install a sealed pack
+    pub fn install(
+        &self,
+        archive: &Path,
+        options: &pack::InstallOptions,
+    ) -> Result<pack::Deployment> {
+        self.deploy(
+            Action::Install,
+            pack::install(archive, options)?,
+            options.on_conflict,
+        )
+    }
+
+    /// Restore a sealed pack to its original locations
+    pub fn restore(
+        &self,
+        archive: &Path,
+        options: &pack::RestoreOptions,
+    ) -> Result<pack::Deployment> {
+        self.deploy(
+            Action::Restore,
+            pack::restore(archive, options)?,
+            options.on_conflict,
+        )
+    }
+
+    /// Copy an extracted pack's files into place as one batch
+    ///
+    /// Files already in place are resolved by `on_conflict`, or else the managed
+    /// folder's configured policy; a pack has no managed copy to adopt them into, so
+    /// adopting keeps them. Files that are overwritten are kept in the history so the
+    /// batch can be undone. Folded directories that files are placed in are unfolded
+    /// first.
+    fn deploy(
+        &self,
+        action: Action,
+        extraction: pack::Extraction,
+        on_conflict: Option<ConflictPolicy>,
+    ) -> Result<pack::Deployment> {
+        let mut report = extraction.report;
+        if !extraction.placements.is_empty() {
+            self.ensure_no_pending_batch()?;
+        }
+        let resolver = self.resolver(on_conflict)?;
+
+        let mut entries = Vec::new();
+        let mut unfolded = Vec::new();
+        for placement in &extraction.placements {
+            let mut entry = Entry::new(&placement.to).with_target(&placement.relative_path);
+            if placement.overwrite && !conflict::same_content(&placement.to, &placement.from) {
+                let conflict = Conflict::new(action, &placement.to, &placement.from);
+                match resolver.clear_unmanaged(entry, &conflict) {
+                    Ok(cleared) => entry = cleared,
+                    Err(outcome) => {
+                        report.push(outcome.with_target(&placement.relative_path));
+                        continue;
+                    }
+                }
+            }
+
+            // Files placed in a folded directory must not land in the managed folder
+            entries.extend(self.unfold_around(&placement.to, &mut unfolded)?);
+            let from = placement.from.clone();
+            let to = placement.to.clone();
+            entries.push(entry.step(if placement.overwrite {
+                Step::Replace { path: to, from }
+            } else {
+                Step::Copy { from, to }
+            }));
+        }
+        report.extend(self.run_batch(action, entries)?);
+
+        Ok(pack::Deployment {
+            manifest: extraction.manifest,
+            report,
+        })
+    }
+
+    /// Describe the contents of a sealed pack
+    pub fn explain(&self, archive: &Path, target: Option<&Path>) -> Result<pack::Explanation> {
+        pack::explain(archive, target)
+    }
+}
+
+/// Plan staging a file or a whole directory behind a symlink in the forge folder
+///
+/// `existing` is the record already held for the target, with its active flag. An
+/// active record is a collision; an inactive one is replaced.
+fn plan_stage(
+    source: &Path,
+    target: &Path,
+    directory: bool,
+    mode: DeployMode,
+    existing: Option<(DotFile, bool)>,
+) -> std::result::Result<Entry, Outcome> {
+    let conflict = |message: String| {
+        Outcome::failed(
+            Action::Stage,
+            source,
+            ForgeError::Conflict(target.to_path_buf()),
+        )
+        .with_target(target)
+        .with_message(message)
+    };
+
+    let previous = match existing {
+        Some((dotfile, true)) if dotfile.source == source => {
+            return Err(Outcome::skipped(
+                Action::Stage,
+                source,
+                format!("already {}", dotfile.status.as_str()),
+            )
+            .with_target(target));
+        }
+        Some((dotfile, true)) => {
+            return Err(conflict(format!(
+                "target is already tracked for {}",
+                dotfile.source.display()
+            )));
+        }
+        Some((dotfile, false)) => Some(TrackedRecord {
+            dotfile,
+            active: false,
+        }),
+        None => None,
+    };
+
+    if directory && matches!(mode, DeployMode::Hardlink | DeployMode::Template) {
+        return Err(Outcome::failed(
+            Action::Stage,
+            source,
+            ForgeError::InvalidInput(format!(
+                "{} is a directory, which cannot be deployed as a {}",
+                source.display(),
+                mode.as_str()
+            )),
+        ));
+    }
+
+    // A template is shared between machines, so one already there is rendered on link
+    // rather than replaced
+    let record = RecordChange::insert(
+        DotFile::new(source.to_path_buf(), target.to_path_buf(), None).with_mode(mode),
+    )
+    .replacing(previous);
+    if mode == DeployMode::Template && target.is_file() && !symlink::is_symlink(target) {
+        return Ok(Entry::new(source)
+            .with_target(target)
+            .with_record(record)
+            .with_message("template already in the managed folder; linking renders it"));
+    }
+
+    // Never replace something already in the managed folder
+    if fs::symlink_metadata(target).is_ok() {
+        return Err(conflict(
+            "target already exists in forge folder".to_string(),
+        ));
+    }
+
+    let entry = Entry::new(source).with_target(target).with_record(record);
+
+    // Link from the forge folder TO the original file (reverse of final state)
+    let entry = entry.step(Step::Symlink {
+        link: target.to_path_buf(),
+        dest: source.to_path_buf(),
+        relative: false,
+    });
+    if directory {
+        return Ok(entry.with_message("directory staged whole; linking folds it into one symlink"));
+    }
+    Ok(entry)
+}
+
+/// A file that would stage to the same target as `other`, staged earlier in the batch
+fn collision(source: &Path, target: &Path, other: &Path) -> Outcome {
+    Outcome::failed(
+        Action::Stage,
+        source,
+        ForgeError::Conflict(target.to_path_buf()),
+    )
+    .with_target(target)
+    .with_message(format!(
+        "collides with {}, which stages to the same target",
+        other.display()
+    ))
+}
+
+/// Plan tracking a symlink that already leads into a managed folder as linked
+///
+/// `hops` is the number of symlinks on the way to `end`; a chain of them is replaced
+/// by one symlink straight to the managed file. A symlink leading anywhere else is
+/// refused rather than staged behind a symlink of its own.
+fn plan_adopt(
+    link: &Path,
+    hops: usize,
+    end: &Path,
+    folders: &[PathBuf],
+    style: LinkStyle,
+    existing: Option<(DotFile, bool)>,
+) -> std::result::Result<Entry, Outcome> {
+    if !folders.iter().any(|folder| end.starts_with(folder)) {
+        return Err(Outcome::failed(
+            Action::Stage,
+            link,
+            ForgeError::InvalidInput(format!(
+                "{} is a symlink to {}; stage the file it points to instead",
+                link.display(),
+                end.display()
+            )),
+        ));
+    }
+    if !end.exists() {
+        return Err(
+            Outcome::failed(Action::Stage, link, ForgeError::NotFound(end.to_path_buf()))
+                .with_target(end),
+        );
+    }
+
+    let previous = match existing {
+        Some((dotfile, true)) if dotfile.source == link => {
+            return Err(Outcome::skipped(
+                Action::Stage,
+                link,
+                format!("already {}", dotfile.status.as_str()),
+            )
+            .with_target(end));
+        }
+        Some((dotfile, true)) => {
+            return Err(Outcome::failed(
+                Action::Stage,
+                link,
+                ForgeError::Conflict(end.to_path_buf()),
+            )
+            .with_target(end)
+            .with_message(format!(
+                "managed file is already tracked for {}",
+                dotfile.source.display()
+            )));
+        }
+        Some((dotfile, false)) => Some(TrackedRecord {
+            dotfile,
+            active: false,
+        }),
+        None => None,
+    };
+
+    let entry = Entry::new(link).with_target(end).with_record(
+        RecordChange::insert(DotFile::with_status(
+            link.to_path_buf(),
+            end.to_path_buf(),
+            None,
+            DotFileStatus::Linked,
+        ))
+        .replacing(previous),
+    );
+    if hops == 1 {
+        return Ok(entry.with_message("adopted existing symlink"));
+    }
+    Ok(entry
+        .step(Step::Relink {
+            link: link.to_path_buf(),
+            dest: end.to_path_buf(),
+            relative: style.is_relative(),
+        })
+        .with_message(format!("adopted; replaced a chain of {} symlinks", hops)))
+}
+
+/// Plan moving one staged file into the forge folder and linking it back into place
+///
+/// If the forge folder already holds a different file at the target, `resolver`
+/// decides which of the two the symlink ends up leading to.
+fn plan_link(
+    dotfile: &DotFile,
+    style: LinkStyle,
+    resolver: &Resolver,
+) -> std::result::Result<Entry, Outcome> {
+    let source = &dotfile.source;
+    let target = &dotfile.target;
+    let record = RecordChange::update(
+        dotfile,
+        DotFile::with_status(
+            source.clone(),
+            target.clone(),
+            dotfile.profile.clone(),
+            DotFileStatus::Linked,
+        ),
+    );
+
+    if !source.exists() {
+        return Err(Outcome::failed(
+            Action::Link,
+            source,
+            ForgeError::NotFound(source.clone()),
+        ));
+    }
+
+    let mut entry = Entry::new(source).with_target(target);
+    // Remove the staging symlink, the empty directory older versions staged
+    // directories as, or a copy that matches the file
+    if symlink::is_symlink(target) || is_empty_dir(target) || conflict::same_content(source, target)
+    {
+        entry = entry.step(Step::Remove {
+            path: target.clone(),
+        });
+    } else if fs::symlink_metadata(target).is_ok() {
+        let conflict = Conflict::new(Action::Link, source, target);
+        return Ok(resolver
+            .clear(entry, &conflict)?
+            .step(Step::Relink {
+                link: source.clone(),
+                dest: target.clone(),
+                relative: style.is_relative(),
+            })
+            .with_record(record));
+    }
+
+    let entry = move_and_link(entry, source, target, style).with_record(record);
+    if source.is_dir() {
+        return Ok(entry.with_message("folded into one directory symlink"));
+    }
+    Ok(entry)
+}
+
+/// Plan deploying a staged file as a copy: the original stays in place and a copy of it
+/// becomes the managed copy, with the hash of their content recorded
+///
+/// If the forge folder already holds a different file at the target, `resolver`
+/// decides which of the two both copies end up holding.
+fn plan_copy(dotfile: &DotFile, resolver: &Resolver) -> std::result::Result<Entry, Outcome> {
+    let source = &dotfile.source;
+    let target = &dotfile.target;
+    let record = |hash: String| {
+        RecordChange::update(
+            dotfile,
+            DotFile::with_status(
+                source.clone(),
+                target.clone(),
+                dotfile.profile.clone(),
+                DotFileStatus::Linked,
+            )
+            .with_mode(DeployMode::Copy)
+            .with_hash(Some(hash)),
+        )
+    };
+
+    if !source.exists() {
+        return Err(Outcome::failed(
+            Action::Link,
+            source,
+            ForgeError::NotFound(source.clone()),
+        ));
+    }
+
+    let mut entry = Entry::new(source).with_target(target);
+    if symlink::is_symlink(target) || is_empty_dir(target) || conflict::same_content(source, target)
+    {
+        entry = entry.step(Step::Remove {
+            path: target.clone(),
+        });
+    } else if fs::symlink_metadata(target).is_ok() {
+        let conflict = Conflict::new(Action::Link, source, target);
+        let policy = resolver.resolve(&conflict)?;
+        let entry = resolver.apply(entry, policy, &conflict)?;
+        if policy == ConflictPolicy::Adopt {
+            return Ok(entry.with_record(record(sync::hash_for(Action::Link, source)?)));
+        }
+        return Ok(entry
+            .step(Step::Replace {
+                path: source.clone(),
+                from: target.clone(),
+            })
+            .with_record(record(sync::hash_for(Action::Link, target)?)));
+    }
+
+    Ok(entry
+        .step(Step::Copy {
+            from: source.clone(),
+            to: target.clone(),
+        })
+        .with_record(record(sync::hash_for(Action::Link, source)?))
+        .with_message("deployed as a copy"))
+}
+
+/// Plan linking a staged directory
+///
+/// A directory forge owns entirely is folded into one symlink. If other paths in
+/// `tracked` lie inside it, its own files are moved and linked one by one instead,
+/// and its record is replaced by one record per file.
+fn plan_link_directory(
+    dotfile: &DotFile,
+    style: LinkStyle,
+    tracked: &[PathBuf],
+    resolver: &Resolver,
+) -> std::result::Result<Vec<Entry>, Outcome> {
+    let source = &dotfile.source;
+    let target = &dotfile.target;
+    let others: Vec<&PathBuf> = tracked
+        .iter()
+        .filter(|path| path.starts_with(source) && *path != source)
+        .collect();
+    if others.is_empty() {
+        return plan_link(dotfile, style, resolver).map(|entry| vec![entry]);
+    }
+
+    let mut first = Entry::new(source)
+        .with_target(target)
+        .with_record(RecordChange::forget(dotfile))
+        .with_message(format!(
+            "linked file by file; {} other tracked path(s) live in it",
+            others.len()
+        ));
+    if symlink::is_symlink(target) || is_empty_dir(target) {
+        first = first.step(Step::Remove {
+            path: target.clone(),
+        });
+    }
+    first = first.step(Step::CreateDir {
+        path: target.clone(),
+    });
+    let mut files = Vec::new();
+
+    let walker = walkdir::WalkDir::new(source)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| !others.iter().any(|other| entry.path() == *other));
+    for entry in walker.filter_map(|e| e.ok()) {
+        let Ok(relative) = entry.path().strip_prefix(source) else {
+            continue;
+        };
+        let forge_path = target.join(relative);
+        if entry.file_type().is_dir() {
+            first = first.step(Step::CreateDir { path: forge_path });
+            continue;
+        }
+
+        let file = entry.into_path();
+        let linked = DotFile::with_status(
+            file.clone(),
+            forge_path.clone(),
+            dotfile.profile.clone(),
+            DotFileStatus::Linked,
+        );
+        files.push(
+            move_and_link(Entry::new(&file), &file, &forge_path, style)
+                .with_target(&forge_path)
+                .with_record(RecordChange::insert(linked)),
+        );
+    }
+
+    Ok(std::iter::once(first).chain(files).collect())
+}
+
+/// Add steps that copy the original into the forge folder and replace it with a symlink
+fn move_and_link(entry: Entry, source: &Path, target: &Path, style: LinkStyle) -> Entry {
+    entry
+        .step(Step::Copy {
+            from: source.to_path_buf(),
+            to: target.to_path_buf(),
+        })
+        .step(Step::Relink {
+            link: source.to_path_buf(),
+            dest: target.to_path_buf(),
+            relative: style.is_relative(),
+        })
+}
+
+/// Whether a linked record is a whole directory folded into one symlink
+fn is_folded(dotfile: &DotFile) -> bool {
+    dotfile.is_linked()
+        && dotfile.profile.is_none()
+        && dotfile.target.is_dir()
+        && symlink::points_to(&dotfile.source, &dotfile.target)
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    !symlink::is_symlink(path)
+        && fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
+
+/// Plan giving a deployed file its own content back and deactivating its record
+///
+/// A symlink or hard link is replaced by a copy of the forge file. Copies and generated
+/// files already are files of their own, and stay as they are.
+fn plan_unlink(dotfile: &DotFile) -> std::result::Result<Entry, Outcome> {
+    let source = &dotfile.source;
+    let target = &dotfile.target;
+    let entry = Entry::new(source)
+        .with_target(target)
+        .with_record(RecordChange::deactivate(dotfile));
+
+    if dotfile.is_staged() {
+        return Err(Outcome::skipped(Action::Unlink, source, "not linked"));
+    }
+
+    match dotfile.mode {
+        DeployMode::Copy => return Ok(entry.with_message("deployed as a copy; kept it in place")),
+        DeployMode::Template => {
+            return Ok(entry.with_message("deployed as a generated file; kept it in place"));
+        }
+        DeployMode::Hardlink if source.is_file() && !symlink::is_symlink(source) => {
+            if !target.exists() {
+                return Err(Outcome::failed(
+                    Action::Unlink,
+                    source,
+                    ForgeError::NotFound(target.clone()),
+                )
+                .with_target(target));
+            }
+            return Ok(entry
+                .step(Step::Replace {
+                    path: source.clone(),
+                    from: target.clone(),
+                })
+                .with_message("replaced the hard link with a copy"));
+        }
+        DeployMode::Symlink | DeployMode::Hardlink => {}
+    }
+
+    // Directories are tracked in place, so there is nothing to restore
+    if source.is_dir() && !symlink::is_symlink(source) {
+        return Ok(entry.with_message("directory tracked; nothing to restore"));
+    }
+
+    restore_original(Action::Unlink, dotfile, entry)
+}
+
+/// Plan deleting the forge copy and its record, restoring the original if it is linked
+fn plan_remove(dotfile: &DotFile) -> std::result::Result<Entry, Outcome> {
+    let source = &dotfile.source;
+    let target = &dotfile.target;
+    let mut entry = Entry::new(source).with_target(target);
+
+    if symlink::is_symlink(source) {
+        entry = restore_original(Action::Remove, dotfile, entry)?;
+    }
+
+    removable(Action::Remove, target, true).map_err(|outcome| outcome.with_target(target))?;
+    Ok(entry
+        .step(Step::Remove {
+            path: target.clone(),
+        })
+        .with_record(RecordChange::forget(dotfile)))
+}
+
+/// Plan deleting the original, the forge copy and the record
+fn plan_delete(
+    dotfile: &DotFile,
+    discard: &mut impl FnMut(&Path) -> Step,
+) -> std::result::Result<Entry, Outcome> {
+    let source = &dotfile.source;
+    let target = &dotfile.target;
+    let mut entry = Entry::new(source).with_target(target);
+
+    // A missing original does not stop the forge copy from being deleted
+    if fs::symlink_metadata(source).is_ok() {
+        removable(Action::Delete, source, true)?;
+        entry = entry.step(discard(source));
+    } else {
+        entry = entry.with_message(format!("{} was already gone", source.display()));
+    }
+
+    removable(Action::Delete, target, true).map_err(|outcome| outcome.with_target(target))?;
+    Ok(entry
+        .step(discard(target))
+        .with_record(RecordChange::forget(dotfile)))
+}
+
+/// Add steps that put a copy of the forge file or directory back where its symlink is
+fn restore_original(
+    action: Action,
+    dotfile: &DotFile,
+    entry: Entry,
+) -> std::result::Result<Entry, Outcome> {
+    let source = &dotfile.source;
+    let target = &dotfile.target;
+
+    if !target.exists() {
+        return Err(
+            Outcome::failed(action, source, ForgeError::NotFound(target.clone()))
+                .with_target(target),
+        );
+    }
+
+    // The copy replaces the symlink in one rename, so the path never goes missing
+    if symlink::is_symlink(source) {
+        Ok(entry.step(Step::Replace {
+            path: source.clone(),
+            from: target.clone(),
+        }))
+    } else if source.exists() {
+        Err(
+            Outcome::failed(action, source, ForgeError::Conflict(source.clone()))
+                .with_target(target),
+        )
+    } else {
+        Ok(entry.step(Step::Copy {
+            from: target.clone(),
+            to: source.clone(),
+        }))
+    }
+}
+
+/// Check that `path` is a file or symlink that a batch can remove
+///
+/// Directories can only be removed when they are `tracked` as a whole.
+fn removable(action: Action, path: &Path, tracked: bool) -> std::result::Result<(), Outcome> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() && !tracked => Err(Outcome::failed(
+            action,
+            path,
+            ForgeError::InvalidInput(format!("{} is a directory", path.display())),
+        )),
+        Ok(_) => Ok(()),
+        Err(_) => Err(Outcome::failed(
+            action,
+            path,
+            ForgeError::NotFound(path.to_path_buf()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ErrorKind, exit_code};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+    use std::time::{Duration, SystemTime};
+
+    /// An engine whose configuration, database and managed folder live in a temp dir
+    pub(crate) fn test_engine(temp: &TempDir) -> Engine {
+        let config = Config::with_dirs(
+            temp.path().join("config"),
+            temp.path().join("data").join("forge.db"),
+        )
+        .with_home_dir(temp.path().join("home"))
+        .with_data_home(temp.path().join("home/.local/share"));
+        config
+            .add_managed_folder("default", &temp.path().join("forge"))
+            .unwrap();
+        Engine::with_config(config).unwrap()
+    }
+
+    #[test]
+    fn test_stage_and_link_file() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let file = temp.child("home/.bashrc");
+        file.write_str("export EDITOR=vi\n").unwrap();
+
+        let report = engine
+            .stage(&[file.path().to_path_buf()], &StageOptions::default())
+            .unwrap();
+        assert_eq!(report.succeeded(), 1);
+        let forge_copy = temp.path().join("forge/.bashrc");
+        assert_eq!(
+            report.outcomes[0].target.as_deref(),
+            Some(forge_copy.as_path())
+        );
+        assert!(engine.list(None).unwrap()[0].is_staged());
+
+        let report = engine.link(&[], &LinkOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert!(file.path().is_symlink());
+        assert_eq!(fs::read_link(file.path()).unwrap(), forge_copy);
+        assert_eq!(
+            fs::read_to_string(&forge_copy).unwrap(),
+            "export EDITOR=vi\n"
+        );
+        assert!(engine.list(None).unwrap()[0].is_linked());
+    }
+
+    #[test]
+    fn test_link_writes_relative_symlinks() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let file = temp.child("home/.config/git/config");
+        file.write_str("[user]\n").unwrap();
+        engine
+            .stage(&[file.path().to_path_buf()], &StageOptions::default())
+            .unwrap();
+
+        // The folder's setting applies unless the command overrides it
+        let forge = temp.path().join("forge");
+        let settings = FolderConfig {
+            links: LinkStyle::Relative,
+            ..FolderConfig::default()
+        };
+        settings.save(&forge).unwrap();
+        let report = engine.link(&[], &LinkOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+
+        assert_eq!(
+            fs::read_link(file.path()).unwrap(),
+            Path::new("../../../forge/.config/git/config")
+        );
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "[user]\n");
+        assert!(engine.status(None).unwrap()[0].health.is_ok());
+
+        // Undoing the link recognises the relative symlink as its own
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert!(file.path().is_file() && !file.path().is_symlink());
+
+        engine
+            .link(
+                &[],
+                &LinkOptions {
+                    style: Some(LinkStyle::Absolute),
+                    ..LinkOptions::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            fs::read_link(file.path()).unwrap(),
+            forge.join(".config/git/config")
+        );
+    }
+
+    #[test]
+    fn test_relative_symlink_under_symlinked_parent() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let real = temp.child("mnt/disk/config");
+        real.child("git/config").write_str("[user]\n").unwrap();
+        fs::create_dir_all(temp.path().join("home")).unwrap();
+        symlink::create_symlink(real.path(), temp.path().join("home/.config")).unwrap();
+        let file = temp.child("home/.config/git/config");
+        engine
+            .stage(&[file.path().to_path_buf()], &StageOptions::default())
+            .unwrap();
+
+        // `..` climbs out of the real directory, not the symlink to it
+        let options = LinkOptions {
+            style: Some(LinkStyle::Relative),
+            ..LinkOptions::default()
+        };
+        let report = engine.link(&[], &options).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert_eq!(
+            fs::read_link(file.path()).unwrap(),
+            Path::new("../../../../forge/.config/git/config")
+        );
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "[user]\n");
+        assert!(engine.status(None).unwrap()[0].health.is_ok());
+        assert!(symlink::points_to(
+            file.path(),
+            temp.path().join("forge/.config/git/config")
+        ));
+    }
+
+    #[test]
+    fn test_link_resolves_conflicts_by_policy() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let file = temp.child("home/.bashrc");
+        file.write_str("home\n").unwrap();
+        let forge = temp.child("forge/.bashrc");
+        let backed_up = |engine: &Engine| {
+            let backups = engine.backups(Some(file.path())).unwrap();
+            backups
+                .first()
+                .map(|backup| fs::read_to_string(engine.backup_content(backup)).unwrap())
+        };
+        engine
+            .stage(&[file.to_path_buf()], &StageOptions::default())
+            .unwrap();
+        // The forge folder already holds another version, as if synced from elsewhere
+        fs::remove_file(forge.path()).unwrap();
+        forge.write_str("forge\n").unwrap();
+        let link = |on_conflict| {
+            let options = LinkOptions {
+                on_conflict,
+                ..LinkOptions::default()
+            };
+            engine.link(&[], &options).unwrap()
+        };
+
+        // Without a policy the conflict fails; skip leaves both files alone
+        let report = link(None);
+        assert_eq!(report.outcomes[0].error, Some(ErrorKind::Conflict));
+        let report = link(Some(ConflictPolicy::Skip));
+        assert_eq!(report.count(Status::Skipped), 1);
+        assert!(!file.path().is_symlink());
+
+        // A prompt fails without anyone to ask
+        assert_eq!(link(Some(ConflictPolicy::Prompt)).failed(), 1);
+
+        // Backup keeps the home file in the backup store and links to the forge version
+        assert_eq!(link(Some(ConflictPolicy::Backup)).succeeded(), 1);
+        assert!(symlink::points_to(file.path(), forge.path()));
+        assert_eq!(backed_up(&engine).as_deref(), Some("home\n"));
+        assert_eq!(fs::read_to_string(forge.path()).unwrap(), "forge\n");
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert_eq!(backed_up(&engine), None);
+
+        // Adopt pulls the home file into the forge copy
+        assert_eq!(link(Some(ConflictPolicy::Adopt)).succeeded(), 1);
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "home\n");
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(forge.path()).unwrap(), "forge\n");
+
+        // Overwrite replaces the home file; the folder setting applies without a flag
+        FolderConfig {
+            on_conflict: Some(ConflictPolicy::Overwrite),
+            ..FolderConfig::default()
+        }
+        .save(&temp.path().join("forge"))
+        .unwrap();
+        assert_eq!(link(None).succeeded(), 1);
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "forge\n");
+        assert_eq!(backed_up(&engine), None);
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+
+        // The answer to a prompt is used
+        let engine = engine.with_prompt(|_| ConflictPolicy::Backup);
+        let options = LinkOptions {
+            on_conflict: Some(ConflictPolicy::Prompt),
+            ..LinkOptions::default()
+        };
+        assert_eq!(engine.link(&[], &options).unwrap().succeeded(), 1);
+        assert_eq!(backed_up(&engine).as_deref(), Some("home\n"));
+    }
+
+    #[test]
+    fn test_unlink_restores_original_content() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let file = temp.child("home/.gitconfig");
+        file.write_str("[user]\n").unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::options()
+            .write(true)
+            .open(file.path())
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        engine
+            .stage(&[file.path().to_path_buf()], &StageOptions::default())
+            .unwrap();
+        engine.link(&[], &LinkOptions::default()).unwrap();
+        let forge_copy = temp.path().join("forge/.gitconfig");
+        let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+        assert_eq!(modified(&forge_copy), mtime);
+
+        let selections = engine.select(&[PathBuf::from(".gitconfig")]).unwrap();
+        let report = engine.unlink(&selections).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert!(!file.path().is_symlink());
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "[user]\n");
+        assert_eq!(modified(file.path()), mtime);
+        assert_eq!(fs::read_to_string(&forge_copy).unwrap(), "[user]\n");
+
+        // Undo swaps the symlink back in over the copy
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert!(symlink::points_to(file.path(), &forge_copy));
+    }
+
+    #[test]
+    fn test_stage_reports_missing_and_duplicate_paths() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let file = temp.child("home/.vimrc");
+        file.touch().unwrap();
+
+        let files = vec![file.path().to_path_buf(), temp.path().join("home/missing")];
+        let report = engine.stage(&files, &StageOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 1);
+        assert_eq!(report.failed(), 1);
+        assert_eq!(report.outcomes[1].error, Some(ErrorKind::NotFound));
+        assert_eq!(report.exit_code(), exit_code::PARTIAL_FAILURE);
+
+        let report = engine.stage(&files[..1], &StageOptions::default()).unwrap();
+        assert_eq!(report.count(Status::Skipped), 1);
+        assert_eq!(
+            report.outcomes[0].message.as_deref(),
+            Some("already staged")
+        );
+    }
+
+    #[test]
+    fn test_stage_mirrors_home_and_reports_collisions() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let git = temp.child("home/.config/git/config");
+        git.write_str("[user]\n").unwrap();
+        let ssh = temp.child("home/.ssh/config");
+        ssh.write_str("Host *\n").unwrap();
+        let files = vec![git.path().to_path_buf(), ssh.path().to_path_buf()];
+
+        // Flattening both to `config` collides, and nothing is written for the loser
+        let flat = StageOptions {
+            layout: Some(Layout::Flat),
+            ..Default::default()
+        };
+        let report = engine.stage(&files, &flat).unwrap();
+        assert_eq!(report.outcomes[1].error, Some(ErrorKind::Conflict));
+        assert_eq!(report.exit_code(), exit_code::CONFLICT);
+        assert!(temp.path().join("forge/config").is_symlink());
+        assert_eq!(engine.list(None).unwrap().len(), 1);
+
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        let report = engine.stage(&files, &StageOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 2, "{:?}", report);
+        assert!(temp.path().join("forge/.config/git/config").is_symlink());
+        assert!(temp.path().join("forge/.ssh/config").is_symlink());
+
+        let package = StageOptions {
+            package: Some("git".to_string()),
+            ..Default::default()
+        };
+        let gitignore = temp.child("home/.config/git/ignore");
+        gitignore.touch().unwrap();
+        engine
+            .stage(&[gitignore.path().to_path_buf()], &package)
+            .unwrap();
+        assert!(
+            temp.path()
+                .join("forge/git/.config/git/ignore")
+                .is_symlink()
+        );
+    }
+
+    #[test]
+    fn test_tracking_records_stay_unique() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let file = temp.child("home/.inputrc");
+        file.touch().unwrap();
+        let files = vec![file.path().to_path_buf()];
+        engine.stage(&files, &StageOptions::default()).unwrap();
+        engine.link(&[], &LinkOptions::default()).unwrap();
+        let selections = engine.select(&[PathBuf::from(".inputrc")]).unwrap();
+        engine.unlink(&selections).unwrap();
+
+        // Staging again takes over the inactive record instead of adding another
+        fs::remove_file(temp.path().join("forge/.inputrc")).unwrap();
+        let report = engine.stage(&files, &StageOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert_eq!(engine.list(None).unwrap().len(), 1);
+        let record = engine
+            .config
+            .find_record_by_target(&temp.path().join("forge/.inputrc"))
+            .unwrap();
+        assert!(record.is_some_and(|(dotfile, active)| active && dotfile.is_staged()));
+    }
+
+    #[test]
+    fn test_stage_adopts_links_into_managed_folder() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let forge = temp.child("forge");
+        forge
+            .child(".bashrc")
+            .write_str("alias ll='ls -l'\n")
+            .unwrap();
+        forge.child(".profile").touch().unwrap();
+        let home = temp.child("home");
+        home.create_dir_all().unwrap();
+        let bashrc = home.child(".bashrc");
+        symlink::create_symlink(forge.child(".bashrc").path(), bashrc.path()).unwrap();
+
+        // A chain through another symlink is collapsed to point straight at the file
+        let profile = home.child(".profile");
+        let hop = home.child(".profile.hop");
+        symlink::create_symlink(forge.child(".profile").path(), hop.path()).unwrap();
+        symlink::create_symlink(hop.path(), profile.path()).unwrap();
+
+        // Cycles and symlinks leading elsewhere are refused
+        let loop_a = home.child(".loop-a");
+        let loop_b = home.child(".loop-b");
+        symlink::create_symlink(loop_b.path(), loop_a.path()).unwrap();
+        symlink::create_symlink(loop_a.path(), loop_b.path()).unwrap();
+        let elsewhere = temp.child("elsewhere/.inputrc");
+        elsewhere.touch().unwrap();
+        let outside = home.child(".inputrc");
+        symlink::create_symlink(elsewhere.path(), outside.path()).unwrap();
+
+        let files: Vec<PathBuf> = [&bashrc, &profile, &loop_a, &outside]
+            .iter()
+            .map(|child| child.path().to_path_buf())
+            .collect();
+        let report = engine.stage(&files, &StageOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 2, "{:?}", report);
+        assert_eq!(report.outcomes[2].error, Some(ErrorKind::InvalidInput));
+        assert_eq!(report.outcomes[3].error, Some(ErrorKind::InvalidInput));
+
+        assert_eq!(
+            fs::read_link(profile.path()).unwrap(),
+            forge.child(".profile").path()
+        );
+        assert!(!forge.child(".bashrc").path().is_symlink());
+        let status = engine.status(None).unwrap();
+        assert_eq!(status.len(), 2);
+        assert!(
+            status
+                .iter()
+                .all(|s| s.dotfile.is_linked() && s.health.is_ok())
+        );
+
+        // Adopted files unlink like any linked file
+        let selections = engine.select(&[PathBuf::from(".bashrc")]).unwrap();
+        engine.unlink(&selections).unwrap();
+        assert_eq!(
+            fs::read_to_string(bashrc.path()).unwrap(),
+            "alias ll='ls -l'\n"
+        );
+    }
+
+    #[test]
+    fn test_directories_fold_and_unfold() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let nvim = temp.child("home/.config/nvim");
+        nvim.child("init.lua")
+            .write_str("require('plugins')\n")
+            .unwrap();
+        nvim.child("lua/plugins.lua")
+            .write_str("return {}\n")
+            .unwrap();
+        let forge_copy = temp.path().join("forge/.config/nvim");
+
+        // A directory staged without --recursive is linked as one symlink
+        engine
+            .stage(&[nvim.path().to_path_buf()], &StageOptions::default())
+            .unwrap();
+        assert!(forge_copy.is_symlink());
+        let report = engine.link(&[], &LinkOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert_eq!(fs::read_link(nvim.path()).unwrap(), forge_copy);
+        assert!(forge_copy.join("lua/plugins.lua").is_file());
+        assert!(engine.status(None).unwrap()[0].health.is_ok());
+
+        // Files inside it already belong to the folded directory
+        let report = engine
+            .stage(&[nvim.path().join("init.lua")], &StageOptions::default())
+            .unwrap();
+        assert_eq!(report.count(Status::Skipped), 1);
+
+        let report = engine.unfold(&[nvim.path().to_path_buf()]).unwrap();
+        assert_eq!(report.succeeded(), 3, "{:?}", report);
+        assert!(nvim.path().is_dir() && !nvim.path().is_symlink());
+        assert_eq!(
+            fs::read_link(nvim.path().join("lua/plugins.lua")).unwrap(),
+            forge_copy.join("lua/plugins.lua")
+        );
+        let status = engine.status(None).unwrap();
+        assert_eq!(status.len(), 2);
+        assert!(status.iter().all(|s| s.health.is_ok()));
+
+        // Undoing the unfold puts the directory symlink back
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert_eq!(fs::read_link(nvim.path()).unwrap(), forge_copy);
+        assert_eq!(engine.list(None).unwrap().len(), 1);
+
+        // Unlinking restores a real copy of the directory
+        let selections = engine.select(std::slice::from_ref(&forge_copy)).unwrap();
+        let report = engine.unlink(&selections).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert!(!nvim.path().is_symlink());
+        assert_eq!(
+            fs::read_to_string(nvim.path().join("lua/plugins.lua")).unwrap(),
+            "return {}\n"
+        );
+    }
+
+    #[test]
+    fn test_directories_with_other_tracked_files_link_file_by_file() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let vim = temp.child("home/.vim");
+        vim.child("vimrc").write_str("set nu\n").unwrap();
+        vim.child("after/ftplugin/rust.vim").touch().unwrap();
+
+        let package = StageOptions {
+            package: Some("rust".to_string()),
+            ..Default::default()
+        };
+        engine
+            .stage(&[vim.path().join("after/ftplugin/rust.vim")], &package)
+            .unwrap();
+        engine
+            .stage(&[vim.path().to_path_buf()], &StageOptions::default())
+            .unwrap();
+
+        let report = engine.link(&[], &LinkOptions::default()).unwrap();
+        assert_eq!(report.failed(), 0, "{:?}", report);
+        assert!(vim.path().is_dir() && !vim.path().is_symlink());
+        assert_eq!(
+            fs::read_link(vim.path().join("vimrc")).unwrap(),
+            temp.path().join("forge/.vim/vimrc")
+        );
+        assert_eq!(
+            fs::read_link(vim.path().join("after/ftplugin/rust.vim")).unwrap(),
+            temp.path().join("forge/rust/.vim/after/ftplugin/rust.vim")
+        );
+        let status = engine.status(None).unwrap();
+        assert_eq!(status.len(), 2);
+        assert!(status.iter().all(|s| s.health.is_ok()), "{:?}", status);
+
+        // The whole batch, unfolded directory included, can be undone
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(vim.path().join("vimrc")).unwrap(),
+            "set nu\n"
+        );
+        assert!(temp.path().join("forge/.vim").is_symlink());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid_utf8_names_round_trip() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let name = OsStr::from_bytes(b".caf\xe9rc");
+        let file = temp.path().join("home").join(name);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "set bell-style none\n").unwrap();
+
+        let report = engine
+            .stage(std::slice::from_ref(&file), &StageOptions::default())
+            .unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        engine.link(&[], &LinkOptions::default()).unwrap();
+        let tracked = engine.list(None).unwrap();
+        assert_eq!(tracked[0].source, file);
+        assert_eq!(tracked[0].target, temp.path().join("forge").join(name));
+        assert!(file.is_symlink());
+
+        let selections = engine.select(&[PathBuf::from(name)]).unwrap();
+        engine.unlink(&selections).unwrap();
+        assert!(!file.is_symlink());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "set bell-style none\n");
+
+        // The history keeps the name exactly, so the unlink can be undone
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert!(file.is_symlink());
+    }
+
+    #[test]
+    fn test_operations_require_managed_folder() {
+        let temp = TempDir::new().unwrap();
+        let config = Config::with_dirs(temp.path().join("config"), temp.path().join("forge.db"));
+        let engine = Engine::with_config(config).unwrap();
+
+        let err = engine.stage(&[], &StageOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NoManagedFolder);
+        assert!(engine.link(&[], &LinkOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+// Rebuilding tracking records from the symlinks on disk
+use super::{Action, Engine, Outcome, Report};
+use crate::dotfile::{DotFile, DotFileStatus};
+use crate::error::{ForgeError, Result};
+use crate::symlink;
+use crate::utils::path_utils;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Forge's own bookkeeping inside a managed folder (packs, archives)
+const INTERNAL_DIR: &str = ".forge";
+
+impl Engine {
+    /// Rebuild tracking records from symlinks found under `roots` and in the managed folders
+    ///
+    /// Symlinks under `roots` that point into a managed folder become linked records, and
+    /// symlinks inside a managed folder that point out of it become staged records. Files
+    /// in a managed folder that no symlink points to are reported as skipped.
+    pub fn reindex(&self, roots: &[PathBuf], dry_run: bool) -> Result<Report> {
+        let folders: Vec<PathBuf> = self
+            .config
+            .get_managed_folders()
+            .map_err(|e| ForgeError::io("failed to read managed folders", e))?
+            .into_iter()
+            .map(|(_, path)| path_utils::clean(&path))
+            .collect();
+        if folders.is_empty() {
+            return Err(ForgeError::NoManagedFolder);
+        }
+
+        let mut found = Vec::new();
+        for root in roots {
+            let root = path_utils::normalize(root);
+            found.extend(links_into(&root, &folders));
+        }
+        for folder in &folders {
+            found.extend(staging_links(folder, &folders));
+        }
+
+        let mut report = Report::new();
+        for dotfile in &found {
+            report.push(self.index_one(dotfile, dry_run));
+        }
+
+        // Anything a linked record points at, or lies under, is accounted for
+        let referenced: Vec<&Path> = found.iter().map(|d| d.target.as_path()).collect();
+        for folder in &folders {
+            for file in managed_files(folder) {
+                if !referenced.iter().any(|target| file.starts_with(target)) {
+                    report.push(Outcome::skipped(
+                        Action::Reindex,
+                        &file,
+                        "no symlink points to this file",
+                    ));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Record one discovered symlink, replacing any record that disagrees with it
+    fn index_one(&self, dotfile: &DotFile, dry_run: bool) -> Outcome {
+        let source = &dotfile.source;
+        let target = &dotfile.target;
+
+        let existing = match self.config.find_dotfile_by_target(target) {
+            Ok(existing) => existing,
+            Err(e) => return Outcome::failed(Action::Reindex, source, e.into()),
+        };
+        if let Some(existing) = &existing
+            && existing.source == *source
+            && existing.status == dotfile.status
+        {
+            return Outcome::skipped(Action::Reindex, source, "already tracked")
+                .with_target(target);
+        }
+
+        let message = match dotfile.status {
+            DotFileStatus::Staged => "staged",
+            _ => "linked",
+        };
+        if dry_run {
+            return Outcome::planned(Action::Reindex, source)
+                .with_target(target)
+                .with_message(message);
+        }
+
+        let result = (|| {
+            if existing.is_some() {
+                self.config.remove_dotfile(target)?;
+            }
+            match dotfile.status {
+                DotFileStatus::Staged => self.config.stage_dotfile(source, target, None),
+                _ => self.config.add_dotfile(source, target, None),
+            }
+        })();
+
+        match result {
+            Ok(()) => Outcome::done(Action::Reindex, source)
+                .with_target(target)
+                .with_message(message),
+            Err(e) => Outcome::failed(Action::Reindex, source, e.into()).with_target(target),
+        }
+    }
+}
+
+/// Find symlinks under `root` that point into one of `folders`, as linked records
+fn links_into(root: &Path, folders: &[PathBuf]) -> Vec<DotFile> {
+    let mut found = Vec::new();
+
+    // Managed folders are scanned separately, and symlinks are not followed
+    let walker = WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| !folders.iter().any(|f| entry.path() == f));
+
+    for entry in walker.filter_map(|e| e.ok()) {
+        if !entry.path_is_symlink() {
+            continue;
+        }
+        let Ok(destination) = symlink::resolve_link(entry.path()) else {
+            continue;
+        };
+        if folders.iter().any(|f| destination.starts_with(f)) {
+            found.push(DotFile::with_status(
+                entry.path().to_path_buf(),
+                destination,
+                None,
+                DotFileStatus::Linked,
+            ));
+        }
+    }
+
+    found
+}
+
+/// Find staging symlinks inside `folder` that point out of every managed folder
+fn staging_links(folder: &Path, folders: &[PathBuf]) -> Vec<DotFile> {
+    let mut found = Vec::new();
+
+    for entry in managed_entries(folder) {
+        if !entry.path_is_symlink() {
+            continue;
+        }
+        let Ok(destination) = symlink::resolve_link(entry.path()) else {
+            continue;
+        };
+        if !folders.iter().any(|f| destination.starts_with(f)) {
+            found.push(DotFile::with_status(
+                destination,
+                entry.path().to_path_buf(),
+                None,
+                DotFileStatus::Staged,
+            ));
+        }
+    }
+
+    found
+}
+
+/// Regular files stored in `folder`
+fn managed_files(folder: &Path) -> Vec<PathBuf> {
+    managed_entries(folder)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// Entries of a managed folder, skipping forge's internal directory
+fn managed_entries(folder: &Path) -> impl Iterator<Item = walkdir::DirEntry> {
+    WalkDir::new(folder)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| entry.depth() != 1 || entry.file_name() != INTERNAL_DIR)
+        .filter_map(|e| e.ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Status;
+    use crate::engine::tests::test_engine;
+    use crate::engine::{LinkOptions, StageOptions};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+    use std::fs;
+
+    #[test]
+    fn test_reindex_rebuilds_lost_database() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let home = temp.child("home");
+        let bashrc = home.child(".bashrc");
+        bashrc.touch().unwrap();
+        let vimrc = home.child(".vimrc");
+        vimrc.touch().unwrap();
+        engine
+            .stage(&[bashrc.path().to_path_buf()], &StageOptions::default())
+            .unwrap();
+        engine.link(&[], &LinkOptions::default()).unwrap();
+        engine
+            .stage(&[vimrc.path().to_path_buf()], &StageOptions::default())
+            .unwrap();
+        temp.child("forge/notes.txt").touch().unwrap();
+
+        // Lose the database
+        fs::remove_file(temp.path().join("data/forge.db")).unwrap();
+        let engine = test_engine(&temp);
+        assert!(engine.list(None).unwrap().is_empty());
+
+        let roots = [home.path().to_path_buf()];
+        let planned = engine.reindex(&roots, true).unwrap();
+        assert_eq!(planned.count(Status::Planned), 2);
+        assert!(engine.list(None).unwrap().is_empty());
+
+        let report = engine.reindex(&roots, false).unwrap();
+        assert_eq!(report.count(Status::Done), 2);
+        let orphans: Vec<_> = report
+            .outcomes
+            .iter()
+            .filter(|o| o.status == Status::Skipped)
+            .map(|o| o.path.clone())
+            .collect();
+        assert_eq!(orphans, vec![temp.path().join("forge/notes.txt")]);
+
+        let mut dotfiles = engine.list(None).unwrap();
+        dotfiles.sort_by(|a, b| a.source.cmp(&b.source));
+        assert_eq!(dotfiles[0].source, bashrc.path());
+        assert_eq!(dotfiles[0].target, temp.path().join("forge/.bashrc"));
+        assert_eq!(dotfiles[0].status, DotFileStatus::Linked);
+        assert_eq!(dotfiles[1].source, vimrc.path());
+        assert_eq!(dotfiles[1].status, DotFileStatus::Staged);
+        assert!(engine.status(None).unwrap().iter().all(|s| !s.is_drifted()));
+
+        // Running it again changes nothing
+        let again = engine.reindex(&roots, false).unwrap();
+        assert_eq!(again.count(Status::Done), 0);
+        assert_eq!(engine.list(None).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,261 @@
+// Structured results returned by engine operations
+use crate::config::folder::DeployMode;
+use crate::error::{ErrorKind, ForgeError, exit_code};
+use crate::utils::path_utils::{escaped, escaped_opt};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The kind of change an outcome describes
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Init,
+    Stage,
+    Link,
+    Unfold,
+    Unlink,
+    Sync,
+    Render,
+    Remove,
+    Delete,
+    Pack,
+    Unpack,
+    Seal,
+    Install,
+    Restore,
+    Prune,
+    Repair,
+    Reindex,
+    Import,
+    Stow,
+    Unstow,
+    Migrate,
+    Rollback,
+    Undo,
+}
+
+impl Action {
+    /// Lowercase command-style name of the action
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Init => "init",
+            Action::Stage => "stage",
+            Action::Link => "link",
+            Action::Unfold => "unfold",
+            Action::Unlink => "unlink",
+            Action::Sync => "sync",
+            Action::Render => "render",
+            Action::Remove => "remove",
+            Action::Delete => "delete",
+            Action::Pack => "pack",
+            Action::Unpack => "unpack",
+            Action::Seal => "seal",
+            Action::Install => "install",
+            Action::Restore => "restore",
+            Action::Prune => "prune",
+            Action::Repair => "repair",
+            Action::Reindex => "reindex",
+            Action::Import => "import",
+            Action::Stow => "stow",
+            Action::Unstow => "unstow",
+            Action::Migrate => "migrate",
+            Action::Rollback => "rollback",
+            Action::Undo => "undo",
+        }
+    }
+}
+
+/// Whether an action was carried out
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// The action completed
+    Done,
+    /// The action would be performed (dry run)
+    Planned,
+    /// The action was deliberately not performed
+    Skipped,
+    /// The action was attempted and failed
+    Failed,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Done => "done",
+            Status::Planned => "planned",
+            Status::Skipped => "skipped",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+/// The result of applying one action to one path
+#[derive(Debug, Clone, Serialize)]
+pub struct Outcome {
+    /// The user-facing path the action applied to
+    #[serde(with = "escaped")]
+    pub path: PathBuf,
+    /// The counterpart of `path` (forge copy, link target or install source)
+    #[serde(with = "escaped_opt")]
+    pub target: Option<PathBuf>,
+    pub action: Action,
+    pub status: Status,
+    /// Reason for a skip or failure, or extra detail for a completed action
+    pub message: Option<String>,
+    /// Classification of the error for failed outcomes
+    pub error: Option<ErrorKind>,
+    /// How the file is deployed once the action completed, for actions that track it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<DeployMode>,
+}
+
+impl Outcome {
+    pub fn new(action: Action, status: Status, path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            target: None,
+            action,
+            status,
+            message: None,
+            error: None,
+            mode: None,
+        }
+    }
+
+    pub fn done(action: Action, path: &Path) -> Self {
+        Self::new(action, Status::Done, path)
+    }
+
+    pub fn planned(action: Action, path: &Path) -> Self {
+        Self::new(action, Status::Planned, path)
+    }
+
+    pub fn skipped(action: Action, path: &Path, reason: impl Into<String>) -> Self {
+        Self::new(action, Status::Skipped, path).with_message(reason)
+    }
+
+    pub fn failed(action: Action, path: &Path, error: ForgeError) -> Self {
+        let mut outcome = Self::new(action, Status::Failed, path).with_message(error.to_string());
+        outcome.error = Some(error.kind());
+        outcome
+    }
+
+    pub fn with_target(mut self, target: &Path) -> Self {
+        self.target = Some(target.to_path_buf());
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.status == Status::Failed
+    }
+}
+
+/// Per-path outcomes of a single engine operation
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub outcomes: Vec<Outcome>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, outcome: Outcome) {
+        self.outcomes.push(outcome);
+    }
+
+    pub fn extend(&mut self, other: Report) {
+        self.outcomes.extend(other.outcomes);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outcomes.is_empty()
+    }
+
+    /// Number of outcomes with the given status
+    pub fn count(&self, status: Status) -> usize {
+        self.outcomes.iter().filter(|o| o.status == status).count()
+    }
+
+    pub fn succeeded(&self) -> usize {
+        self.count(Status::Done)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(Status::Failed)
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.outcomes.iter().any(Outcome::is_failed)
+    }
+
+    /// Exit code summarizing the report
+    ///
+    /// Failures other than conflicts take precedence over conflicts.
+    pub fn exit_code(&self) -> u8 {
+        let failures = self.outcomes.iter().filter(|o| o.is_failed());
+        let mut code = exit_code::SUCCESS;
+        for outcome in failures {
+            if outcome.error == Some(ErrorKind::Conflict) {
+                code = exit_code::CONFLICT;
+            } else {
+                return exit_code::PARTIAL_FAILURE;
+            }
+        }
+        code
+    }
+}
+
+impl FromIterator<Outcome> for Report {
+    fn from_iter<I: IntoIterator<Item = Outcome>>(outcomes: I) -> Self {
+        Self {
+            outcomes: outcomes.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report_of(outcomes: Vec<Outcome>) -> Report {
+        Report { outcomes }
+    }
+
+    #[test]
+    fn test_exit_code_precedence() {
+        let path = Path::new("/tmp/file");
+        let done = Outcome::done(Action::Link, path);
+        let skipped = Outcome::skipped(Action::Link, path, "already linked");
+        let conflict = Outcome::failed(
+            Action::Install,
+            path,
+            ForgeError::Conflict(path.to_path_buf()),
+        );
+        let missing = Outcome::failed(
+            Action::Stage,
+            path,
+            ForgeError::NotFound(path.to_path_buf()),
+        );
+
+        assert_eq!(
+            report_of(vec![done.clone(), skipped]).exit_code(),
+            exit_code::SUCCESS
+        );
+        assert_eq!(
+            report_of(vec![done, conflict.clone()]).exit_code(),
+            exit_code::CONFLICT
+        );
+        assert_eq!(
+            report_of(vec![conflict, missing]).exit_code(),
+            exit_code::PARTIAL_FAILURE
+        );
+    }
+}
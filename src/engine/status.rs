@@ -0,0 +1,295 @@
+// Drift detection between the tracking database and the filesystem
+use super::{Engine, hardlink, sync};
+use crate::config::folder::DeployMode;
+use crate::dotfile::{DotFile, DotFileStatus};
+use crate::error::Result;
+use crate::symlink;
+use crate::utils::path_utils::{escaped, escaped_opt};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// How a tracked file on disk compares with its database record
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    /// The symlink exists and points where the database says it should
+    Ok,
+    /// Nothing exists where the deployed file should be
+    MissingLink,
+    /// The symlink points to another existing file
+    WrongTarget,
+    /// A regular file or directory sits where the symlink should be, or a separate
+    /// file where the hard link should be
+    ReplacedByFile,
+    /// The copy in the managed folder is gone
+    ForgeCopyMissing,
+    /// The symlink points to a path that does not exist
+    Broken,
+    /// The file deployed as a copy changed since the last sync, or the generated file
+    /// since it was rendered
+    CopyChanged,
+    /// The managed copy of a file deployed as a copy changed since the last sync
+    ForgeCopyChanged,
+    /// Both sides of a file deployed as a copy changed differently since the last sync
+    BothChanged,
+    /// The generated file is no longer what its template renders as, because the
+    /// template or a variable changed
+    Stale,
+}
+
+impl Health {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Health::Ok => "ok",
+            Health::MissingLink => "missing_link",
+            Health::WrongTarget => "wrong_target",
+            Health::ReplacedByFile => "replaced_by_file",
+            Health::ForgeCopyMissing => "forge_copy_missing",
+            Health::Broken => "broken",
+            Health::CopyChanged => "copy_changed",
+            Health::ForgeCopyChanged => "forge_copy_changed",
+            Health::BothChanged => "both_changed",
+            Health::Stale => "stale",
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        *self == Health::Ok
+    }
+}
+
+/// The health of one tracked file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileStatus {
+    pub dotfile: DotFile,
+    /// Where the symlink should be
+    #[serde(with = "escaped")]
+    pub link: PathBuf,
+    /// What the symlink should point to
+    #[serde(with = "escaped")]
+    pub expected: PathBuf,
+    /// What the symlink actually points to, when it is a symlink
+    #[serde(with = "escaped_opt")]
+    pub actual: Option<PathBuf>,
+    pub health: Health,
+}
+
+impl FileStatus {
+    pub fn is_drifted(&self) -> bool {
+        !self.health.is_ok()
+    }
+}
+
+/// The symlink a tracking record describes, as (link, destination)
+///
+/// Staged files are linked from the managed folder to the original. Linked files
+/// are linked from their original location to the forge copy, except for profile
+/// files, which are recorded with the profile copy as their source.
+pub fn expected_link(dotfile: &DotFile) -> (&Path, &Path) {
+    match dotfile.status {
+        DotFileStatus::Staged => (&dotfile.target, &dotfile.source),
+        _ if dotfile.profile.is_some() => (&dotfile.target, &dotfile.source),
+        _ => (&dotfile.source, &dotfile.target),
+    }
+}
+
+/// Compare a tracking record with the filesystem
+pub fn check(dotfile: &DotFile) -> FileStatus {
+    let (link, expected) = expected_link(dotfile);
+    let recorded = dotfile.hash.as_deref().unwrap_or_default();
+    let health = match dotfile.mode {
+        // A template found in the managed folder is staged without a symlink
+        DeployMode::Template
+            if dotfile.status == DotFileStatus::Staged
+                && link.is_file()
+                && !symlink::is_symlink(link) =>
+        {
+            Some(Health::Ok)
+        }
+        _ if !dotfile.is_linked() => None,
+        DeployMode::Symlink => None,
+        DeployMode::Hardlink => check_hardlink(link, expected),
+        DeployMode::Copy => Some(check_copy(link, expected, recorded)),
+        DeployMode::Template => Some(check_generated(link, expected, recorded)),
+    };
+    if let Some(health) = health {
+        return FileStatus {
+            dotfile: dotfile.clone(),
+            link: link.to_path_buf(),
+            expected: expected.to_path_buf(),
+            actual: None,
+            health,
+        };
+    }
+    // Only linked files have their content in the managed folder
+    let forge_copy = dotfile.status != DotFileStatus::Staged;
+    let actual = symlink::resolve_link(link).ok();
+
+    let health = if let Some(actual) = &actual {
+        if symlink::points_to(link, expected) {
+            if expected.exists() {
+                Health::Ok
+            } else if forge_copy {
+                Health::ForgeCopyMissing
+            } else {
+                Health::Broken
+            }
+        } else if actual.exists() {
+            Health::WrongTarget
+        } else {
+            Health::Broken
+        }
+    } else if link.exists() {
+        // Directories are tracked without being replaced by a symlink
+        if link.is_dir() && expected.is_dir() {
+            Health::Ok
+        } else {
+            Health::ReplacedByFile
+        }
+    } else if forge_copy && !expected.exists() {
+        Health::ForgeCopyMissing
+    } else {
+        Health::MissingLink
+    };
+
+    FileStatus {
+        dotfile: dotfile.clone(),
+        link: link.to_path_buf(),
+        expected: expected.to_path_buf(),
+        actual,
+        health,
+    }
+}
+
+/// Compare a file deployed as a copy, and its managed copy, with the hash last synced
+fn check_copy(deployed: &Path, managed: &Path, recorded: &str) -> Health {
+    let hash = |path: &Path| sync::hash(path).ok();
+    match (hash(deployed), hash(managed)) {
+        (_, None) => Health::ForgeCopyMissing,
+        (None, _) => Health::MissingLink,
+        (Some(deployed), Some(managed)) if deployed == managed => Health::Ok,
+        (Some(deployed), _) if deployed == recorded => Health::ForgeCopyChanged,
+        (_, Some(managed)) if managed == recorded => Health::CopyChanged,
+        _ => Health::BothChanged,
+    }
+}
+
+/// Check that a file deployed as a hard link is still another name for its managed
+/// copy, leaving a symlink in its place to the checks for symlinks
+fn check_hardlink(link: &Path, managed: &Path) -> Option<Health> {
+    if symlink::is_symlink(link) {
+        return None;
+    }
+    let health = if !managed.exists() {
+        Health::ForgeCopyMissing
+    } else if !link.exists() {
+        Health::MissingLink
+    } else if hardlink::same_file(link, managed) {
+        Health::Ok
+    } else {
+        Health::ReplacedByFile
+    };
+    Some(health)
+}
+
+/// Compare a generated file with the hash of the output it was rendered as
+fn check_generated(deployed: &Path, template: &Path, recorded: &str) -> Health {
+    if !template.exists() {
+        Health::ForgeCopyMissing
+    } else {
+        match sync::hash(deployed) {
+            Err(_) => Health::MissingLink,
+            Ok(hash) if hash == recorded => Health::Ok,
+            Ok(_) => Health::CopyChanged,
+        }
+    }
+}
+
+impl Engine {
+    /// Check every active tracked file against the filesystem
+    ///
+    /// Templates are rendered again to tell whether their generated files are stale.
+    pub fn status(&self, profile: Option<&str>) -> Result<Vec<FileStatus>> {
+        let dotfiles = self.config.get_dotfiles(profile)?;
+        let renderer = match dotfiles.iter().any(DotFile::is_template) {
+            true => Some(self.renderer()?),
+            false => None,
+        };
+        Ok(dotfiles
+            .iter()
+            .map(|dotfile| {
+                let mut status = check(dotfile);
+                if status.health.is_ok()
+                    && dotfile.is_template()
+                    && renderer.as_ref().is_some_and(|r| r.is_stale(dotfile))
+                {
+                    status.health = Health::Stale;
+                }
+                status
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+    use std::fs;
+
+    fn linked(source: &Path, target: &Path) -> DotFile {
+        DotFile::with_status(
+            source.to_path_buf(),
+            target.to_path_buf(),
+            None,
+            DotFileStatus::Linked,
+        )
+    }
+
+    #[test]
+    fn test_check_classifies_drift() {
+        let temp = TempDir::new().unwrap();
+        let forge_copy = temp.child("forge/.bashrc");
+        forge_copy.write_str("alias ll='ls -l'\n").unwrap();
+        let other = temp.child("other");
+        other.touch().unwrap();
+        let link = temp.path().join(".bashrc");
+        let dotfile = linked(&link, forge_copy.path());
+
+        assert_eq!(check(&dotfile).health, Health::MissingLink);
+
+        symlink::create_symlink(forge_copy.path(), &link).unwrap();
+        assert_eq!(check(&dotfile).health, Health::Ok);
+
+        fs::remove_file(&link).unwrap();
+        symlink::create_symlink(other.path(), &link).unwrap();
+        let status = check(&dotfile);
+        assert_eq!(status.health, Health::WrongTarget);
+        assert_eq!(status.actual.as_deref(), Some(other.path()));
+
+        fs::remove_file(&link).unwrap();
+        symlink::create_symlink(temp.path().join("gone"), &link).unwrap();
+        assert_eq!(check(&dotfile).health, Health::Broken);
+
+        fs::remove_file(&link).unwrap();
+        fs::write(&link, "clobbered by an editor").unwrap();
+        assert_eq!(check(&dotfile).health, Health::ReplacedByFile);
+
+        fs::remove_file(&link).unwrap();
+        symlink::create_symlink(forge_copy.path(), &link).unwrap();
+        fs::remove_file(forge_copy.path()).unwrap();
+        assert_eq!(check(&dotfile).health, Health::ForgeCopyMissing);
+    }
+
+    #[test]
+    fn test_relative_link_is_ok() {
+        let temp = TempDir::new().unwrap();
+        let forge_copy = temp.child("forge/.vimrc");
+        forge_copy.touch().unwrap();
+        let link = temp.path().join(".vimrc");
+        symlink::create_symlink("forge/.vimrc", &link).unwrap();
+
+        assert_eq!(check(&linked(&link, forge_copy.path())).health, Health::Ok);
+    }
+}
@@ -0,0 +1,724 @@
+// Compatibility with GNU Stow: adopting stow directories and stow-style commands
+use super::journal::{Entry, RecordChange, Step, TrackedRecord};
+use super::{Action, Engine, Outcome, Report, is_folded};
+use crate::config::folder::{FolderConfig, Layout, LinkStyle};
+use crate::dotfile::{DotFile, DotFileStatus};
+use crate::error::{ForgeError, Result};
+use crate::symlink;
+use crate::utils::path_utils;
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+const LOCAL_IGNORE_FILE: &str = ".stow-local-ignore";
+
+/// Stow's ignore list for packages without a `.stow-local-ignore`
+const DEFAULT_IGNORE: &str = r"
+RCS
+.+,v
+CVS
+\.\#.+       # CVS conflict files / emacs lock files
+\.cvsignore
+\.svn
+_darcs
+\.hg
+\.git
+\.gitignore
+\.gitmodules
+.+~          # emacs backup files
+\#.*\#       # emacs autosave files
+^/README.*
+^/LICENSE.*
+^/COPYING
+";
+
+/// Options for adopting an existing stow directory
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Directory the packages are stowed into (defaults to the stow directory's parent)
+    pub target: Option<PathBuf>,
+    /// Name to register the stow directory under (defaults to its directory name)
+    pub name: Option<String>,
+    /// Report what would be adopted without writing anything
+    pub dry_run: bool,
+}
+
+/// A stow-style command: packages to stow, unstow and restow
+#[derive(Debug, Clone, Default)]
+pub struct StowOptions {
+    /// The stow directory holding the packages
+    pub dir: PathBuf,
+    /// Directory to stow into (defaults to the stow directory's parent)
+    pub target: Option<PathBuf>,
+    pub stow: Vec<String>,
+    pub delete: Vec<String>,
+    pub restow: Vec<String>,
+    /// Report what would be done without changing anything
+    pub simulate: bool,
+}
+
+/// The entries of a stow package that are never linked
+#[derive(Debug)]
+pub struct IgnoreList {
+    /// Patterns matched against the path from the package root, with a leading `/`
+    paths: Vec<Regex>,
+    /// Patterns matched against the entry's name
+    names: Vec<Regex>,
+}
+
+impl IgnoreList {
+    /// Read a package's `.stow-local-ignore`, or use Stow's built-in list without one
+    pub fn load(package: &Path) -> Result<Self> {
+        let path = package.join(LOCAL_IGNORE_FILE);
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).map_err(|e| {
+                ForgeError::InvalidInput(format!("invalid {}: {}", path.display(), e))
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(Self::parse(DEFAULT_IGNORE).expect("built-in ignore list is valid"))
+            }
+            Err(e) => Err(ForgeError::io(
+                format!("failed to read {}", path.display()),
+                e,
+            )),
+        }
+    }
+
+    /// Parse one regular expression per line, with comments as Stow reads them
+    ///
+    /// A pattern containing `/` is matched against the entry's path from the package
+    /// root with a leading `/`; any other pattern against its name. Either way the
+    /// whole path or name must match.
+    pub fn parse(text: &str) -> std::result::Result<Self, regex::Error> {
+        let mut list = Self {
+            paths: Vec::new(),
+            names: Vec::new(),
+        };
+        for line in text.lines() {
+            let line = strip_comment(line).replace(r"\#", "#");
+            let pattern = line.trim();
+            if pattern.is_empty() {
+                continue;
+            }
+            let regex = Regex::new(&format!("^(?:{})$", pattern))?;
+            if pattern.contains('/') {
+                list.paths.push(regex);
+            } else {
+                list.names.push(regex);
+            }
+        }
+        Ok(list)
+    }
+
+    /// Whether the entry at `relative`, a path from the package root, is ignored
+    pub fn is_ignored(&self, relative: &Path) -> bool {
+        if relative == Path::new(LOCAL_IGNORE_FILE) {
+            return true;
+        }
+        let path = format!("/{}", relative.to_string_lossy());
+        let name = relative
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        self.paths.iter().any(|regex| regex.is_match(&path))
+            || self.names.iter().any(|regex| regex.is_match(&name))
+    }
+}
+
+/// Drop a comment: a line starting with `#`, or a `#` that follows whitespace
+fn strip_comment(line: &str) -> &str {
+    if line.starts_with('#') {
+        return "";
+    }
+    let bytes = line.as_bytes();
+    match (1..bytes.len()).find(|&i| bytes[i] == b'#' && bytes[i - 1].is_ascii_whitespace()) {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+/// How a package entry is reflected where it belongs in the target directory
+#[derive(Debug, Clone, PartialEq)]
+enum Placement {
+    /// A symlink already points at the entry
+    Stowed,
+    /// Nothing is there yet
+    Missing,
+    /// Both are real directories, so the entry's contents are placed one by one
+    Directory,
+    /// Another package's directory is folded into a symlink there
+    Folded(PathBuf),
+    /// Something else is in the way
+    Occupied,
+}
+
+/// A package entry, the path it is linked at, and how that path looks now
+struct Surveyed {
+    entry: PathBuf,
+    link: PathBuf,
+    placement: Placement,
+}
+
+/// Whether adopting a link to a package entry needs a new record
+enum Adoption {
+    New(RecordChange),
+    Tracked,
+    /// The entry is already tracked for another link
+    Conflict(PathBuf),
+}
+
+impl Engine {
+    /// Adopt the links a GNU Stow directory already has in its target
+    ///
+    /// The stow directory is registered as a managed folder laid out by package, and
+    /// every symlink in the target that points at a package entry gets a linked record.
+    /// No file is changed.
+    pub fn import_stow(&self, dir: &Path, options: &ImportOptions) -> Result<Report> {
+        let dir = stow_dir(dir)?;
+        let target = target_dir(&dir, options.target.as_deref())?;
+        if !options.dry_run {
+            self.register_stow_dir(&dir, options.name.as_deref())?;
+        }
+        self.ensure_no_pending_batch()?;
+
+        let mut report = Report::new();
+        let mut entries = Vec::new();
+        for package in packages(&dir)? {
+            let name = package.file_name().unwrap_or_default().to_string_lossy();
+            let surveyed = match self.survey(&dir, &package, &target, false) {
+                Ok(surveyed) => surveyed,
+                Err(e) => {
+                    report.push(Outcome::failed(Action::Import, &package, e));
+                    continue;
+                }
+            };
+
+            for Surveyed {
+                entry,
+                link,
+                placement,
+            } in surveyed
+            {
+                match placement {
+                    Placement::Stowed => match self.adoption(&link, &entry)? {
+                        Adoption::New(record) => entries.push(
+                            Entry::new(&link)
+                                .with_target(&entry)
+                                .with_record(record)
+                                .with_message(format!("adopted from package {}", name)),
+                        ),
+                        Adoption::Tracked => report.push(
+                            Outcome::skipped(Action::Import, &link, "already tracked")
+                                .with_target(&entry),
+                        ),
+                        Adoption::Conflict(other) => {
+                            report.push(tracked_elsewhere(Action::Import, &link, &entry, &other))
+                        }
+                    },
+                    Placement::Directory => {}
+                    _ => report.push(
+                        Outcome::skipped(Action::Import, &link, "not stowed").with_target(&entry),
+                    ),
+                }
+            }
+        }
+
+        if options.dry_run {
+            report.extend(planned(Action::Import, &entries));
+            return Ok(report);
+        }
+        report.extend(self.run_batch(Action::Import, entries)?);
+        Ok(report)
+    }
+
+    /// Stow, unstow and restow packages as GNU Stow would, tracking every link
+    ///
+    /// Packages are unstowed first, then stowed, each phase as one batch. Links are
+    /// relative, and a directory is linked whole unless something is already there; a
+    /// directory folded by another package is unfolded to make room. If anything is in
+    /// the way of a package being stowed, nothing is stowed.
+    pub fn stow(&self, options: &StowOptions) -> Result<Report> {
+        let dir = stow_dir(&options.dir)?;
+        let target = target_dir(&dir, options.target.as_deref())?;
+        self.ensure_no_pending_batch()?;
+        let mut report = Report::new();
+
+        let mut entries = Vec::new();
+        for name in options.delete.iter().chain(&options.restow) {
+            match package_dir(&dir, name) {
+                Ok(package) => {
+                    entries.extend(self.plan_unstow(&dir, &package, &target, &mut report)?)
+                }
+                Err(e) => report.push(Outcome::failed(Action::Unstow, &dir.join(name), e)),
+            }
+        }
+        if options.simulate {
+            report.extend(planned(Action::Unstow, &entries));
+        } else {
+            report.extend(self.run_batch(Action::Unstow, entries)?);
+        }
+
+        let mut entries = Vec::new();
+        let mut conflicts = Report::new();
+        for name in options.stow.iter().chain(&options.restow) {
+            match package_dir(&dir, name) {
+                Ok(package) => {
+                    entries.extend(self.plan_stow(&dir, &package, &target, &mut conflicts)?)
+                }
+                Err(e) => report.push(Outcome::failed(Action::Stow, &dir.join(name), e)),
+            }
+        }
+        if conflicts.failed() > 0 {
+            report.extend(conflicts);
+            for entry in &entries {
+                report.push(
+                    Outcome::skipped(Action::Stow, &entry.path, "not stowed because of conflicts")
+                        .with_target(entry.target.as_deref().unwrap_or(&entry.path)),
+                );
+            }
+            return Ok(report);
+        }
+        report.extend(conflicts);
+        if options.simulate {
+            report.extend(planned(Action::Stow, &entries));
+        } else {
+            report.extend(self.run_batch(Action::Stow, entries)?);
+        }
+        Ok(report)
+    }
+
+    /// Plan the links and records that stow `package`
+    ///
+    /// Entries already stowed and entries in the way are reported into `report`.
+    fn plan_stow(
+        &self,
+        dir: &Path,
+        package: &Path,
+        target: &Path,
+        report: &mut Report,
+    ) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for Surveyed {
+            entry,
+            link,
+            placement,
+        } in self.survey(dir, package, target, true)?
+        {
+            match placement {
+                Placement::Stowed => report.push(
+                    Outcome::skipped(Action::Stow, &link, "already stowed").with_target(&entry),
+                ),
+                Placement::Missing => match self.adoption(&link, &entry)? {
+                    Adoption::Conflict(other) => {
+                        report.push(tracked_elsewhere(Action::Stow, &link, &entry, &other))
+                    }
+                    adoption => {
+                        let mut planned =
+                            Entry::new(&link).with_target(&entry).step(Step::Symlink {
+                                link: link.clone(),
+                                dest: entry.clone(),
+                                relative: true,
+                            });
+                        if let Adoption::New(record) = adoption {
+                            planned = planned.with_record(record);
+                        }
+                        entries.push(planned);
+                    }
+                },
+                Placement::Directory => {}
+                Placement::Folded(other) => {
+                    let (folded, tracked) = match self.config.find_dotfile_by_source(&link)? {
+                        Some(dotfile) if is_folded(&dotfile) => (dotfile, true),
+                        _ => (
+                            DotFile::with_status(link.clone(), other, None, DotFileStatus::Linked),
+                            false,
+                        ),
+                    };
+                    entries.extend(self.plan_unfold(&folded, LinkStyle::Relative, tracked)?);
+                }
+                Placement::Occupied => report.push(
+                    Outcome::failed(Action::Stow, &link, ForgeError::Conflict(link.clone()))
+                        .with_target(&entry)
+                        .with_message("existing target is not owned by stow"),
+                ),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Plan removing the links that stow `package`, and their records
+    fn plan_unstow(
+        &self,
+        dir: &Path,
+        package: &Path,
+        target: &Path,
+        report: &mut Report,
+    ) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for Surveyed { entry, link, .. } in self
+            .survey(dir, package, target, false)?
+            .into_iter()
+            .filter(|surveyed| surveyed.placement == Placement::Stowed)
+        {
+            let mut planned = Entry::new(&link)
+                .with_target(&entry)
+                .step(Step::Remove { path: link.clone() });
+            if let Some(dotfile) = self.config.find_dotfile_by_source(&link)?
+                && dotfile.target == entry
+            {
+                planned = planned.with_record(RecordChange::forget(&dotfile));
+            }
+            entries.push(planned);
+        }
+
+        if entries.is_empty() {
+            report.push(Outcome::skipped(
+                Action::Unstow,
+                package,
+                "package is not stowed",
+            ));
+        }
+        Ok(entries)
+    }
+
+    /// Walk `package`, pairing each entry with the path it is linked at under `target`
+    ///
+    /// Directories are descended into when they are real on both sides, and with
+    /// `through_folds` also where another package's directory is folded.
+    fn survey(
+        &self,
+        dir: &Path,
+        package: &Path,
+        target: &Path,
+        through_folds: bool,
+    ) -> Result<Vec<Surveyed>> {
+        let ignore = IgnoreList::load(package)?;
+        let mut surveyed = Vec::new();
+        let mut pending = vec![PathBuf::new()];
+
+        while let Some(relative) = pending.pop() {
+            let read = package.join(&relative);
+            let mut children = fs::read_dir(&read)
+                .and_then(|entries| {
+                    entries
+                        .map(|e| e.map(|e| e.file_name()))
+                        .collect::<io::Result<Vec<_>>>()
+                })
+                .map_err(|e| ForgeError::io(format!("failed to read {}", read.display()), e))?;
+            children.sort();
+
+            for name in children.into_iter().rev() {
+                let relative = relative.join(name);
+                if ignore.is_ignored(&relative) {
+                    continue;
+                }
+                let entry = package.join(&relative);
+                let link = target.join(&relative);
+                let placement = self.placement(dir, &entry, &link)?;
+                let descend = match &placement {
+                    Placement::Directory => true,
+                    Placement::Folded(_) => through_folds,
+                    _ => false,
+                };
+                if descend {
+                    pending.push(relative);
+                }
+                surveyed.push(Surveyed {
+                    entry,
+                    link,
+                    placement,
+                });
+            }
+        }
+
+        Ok(surveyed)
+    }
+
+    /// How the place `link` for package entry `entry` looks now
+    fn placement(&self, dir: &Path, entry: &Path, link: &Path) -> Result<Placement> {
+        if symlink::is_symlink(link) {
+            if symlink::points_to(link, entry) {
+                return Ok(Placement::Stowed);
+            }
+            let folded = match symlink::resolve_link(link) {
+                Ok(dest) if dest.is_dir() && entry.is_dir() => {
+                    let forge_folded = self
+                        .config
+                        .find_dotfile_by_source(link)?
+                        .is_some_and(|dotfile| is_folded(&dotfile));
+                    (dest.starts_with(dir) || forge_folded).then_some(dest)
+                }
+                _ => None,
+            };
+            return Ok(folded.map_or(Placement::Occupied, Placement::Folded));
+        }
+
+        Ok(if link.is_dir() && entry.is_dir() {
+            Placement::Directory
+        } else if fs::symlink_metadata(link).is_err() {
+            Placement::Missing
+        } else {
+            Placement::Occupied
+        })
+    }
+
+    /// The record change that tracks `link` as a linked copy of package entry `entry`
+    fn adoption(&self, link: &Path, entry: &Path) -> Result<Adoption> {
+        let previous = match self.config.find_record_by_target(entry)? {
+            Some((dotfile, true)) if dotfile.source == link => return Ok(Adoption::Tracked),
+            Some((dotfile, true)) => return Ok(Adoption::Conflict(dotfile.source)),
+            Some((dotfile, false)) => Some(TrackedRecord {
+                dotfile,
+                active: false,
+            }),
+            None => None,
+        };
+        let record = DotFile::with_status(
+            link.to_path_buf(),
+            entry.to_path_buf(),
+            None,
+            DotFileStatus::Linked,
+        );
+        Ok(Adoption::New(
+            RecordChange::insert(record).replacing(previous),
+        ))
+    }
+
+    /// Register a stow directory as a managed folder laid out by package
+    fn register_stow_dir(&self, dir: &Path, name: Option<&str>) -> Result<()> {
+        let folders = self
+            .config
+            .get_managed_folders()
+            .map_err(|e| ForgeError::io("failed to read managed folders", e))?;
+        if folders.iter().any(|(_, path)| path == dir) {
+            return Ok(());
+        }
+
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        if let Some((_, path)) = folders.iter().find(|(taken, _)| *taken == name) {
+            return Err(ForgeError::InvalidInput(format!(
+                "managed folder name '{}' is already used for {}; choose another with --name",
+                name,
+                path.display()
+            )));
+        }
+        self.config
+            .add_managed_folder(&name, dir)
+            .map_err(|e| ForgeError::io("failed to register managed folder", e))?;
+
+        // Keep settings the folder already has
+        if !FolderConfig::path(dir).exists() {
+            FolderConfig {
+                layout: Layout::Package,
+                links: LinkStyle::Relative,
+                ..FolderConfig::default()
+            }
+            .save(dir)?;
+        }
+        Ok(())
+    }
+}
+
+fn stow_dir(dir: &Path) -> Result<PathBuf> {
+    let dir = path_utils::clean(path_utils::normalize(dir));
+    if !dir.is_dir() {
+        return Err(ForgeError::NotFound(dir));
+    }
+    Ok(dir)
+}
+
+/// The target directory, which Stow takes to be the stow directory's parent by default
+fn target_dir(dir: &Path, target: Option<&Path>) -> Result<PathBuf> {
+    match target {
+        Some(target) => Ok(path_utils::clean(path_utils::normalize(target))),
+        None => dir.parent().map(Path::to_path_buf).ok_or_else(|| {
+            ForgeError::InvalidInput(format!("{} has no parent; pass a target", dir.display()))
+        }),
+    }
+}
+
+/// The packages in a stow directory, one directory each mirroring the target directory,
+/// skipping hidden directories such as `.forge`
+fn packages(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut packages: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| ForgeError::io(format!("failed to read {}", dir.display()), e))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.path().is_dir() && !entry.file_name().to_string_lossy().starts_with('.')
+        })
+        .map(|entry| entry.path())
+        .collect();
+    packages.sort();
+    Ok(packages)
+}
+
+fn package_dir(dir: &Path, name: &str) -> Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return Err(ForgeError::InvalidInput(format!(
+            "invalid package name '{}'",
+            name
+        )));
+    }
+    let package = dir.join(name);
+    if !package.is_dir() {
+        return Err(ForgeError::NotFound(package));
+    }
+    Ok(package)
+}
+
+fn tracked_elsewhere(action: Action, link: &Path, entry: &Path, other: &Path) -> Outcome {
+    Outcome::failed(action, link, ForgeError::Conflict(entry.to_path_buf()))
+        .with_target(entry)
+        .with_message(format!(
+            "package entry is already tracked for {}",
+            other.display()
+        ))
+}
+
+/// Outcomes for entries that a dry run would apply
+fn planned(action: Action, entries: &[Entry]) -> Report {
+    entries
+        .iter()
+        .map(|entry| {
+            let mut outcome = Outcome::planned(action, &entry.path);
+            outcome.target = entry.target.clone();
+            outcome.message = entry.message.clone();
+            outcome
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Status;
+    use crate::engine::tests::test_engine;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_ignore_lists() {
+        let defaults = IgnoreList::parse(DEFAULT_IGNORE).unwrap();
+        assert!(defaults.is_ignored(Path::new(".git")));
+        assert!(defaults.is_ignored(Path::new(".config/nvim/init.lua~")));
+        assert!(defaults.is_ignored(Path::new("README.md")));
+        assert!(!defaults.is_ignored(Path::new("docs/README.md")));
+        assert!(!defaults.is_ignored(Path::new(".gitconfig")));
+
+        let local =
+            IgnoreList::parse("# build output\n^/build\ncache   # anywhere\n\\#notes\n").unwrap();
+        assert!(local.is_ignored(Path::new("build")));
+        assert!(!local.is_ignored(Path::new("src/build")));
+        assert!(local.is_ignored(Path::new(".config/app/cache")));
+        assert!(local.is_ignored(Path::new("#notes")));
+        assert!(local.is_ignored(Path::new(LOCAL_IGNORE_FILE)));
+        assert!(!local.is_ignored(Path::new(".git")));
+    }
+
+    #[test]
+    fn test_import_adopts_stow_links() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let stow = temp.child("home/dotfiles");
+        stow.child("bash/.bashrc").touch().unwrap();
+        stow.child("nvim/.config/nvim/init.lua").touch().unwrap();
+        stow.child("git/.gitconfig").touch().unwrap();
+        let home = temp.child("home");
+        home.child(".config").create_dir_all().unwrap();
+        symlink::create_symlink("dotfiles/bash/.bashrc", home.child(".bashrc").path()).unwrap();
+        symlink::create_symlink(
+            "../dotfiles/nvim/.config/nvim",
+            home.child(".config/nvim").path(),
+        )
+        .unwrap();
+
+        let report = engine
+            .import_stow(stow.path(), &ImportOptions::default())
+            .unwrap();
+        assert_eq!(report.succeeded(), 2, "{:?}", report);
+        assert_eq!(report.count(Status::Skipped), 1);
+        assert_eq!(
+            fs::read_link(home.child(".bashrc").path()).unwrap(),
+            Path::new("dotfiles/bash/.bashrc")
+        );
+        let status = engine.status(None).unwrap();
+        assert_eq!(status.len(), 2);
+        assert!(status.iter().all(|s| s.health.is_ok()), "{:?}", status);
+        assert_eq!(
+            FolderConfig::load(stow.path()).unwrap().layout,
+            Layout::Package
+        );
+
+        // Importing again adopts nothing new
+        let report = engine
+            .import_stow(stow.path(), &ImportOptions::default())
+            .unwrap();
+        assert_eq!(report.succeeded(), 0);
+    }
+
+    #[test]
+    fn test_stow_folds_unfolds_and_unstows() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let stow = temp.child("stow");
+        stow.child("vim/.vim/vimrc").touch().unwrap();
+        stow.child("vim/README.md").touch().unwrap();
+        stow.child("rust/.vim/ftplugin/rust.vim").touch().unwrap();
+        stow.child("rust/.stow-local-ignore")
+            .write_str("notes\n")
+            .unwrap();
+        stow.child("rust/.vim/notes").touch().unwrap();
+        let target = temp.child("target");
+        target.create_dir_all().unwrap();
+
+        let options = |stow_: &[&str], delete: &[&str]| StowOptions {
+            dir: stow.path().to_path_buf(),
+            target: Some(target.path().to_path_buf()),
+            stow: stow_.iter().map(|s| s.to_string()).collect(),
+            delete: delete.iter().map(|s| s.to_string()).collect(),
+            ..StowOptions::default()
+        };
+
+        let report = engine.stow(&options(&["vim"], &[])).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert_eq!(
+            fs::read_link(target.child(".vim").path()).unwrap(),
+            Path::new("../stow/vim/.vim")
+        );
+        assert!(!target.child("README.md").path().exists());
+
+        // A second package unfolds the directory the first one folded
+        let report = engine.stow(&options(&["rust"], &[])).unwrap();
+        assert_eq!(report.failed(), 0, "{:?}", report);
+        assert!(!target.child(".vim").path().is_symlink());
+        assert_eq!(
+            fs::read_link(target.child(".vim/vimrc").path()).unwrap(),
+            Path::new("../../stow/vim/.vim/vimrc")
+        );
+        assert!(target.child(".vim/ftplugin").path().is_symlink());
+        assert!(!target.child(".vim/notes").path().exists());
+        assert_eq!(engine.list(None).unwrap().len(), 2);
+
+        let report = engine.stow(&options(&[], &["vim"])).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert!(!target.child(".vim/vimrc").path().exists());
+        assert_eq!(engine.list(None).unwrap().len(), 1);
+
+        // Anything in the way stops the whole stow
+        target.child(".vim/vimrc").touch().unwrap();
+        let report = engine.stow(&options(&["vim"], &[])).unwrap();
+        assert_eq!(report.failed(), 1);
+        assert!(!target.child(".vim/vimrc").path().is_symlink());
+    }
+}
@@ -0,0 +1,280 @@
+// Keeping files deployed as copies in step with their managed copies
+use super::conflict::{Conflict, Resolver};
+use super::journal::{Entry, RecordChange, Step};
+use super::template::{self, Renderer};
+use super::{Action, Engine, Outcome, Report, atomic};
+use crate::config::folder::{ConflictPolicy, DeployMode};
+use crate::dotfile::DotFile;
+use crate::error::{ForgeError, Result};
+use crate::utils::path_utils;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The hash recorded for the content of a file or directory deployed as a copy
+pub(crate) fn hash(path: &Path) -> io::Result<String> {
+    atomic::content_hash(path).map(|hash| hash.to_hex().to_string())
+}
+
+/// Like [`hash`], reporting a failure as the outcome of `action`
+pub(crate) fn hash_for(action: Action, path: &Path) -> std::result::Result<String, Outcome> {
+    hash(path).map_err(|e| {
+        Outcome::failed(
+            action,
+            path,
+            ForgeError::io(format!("failed to hash {}", path.display()), e),
+        )
+    })
+}
+
+impl Engine {
+    /// Copy changes between files deployed as copies and their managed copies, and
+    /// render templates whose output changed again
+    ///
+    /// Only the named files are synced, or every copy and template if none are named.
+    /// A copy whose two sides both changed, or a generated file edited since it was
+    /// rendered, is resolved by `on_conflict`, or else the managed folder's configured
+    /// policy. The files are synced as one batch.
+    pub fn sync(&self, files: &[PathBuf], on_conflict: Option<ConflictPolicy>) -> Result<Report> {
+        let resolver = self.resolver(on_conflict)?;
+        self.ensure_no_pending_batch()?;
+        let mut report = Report::new();
+
+        let copies: Vec<DotFile> = if files.is_empty() {
+            self.list(None)?
+                .into_iter()
+                .filter(|dotfile| dotfile.is_copy() || dotfile.is_template())
+                .collect()
+        } else {
+            self.find_copies(files, &mut report)?
+        };
+        let renderer = self.renderer()?;
+
+        let mut entries = Vec::new();
+        for dotfile in copies {
+            match plan(&dotfile, &resolver, &renderer) {
+                Ok(entry) => entries.push(entry),
+                Err(outcome) => report.push(outcome),
+            }
+        }
+
+        report.extend(self.run_batch(Action::Sync, entries)?);
+        Ok(report)
+    }
+
+    /// Resolve the copies and templates named by `files`, by either side, recording
+    /// lookups that fail
+    fn find_copies(&self, files: &[PathBuf], report: &mut Report) -> Result<Vec<DotFile>> {
+        let mut result = Vec::new();
+        for file in files {
+            let path = path_utils::normalize(file);
+            let found = match self.config.find_dotfile_by_source(&path)? {
+                Some(dotfile) => Some(dotfile),
+                None => self.config.find_dotfile_by_target(&path)?,
+            };
+            match found {
+                Some(dotfile) if dotfile.is_copy() || dotfile.is_template() => result.push(dotfile),
+                Some(_) => report.push(Outcome::skipped(
+                    Action::Sync,
+                    &path,
+                    "not deployed as a copy or template",
+                )),
+                None => report.push(Outcome::failed(
+                    Action::Sync,
+                    &path,
+                    ForgeError::NotTracked(path.clone()),
+                )),
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Plan syncing a copy, or rendering a template again
+pub(crate) fn plan(
+    dotfile: &DotFile,
+    resolver: &Resolver,
+    renderer: &Renderer,
+) -> std::result::Result<Entry, Outcome> {
+    match dotfile.mode {
+        DeployMode::Template => template::plan_render(dotfile, resolver, renderer),
+        _ => plan_sync(dotfile, resolver),
+    }
+}
+
+/// Plan copying whichever side of a copy changed since the last sync to the other
+///
+/// The record holds the BLAKE3 hash both sides had when they were last synced, which
+/// tells which side changed since. Different changes on both sides are a conflict,
+/// resolved by `resolver` like any other.
+fn plan_sync(dotfile: &DotFile, resolver: &Resolver) -> std::result::Result<Entry, Outcome> {
+    let deployed = &dotfile.source;
+    let managed = &dotfile.target;
+    let recorded = dotfile.hash.as_deref().unwrap_or_default();
+    let entry = Entry::new(deployed).with_target(managed);
+    let synced =
+        |hash: String| RecordChange::update(dotfile, dotfile.clone().with_hash(Some(hash)));
+
+    if fs::symlink_metadata(managed).is_err() {
+        return Err(Outcome::failed(
+            Action::Sync,
+            deployed,
+            ForgeError::NotFound(managed.clone()),
+        )
+        .with_target(managed));
+    }
+    let managed_hash = hash_for(Action::Sync, managed)?;
+    if fs::symlink_metadata(deployed).is_err() {
+        return Ok(entry
+            .step(Step::Copy {
+                from: managed.clone(),
+                to: deployed.clone(),
+            })
+            .with_record(synced(managed_hash))
+            .with_message("deployed the missing copy again"));
+    }
+    let deployed_hash = hash_for(Action::Sync, deployed)?;
+
+    let replace = |path: &Path, from: &Path| Step::Replace {
+        path: path.to_path_buf(),
+        from: from.to_path_buf(),
+    };
+    if deployed_hash == managed_hash {
+        if managed_hash == recorded {
+            return Err(
+                Outcome::skipped(Action::Sync, deployed, "already in sync").with_target(managed)
+            );
+        }
+        return Ok(entry
+            .with_record(synced(managed_hash))
+            .with_message("both copies already match"));
+    }
+    if deployed_hash == recorded {
+        return Ok(entry
+            .step(replace(deployed, managed))
+            .with_record(synced(managed_hash))
+            .with_message("deployed changes from the managed folder"));
+    }
+    if managed_hash == recorded {
+        return Ok(entry
+            .step(replace(managed, deployed))
+            .with_record(synced(deployed_hash))
+            .with_message("copied changes into the managed folder"));
+    }
+
+    // Both changed: the policy decides which side both copies end up holding
+    let conflict = Conflict::new(Action::Sync, deployed, managed);
+    let policy = resolver.resolve(&conflict)?;
+    let entry = resolver.apply(entry, policy, &conflict)?;
+    if policy == ConflictPolicy::Adopt {
+        return Ok(entry.with_record(synced(deployed_hash)));
+    }
+    Ok(entry
+        .step(replace(deployed, managed))
+        .with_record(synced(managed_hash)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::folder::{DeployMode, FileSettings, FolderConfig};
+    use crate::engine::tests::test_engine;
+    use crate::engine::{Health, LinkOptions, StageOptions, Status};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    /// Stage and link `file` with the copy mode set for it
+    fn deploy_copy(engine: &Engine, temp: &TempDir, file: &Path) {
+        let folder = temp.path().join("forge");
+        let relative = file.strip_prefix(temp.path().join("home")).unwrap();
+        let mut settings = FolderConfig::load(&folder).unwrap();
+        settings.files.insert(
+            relative.to_string_lossy().into_owned(),
+            FileSettings {
+                mode: DeployMode::Copy,
+            },
+        );
+        settings.save(&folder).unwrap();
+        engine
+            .stage(&[file.to_path_buf()], &StageOptions::default())
+            .unwrap();
+        let report = engine.link(&[], &LinkOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+    }
+
+    #[test]
+    fn test_sync_copies_changes_either_way() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let deployed = temp.child("home/.ssh/config");
+        deployed.write_str("Host *\n").unwrap();
+        let managed = temp.child("forge/.ssh/config");
+        deploy_copy(&engine, &temp, deployed.path());
+
+        // Linking leaves a real file in place, with an identical managed copy
+        assert!(!deployed.path().is_symlink());
+        assert_eq!(fs::read_to_string(managed.path()).unwrap(), "Host *\n");
+        let health = |engine: &Engine| engine.status(None).unwrap()[0].health;
+        assert_eq!(health(&engine), Health::Ok);
+        let report = engine.sync(&[], None).unwrap();
+        assert_eq!(report.outcomes[0].status, Status::Skipped);
+
+        // A tool rewrote the deployed copy
+        deployed.write_str("Host *\n  ForwardAgent no\n").unwrap();
+        assert_eq!(health(&engine), Health::CopyChanged);
+        let report = engine.sync(&[], None).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert_eq!(
+            fs::read_to_string(managed.path()).unwrap(),
+            "Host *\n  ForwardAgent no\n"
+        );
+        assert_eq!(health(&engine), Health::Ok);
+
+        // A pull updated the managed folder
+        managed.write_str("Host example.com\n").unwrap();
+        assert_eq!(health(&engine), Health::ForgeCopyChanged);
+        engine.sync(&[deployed.to_path_buf()], None).unwrap();
+        assert_eq!(
+            fs::read_to_string(deployed.path()).unwrap(),
+            "Host example.com\n"
+        );
+
+        // Undo puts the previous deployed copy back
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(deployed.path()).unwrap(),
+            "Host *\n  ForwardAgent no\n"
+        );
+    }
+
+    #[test]
+    fn test_sync_refuses_when_both_sides_changed() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let deployed = temp.child("home/.config/Code/User/settings.json");
+        deployed.write_str("{}\n").unwrap();
+        let managed = temp.child("forge/.config/Code/User/settings.json");
+        deploy_copy(&engine, &temp, deployed.path());
+
+        deployed.write_str("{\"local\": true}\n").unwrap();
+        managed.write_str("{\"remote\": true}\n").unwrap();
+        assert_eq!(engine.status(None).unwrap()[0].health, Health::BothChanged);
+
+        // Without a policy, both sides are left alone
+        let report = engine.sync(&[], None).unwrap();
+        assert_eq!(report.failed(), 1, "{:?}", report);
+        assert_eq!(
+            fs::read_to_string(deployed.path()).unwrap(),
+            "{\"local\": true}\n"
+        );
+
+        // Adopting keeps the deployed side
+        let report = engine.sync(&[], Some(ConflictPolicy::Adopt)).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert_eq!(
+            fs::read_to_string(managed.path()).unwrap(),
+            "{\"local\": true}\n"
+        );
+        assert_eq!(engine.status(None).unwrap()[0].health, Health::Ok);
+    }
+}
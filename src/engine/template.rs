@@ -0,0 +1,756 @@
+// Deploying managed copies as templates, rendered for this machine
+use super::conflict::{self, Conflict, Resolver};
+use super::journal::{Entry, RecordChange, Step};
+use super::{Action, Engine, Outcome, atomic, sync};
+use crate::config::folder::{ConflictPolicy, DeployMode, FolderConfig};
+use crate::dotfile::{DotFile, DotFileStatus};
+use crate::error::{ForgeError, Result};
+use crate::symlink;
+use crate::utils::path_utils::{self, escaped, escaped_opt};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const RENDERED_DIR: &str = "rendered";
+const TEMPLATE_SUFFIX: &str = ".tmpl";
+
+/// Values for the names a template refers to
+pub type Variables = BTreeMap<String, String>;
+
+/// Describe this machine: its `hostname`, `os`, `user` and `home` directory, and the
+/// `email` in `$EMAIL` if it is set
+pub fn variables(home: &Path) -> Variables {
+    let user = ["USER", "USERNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok())
+        .or_else(|| {
+            home.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    let mut variables = Variables::from([
+        ("hostname".to_string(), hostname()),
+        ("os".to_string(), std::env::consts::OS.to_string()),
+        ("user".to_string(), user),
+        ("home".to_string(), home.to_string_lossy().into_owned()),
+    ]);
+    if let Ok(email) = std::env::var("EMAIL") {
+        variables.insert("email".to_string(), email);
+    }
+    variables
+}
+
+fn hostname() -> String {
+    let from_file = |path: &str| fs::read_to_string(path).ok();
+    let from_env = |name: &str| std::env::var(name).ok();
+    from_file("/proc/sys/kernel/hostname")
+        .or_else(|| from_file("/etc/hostname"))
+        .or_else(|| from_env("HOSTNAME"))
+        .or_else(|| from_env("COMPUTERNAME"))
+        .or_else(|| {
+            std::process::Command::new("hostname")
+                .output()
+                .ok()
+                .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        })
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// The path a template for the managed copy `path` is kept at, with a `.tmpl` suffix
+pub fn template_path(path: &Path) -> PathBuf {
+    if path.to_string_lossy().ends_with(TEMPLATE_SUFFIX) {
+        return path.to_path_buf();
+    }
+    let mut name = OsString::from(path.file_name().unwrap_or_default());
+    name.push(TEMPLATE_SUFFIX);
+    path.with_file_name(name)
+}
+
+/// An `{% if %}` block being rendered
+struct Block {
+    /// The line the block opened on
+    line: usize,
+    /// Whether the text around the block is output
+    outer: bool,
+    /// Whether an earlier branch's condition held
+    taken: bool,
+    /// Whether the current branch is output
+    active: bool,
+    seen_else: bool,
+}
+
+/// Render `template`, replacing each `{{ name }}` with the value of `name` and keeping
+/// only the branches of `{% if %}` blocks whose condition holds
+///
+/// Blocks are `{% if COND %}`, any number of `{% elif COND %}`, an optional
+/// `{% else %}` and `{% endif %}`, and may be nested. A condition is a variable name,
+/// which holds when the variable is set and not empty, or `name == "value"` or
+/// `name != "value"`, and may start with `not`. A line holding nothing but a tag is
+/// left out of the output entirely. An unknown variable outside the branches left out,
+/// an unknown tag or an unclosed block is an error rather than output that quietly
+/// differs from what was meant.
+pub fn render(template: &str, variables: &Variables) -> std::result::Result<String, String> {
+    let line_at = |offset: usize| template[..offset].matches('\n').count() + 1;
+    let is_active = |blocks: &[Block]| blocks.last().is_none_or(|block| block.active);
+    let mut output = String::with_capacity(template.len());
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        let rest = &template[pos..];
+        let Some(found) = [rest.find("{{"), rest.find("{%")]
+            .into_iter()
+            .flatten()
+            .min()
+        else {
+            if is_active(&blocks) {
+                output.push_str(rest);
+            }
+            break;
+        };
+        let start = pos + found;
+        let line = line_at(start);
+        let is_tag = template[start..].starts_with("{%");
+        let close = if is_tag { "%}" } else { "}}" };
+        let Some(length) = template[start + 2..].find(close) else {
+            return Err(format!(
+                "line {}: '{}' is never closed",
+                line,
+                &template[start..start + 2]
+            ));
+        };
+        let inner = template[start + 2..start + 2 + length].trim();
+        let mut text_end = start;
+        let mut end = start + 2 + length + 2;
+
+        // A tag alone on its line takes the whole line with it
+        if is_tag {
+            let line_start = template[..start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = template[end..]
+                .find('\n')
+                .map_or(template.len(), |i| end + i + 1);
+            if line_start >= pos
+                && template[line_start..start].trim().is_empty()
+                && template[end..line_end].trim().is_empty()
+            {
+                text_end = line_start;
+                end = line_end;
+            }
+        }
+
+        if is_active(&blocks) {
+            output.push_str(&template[pos..text_end]);
+        }
+        if is_tag {
+            apply_tag(inner, line, &mut blocks, variables)
+                .map_err(|message| format!("line {}: {}", line, message))?;
+        } else if is_active(&blocks) {
+            match variables.get(inner) {
+                Some(value) => output.push_str(value),
+                None => return Err(format!("line {}: unknown variable '{}'", line, inner)),
+            }
+        }
+        pos = end;
+    }
+
+    match blocks.last() {
+        Some(block) => Err(format!(
+            "line {}: '{{% if %}}' is never closed by '{{% endif %}}'",
+            block.line
+        )),
+        None => Ok(output),
+    }
+}
+
+/// Open, continue or close a block for the tag `tag` on `line`
+fn apply_tag(
+    tag: &str,
+    line: usize,
+    blocks: &mut Vec<Block>,
+    variables: &Variables,
+) -> std::result::Result<(), String> {
+    let (keyword, rest) = tag
+        .split_once(char::is_whitespace)
+        .map_or((tag, ""), |(keyword, rest)| (keyword, rest.trim()));
+    let no_argument = || match rest {
+        "" => Ok(()),
+        _ => Err(format!("'{}' takes no condition", keyword)),
+    };
+    let open = |blocks: &mut Vec<Block>| {
+        blocks
+            .pop()
+            .ok_or_else(|| format!("'{}' without an open '{{% if %}}'", keyword))
+    };
+
+    match keyword {
+        "if" => {
+            let holds = condition(rest, variables)?;
+            let outer = blocks.last().is_none_or(|block| block.active);
+            blocks.push(Block {
+                line,
+                outer,
+                taken: holds,
+                active: outer && holds,
+                seen_else: false,
+            });
+        }
+        "elif" | "else" => {
+            let mut block = open(blocks)?;
+            if block.seen_else {
+                return Err(format!("'{}' after '{{% else %}}'", keyword));
+            }
+            let holds = if keyword == "elif" {
+                condition(rest, variables)?
+            } else {
+                no_argument()?;
+                block.seen_else = true;
+                true
+            };
+            block.active = block.outer && !block.taken && holds;
+            block.taken |= holds;
+            blocks.push(block);
+        }
+        "endif" => {
+            no_argument()?;
+            open(blocks)?;
+        }
+        _ => return Err(format!("unknown tag '{}'", keyword)),
+    }
+    Ok(())
+}
+
+/// Evaluate the condition of an `if` or `elif` tag
+fn condition(expression: &str, variables: &Variables) -> std::result::Result<bool, String> {
+    let (negated, expression) = match expression.strip_prefix("not ") {
+        Some(rest) => (true, rest.trim()),
+        None => (false, expression),
+    };
+    let name = |name: &str| {
+        let name = name.trim();
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        match valid {
+            true => Ok(variables.get(name).map(String::as_str)),
+            false => Err(format!("'{}' is not a variable name", name)),
+        }
+    };
+    fn literal(value: &str) -> std::result::Result<&str, String> {
+        let value = value.trim();
+        ['"', '\'']
+            .iter()
+            .find_map(|quote| {
+                value
+                    .strip_prefix(*quote)
+                    .and_then(|rest| rest.strip_suffix(*quote))
+            })
+            .ok_or_else(|| format!("expected a quoted value, found '{}'", value))
+    }
+
+    if expression.is_empty() {
+        return Err("missing condition".to_string());
+    }
+    let holds = if let Some((left, right)) = expression.split_once("==") {
+        name(left)? == Some(literal(right)?)
+    } else if let Some((left, right)) = expression.split_once("!=") {
+        name(left)? != Some(literal(right)?)
+    } else {
+        name(expression)?.is_some_and(|value| !value.is_empty())
+    };
+    Ok(holds != negated)
+}
+
+/// The rendered files directory inside forge's configuration directory
+///
+/// Output is kept there named by its BLAKE3 hash, so journal steps copy it into place,
+/// and back out on undo, like any other file.
+pub(crate) fn store_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join(RENDERED_DIR)
+}
+
+/// What templates are rendered with, and where their output is kept
+pub(crate) struct Renderer {
+    variables: Variables,
+    store: PathBuf,
+}
+
+impl Renderer {
+    /// Render the template at `path`, reporting a failure as a message
+    pub(crate) fn output(&self, path: &Path) -> std::result::Result<String, String> {
+        let template = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read template {}: {}", path.display(), e))?;
+        render(&template, &self.variables)
+            .map_err(|message| format!("{}: {}", path.display(), message))
+    }
+
+    /// Render the template at `path` into the store, returning the stored output and
+    /// its hash
+    ///
+    /// The output takes the template's permissions, so a private template stays private.
+    pub(crate) fn render_file(
+        &self,
+        action: Action,
+        path: &Path,
+    ) -> std::result::Result<(PathBuf, String), Outcome> {
+        let output = self
+            .output(path)
+            .map_err(|message| Outcome::failed(action, path, ForgeError::InvalidInput(message)))?;
+        self.keep(&output, path).map_err(|e| {
+            Outcome::failed(
+                action,
+                path,
+                ForgeError::io(
+                    format!("failed to store the rendered {}", path.display()),
+                    e,
+                ),
+            )
+        })
+    }
+
+    /// Whether the generated file of `dotfile` is no longer what its template renders
+    /// as, including when the template fails to render
+    ///
+    /// The hash recorded at the last render changes when the template or a variable
+    /// does, and `forge sync` renders stale files again.
+    pub(crate) fn is_stale(&self, dotfile: &DotFile) -> bool {
+        let rendered = self
+            .output(&dotfile.target)
+            .map(|output| blake3::hash(output.as_bytes()).to_hex().to_string());
+        rendered.ok() != dotfile.hash
+    }
+
+    fn keep(&self, output: &str, template: &Path) -> io::Result<(PathBuf, String)> {
+        let hash = blake3::hash(output.as_bytes()).to_hex().to_string();
+        let path = self.store.join(&hash);
+        if !path.exists() {
+            fs::create_dir_all(&self.store)?;
+            let temp = atomic::temp_path(&path);
+            fs::write(&temp, output)?;
+            fs::rename(&temp, &path)?;
+        }
+        fs::set_permissions(&path, fs::metadata(template)?.permissions())?;
+        Ok((path, hash))
+    }
+}
+
+/// A template rendered for this machine, for `forge render`
+#[derive(Debug, Clone, Serialize)]
+pub struct Rendered {
+    #[serde(with = "escaped")]
+    pub template: PathBuf,
+    /// Where the output is deployed, if the template is tracked
+    #[serde(with = "escaped_opt")]
+    pub deployed: Option<PathBuf>,
+    pub output: String,
+    /// Whether the deployed file holds something other than the output
+    pub stale: bool,
+}
+
+impl Engine {
+    /// The variables of this machine, with the active managed folder's `[variables]`
+    /// added, and the store rendered output is kept in
+    pub(crate) fn renderer(&self) -> Result<Renderer> {
+        let mut variables = variables(self.config.get_home_dir());
+        if let Ok(Some((_, folder))) = self.config.get_active_managed_folder() {
+            variables.extend(FolderConfig::load(&folder)?.variables);
+        }
+        Ok(Renderer {
+            variables,
+            store: store_dir(self.config.get_config_dir()),
+        })
+    }
+
+    /// Render a template without deploying it
+    ///
+    /// `file` is a tracked template, by either its deployed or managed path, or any
+    /// other file to render as a template.
+    pub fn render(&self, file: &Path) -> Result<Rendered> {
+        let path = path_utils::normalize(file);
+        let tracked = match self.config.find_dotfile_by_source(&path)? {
+            Some(dotfile) => Some(dotfile),
+            None => self.config.find_dotfile_by_target(&path)?,
+        };
+        let (template, deployed) = match tracked {
+            Some(dotfile) if dotfile.mode == DeployMode::Template => {
+                (dotfile.target, Some(dotfile.source))
+            }
+            _ if path.is_file() => (path, None),
+            _ => return Err(ForgeError::NotFound(path)),
+        };
+
+        let output = self
+            .renderer()?
+            .output(&template)
+            .map_err(ForgeError::InvalidInput)?;
+        let stale = deployed
+            .as_ref()
+            .is_some_and(|deployed| fs::read(deployed).ok().as_deref() != Some(output.as_bytes()));
+        Ok(Rendered {
+            template,
+            deployed,
+            output,
+            stale,
+        })
+    }
+}
+
+/// Plan deploying a staged file with `mode = "template"`: the original becomes the
+/// template in the managed folder and is replaced by its rendered output
+///
+/// A template already in the managed folder that renders as the original is kept as
+/// it is. Any other file there is a conflict, and `resolver` decides whether it or the
+/// original is the template.
+pub(crate) fn plan_template(
+    dotfile: &DotFile,
+    resolver: &Resolver,
+    renderer: &Renderer,
+) -> std::result::Result<Entry, Outcome> {
+    let source = &dotfile.source;
+    let target = &dotfile.target;
+    let record = |hash: String| {
+        RecordChange::update(
+            dotfile,
+            DotFile::with_status(
+                source.clone(),
+                target.clone(),
+                dotfile.profile.clone(),
+                DotFileStatus::Linked,
+            )
+            .with_mode(DeployMode::Template)
+            .with_hash(Some(hash)),
+        )
+    };
+
+    if !source.exists() {
+        return Err(Outcome::failed(
+            Action::Link,
+            source,
+            ForgeError::NotFound(source.clone()),
+        ));
+    }
+    if !source.is_file() {
+        return Err(Outcome::failed(
+            Action::Link,
+            source,
+            ForgeError::InvalidInput(format!(
+                "{} is not a file; only files can be deployed as templates",
+                source.display()
+            )),
+        ));
+    }
+
+    let mut entry = Entry::new(source).with_target(target);
+    let copy = Step::Copy {
+        from: source.clone(),
+        to: target.clone(),
+    };
+    let (output, hash) = if symlink::is_symlink(target) || conflict::same_content(source, target) {
+        entry = entry
+            .step(Step::Remove {
+                path: target.clone(),
+            })
+            .step(copy)
+            .with_message("deployed as a generated file");
+        renderer.render_file(Action::Link, source)?
+    } else if fs::symlink_metadata(target).is_ok() {
+        let rendered = renderer.render_file(Action::Link, target);
+        if let Ok((output, hash)) = &rendered
+            && conflict::same_content(source, output)
+        {
+            return Ok(entry
+                .with_record(record(hash.clone()))
+                .with_message("already holds the rendered template"));
+        }
+        let conflict = Conflict::new(Action::Link, source, target);
+        let policy = resolver.resolve(&conflict)?;
+        entry = resolver.apply(entry, policy, &conflict)?;
+        if policy == ConflictPolicy::Adopt {
+            renderer.render_file(Action::Link, source)?
+        } else {
+            rendered?
+        }
+    } else {
+        entry = entry
+            .step(copy)
+            .with_message("deployed as a generated file");
+        renderer.render_file(Action::Link, source)?
+    };
+
+    if !conflict::same_content(source, &output) {
+        entry = entry.step(Step::Replace {
+            path: source.clone(),
+            from: output,
+        });
+    }
+    Ok(entry.with_record(record(hash)))
+}
+
+/// Plan rendering a template again, replacing its generated file if the output changed
+///
+/// A generated file edited since it was rendered is resolved by `resolver`; it cannot
+/// be adopted into its template, so adopting keeps it as it is.
+pub(crate) fn plan_render(
+    dotfile: &DotFile,
+    resolver: &Resolver,
+    renderer: &Renderer,
+) -> std::result::Result<Entry, Outcome> {
+    let deployed = &dotfile.source;
+    let template = &dotfile.target;
+    let recorded = dotfile.hash.as_deref().unwrap_or_default();
+    let entry = Entry::new(deployed).with_target(template);
+
+    if !template.is_file() {
+        return Err(Outcome::failed(
+            Action::Sync,
+            deployed,
+            ForgeError::NotFound(template.clone()),
+        )
+        .with_target(template));
+    }
+    let (output, hash) = renderer.render_file(Action::Sync, template)?;
+    let rendered = RecordChange::update(dotfile, dotfile.clone().with_hash(Some(hash.clone())));
+    if fs::symlink_metadata(deployed).is_err() {
+        return Ok(entry
+            .step(Step::Copy {
+                from: output,
+                to: deployed.clone(),
+            })
+            .with_record(rendered)
+            .with_message("rendered the missing generated file again"));
+    }
+
+    let current = sync::hash_for(Action::Sync, deployed)?;
+    if current == hash {
+        if hash == recorded {
+            return Err(
+                Outcome::skipped(Action::Sync, deployed, "already up to date")
+                    .with_target(template),
+            );
+        }
+        return Ok(entry
+            .with_record(rendered)
+            .with_message("already holds the rendered template"));
+    }
+
+    let mut entry = entry.with_message("rendered the template again");
+    if current != recorded {
+        let conflict = Conflict::new(Action::Render, deployed, template);
+        entry = match resolver.resolve(&conflict)? {
+            ConflictPolicy::Adopt => {
+                return Err(Outcome::skipped(
+                    Action::Sync,
+                    deployed,
+                    "edited since it was rendered; kept it, as a generated file cannot be \
+                     adopted into its template",
+                )
+                .with_target(template));
+            }
+            policy => resolver.apply(entry, policy, &conflict)?,
+        };
+    }
+    Ok(entry
+        .step(Step::Replace {
+            path: deployed.clone(),
+            from: output,
+        })
+        .with_record(rendered))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::test_engine;
+    use crate::engine::{Health, LinkOptions, StageOptions, Status};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_render_replaces_variables() {
+        let variables = Variables::from([
+            ("user".to_string(), "ada".to_string()),
+            ("hostname".to_string(), "engine".to_string()),
+        ]);
+        assert_eq!(
+            render("Host {{hostname}}\n  User {{ user }}\n", &variables).unwrap(),
+            "Host engine\n  User ada\n"
+        );
+        assert_eq!(
+            render("no variables\n", &variables).unwrap(),
+            "no variables\n"
+        );
+        assert_eq!(
+            render("a\n{{ shell }}\n", &variables).unwrap_err(),
+            "line 2: unknown variable 'shell'"
+        );
+        assert!(render("{{ user", &variables).is_err());
+    }
+
+    #[test]
+    fn test_render_keeps_branches_that_hold() {
+        let variables = Variables::from([
+            ("os".to_string(), "linux".to_string()),
+            ("hostname".to_string(), "work-laptop".to_string()),
+            ("email".to_string(), "ada@example.com".to_string()),
+        ]);
+        let template = "\
+[user]
+{% if email %}
+  email = {{ email }}
+{% endif %}
+{% if hostname == \"home-desktop\" %}
+  signingkey = {{ home_key }}
+{% elif not os == 'macos' %}
+  # {{ os }} {% if os != \"linux\" %}other{% else %}only{% endif %}
+{% else %}
+  # macos
+{% endif %}
+";
+        assert_eq!(
+            render(template, &variables).unwrap(),
+            "[user]\n  email = ada@example.com\n  # linux only\n"
+        );
+
+        let error = |template: &str| render(template, &variables).unwrap_err();
+        assert_eq!(
+            error("a\n{% if os %}\nb\n"),
+            "line 2: '{% if %}' is never closed by '{% endif %}'"
+        );
+        assert_eq!(
+            error("{% endif %}"),
+            "line 1: 'endif' without an open '{% if %}'"
+        );
+        assert_eq!(
+            error("{% if os %}{% else %}{% else %}{% endif %}"),
+            "line 1: 'else' after '{% else %}'"
+        );
+        assert_eq!(error("{% for x %}"), "line 1: unknown tag 'for'");
+        assert_eq!(
+            error("{% if os == linux %}{% endif %}"),
+            "line 1: expected a quoted value, found 'linux'"
+        );
+    }
+
+    #[test]
+    fn test_template_deploys_rendered_output() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let deployed = temp.child("home/.gitconfig");
+        deployed
+            .write_str("[include]\n  path = {{ home }}/.gitconfig.local\n")
+            .unwrap();
+        let managed = temp.child("forge/.gitconfig.tmpl");
+        let options = StageOptions {
+            mode: Some(DeployMode::Template),
+            ..Default::default()
+        };
+        engine.stage(&[deployed.to_path_buf()], &options).unwrap();
+        let report = engine.link(&[], &LinkOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+
+        // The managed folder keeps the template and the home directory the output
+        assert_eq!(
+            fs::read_to_string(managed.path()).unwrap(),
+            "[include]\n  path = {{ home }}/.gitconfig.local\n"
+        );
+        assert_eq!(
+            fs::read_to_string(deployed.path()).unwrap(),
+            format!(
+                "[include]\n  path = {}/.gitconfig.local\n",
+                temp.path().join("home").display()
+            )
+        );
+        let health = |engine: &Engine| engine.status(None).unwrap()[0].health;
+        assert_eq!(health(&engine), Health::Ok);
+
+        // Undoing the link puts the original back
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(deployed.path()).unwrap(),
+            "[include]\n  path = {{ home }}/.gitconfig.local\n"
+        );
+
+        // An edited generated file is reported, and kept in place by unlink
+        engine.link(&[], &LinkOptions::default()).unwrap();
+        deployed.write_str("edited by hand\n").unwrap();
+        assert_eq!(health(&engine), Health::CopyChanged);
+        let selections = engine.select(&[managed.to_path_buf()]).unwrap();
+        engine.unlink(&selections).unwrap();
+        assert!(engine.list(None).unwrap().is_empty());
+        assert_eq!(
+            fs::read_to_string(deployed.path()).unwrap(),
+            "edited by hand\n"
+        );
+    }
+
+    #[test]
+    fn test_stale_templates_render_again() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let folder = temp.path().join("forge");
+        let mut settings = FolderConfig::load(&folder).unwrap();
+        settings
+            .variables
+            .insert("email".to_string(), "ada@example.com".to_string());
+        settings.save(&folder).unwrap();
+
+        // A template from another machine is picked up when its file is staged
+        let managed = temp.child("forge/.gitconfig.tmpl");
+        managed
+            .write_str("[user]\n  email = {{ email }}\n")
+            .unwrap();
+        let deployed = temp.child("home/.gitconfig");
+        deployed
+            .write_str("[user]\n  email = ada@example.com\n")
+            .unwrap();
+        let report = engine
+            .stage(&[deployed.to_path_buf()], &StageOptions::default())
+            .unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        let report = engine.link(&[], &LinkOptions::default()).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        let health = |engine: &Engine| engine.status(None).unwrap()[0].health;
+        assert_eq!(health(&engine), Health::Ok);
+
+        // A changed variable leaves the generated file stale until it is synced
+        settings
+            .variables
+            .insert("email".to_string(), "ada@work.example".to_string());
+        settings.save(&folder).unwrap();
+        assert_eq!(health(&engine), Health::Stale);
+        let preview = engine.render(deployed.path()).unwrap();
+        assert!(preview.stale);
+        assert_eq!(preview.output, "[user]\n  email = ada@work.example\n");
+
+        let report = engine.sync(&[], None).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert_eq!(
+            fs::read_to_string(deployed.path()).unwrap(),
+            "[user]\n  email = ada@work.example\n"
+        );
+        assert_eq!(health(&engine), Health::Ok);
+        let report = engine.sync(&[], None).unwrap();
+        assert_eq!(report.outcomes[0].status, Status::Skipped);
+
+        // A generated file edited by hand is only replaced by a policy
+        deployed.write_str("[user]\n").unwrap();
+        managed
+            .write_str("[user]\n  name = Ada\n  email = {{ email }}\n")
+            .unwrap();
+        assert_eq!(health(&engine), Health::CopyChanged);
+        assert_eq!(engine.sync(&[], None).unwrap().failed(), 1);
+        let report = engine.sync(&[], Some(ConflictPolicy::Adopt)).unwrap();
+        assert_eq!(report.outcomes[0].status, Status::Skipped);
+        let report = engine.sync(&[], Some(ConflictPolicy::Overwrite)).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert_eq!(
+            fs::read_to_string(deployed.path()).unwrap(),
+            "[user]\n  name = Ada\n  email = ada@work.example\n"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+// The freedesktop.org trash that `forge delete` moves files into
+use super::Engine;
+use super::journal::{self, Step};
+use chrono::Local;
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const TRASH_DIR: &str = "Trash";
+const FILES_DIR: &str = "files";
+const INFO_DIR: &str = "info";
+const INFO_EXTENSION: &str = ".trashinfo";
+
+/// Plans the names paths are given in the trash's `files/` directory
+///
+/// Names already taken in the trash, and names handed out earlier in the same batch,
+/// are never reused.
+pub(crate) struct Trash {
+    dir: PathBuf,
+    reserved: HashSet<OsString>,
+}
+
+impl Trash {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            reserved: HashSet::new(),
+        }
+    }
+
+    /// The step that moves `path` into the trash under a free name
+    pub(crate) fn step(&mut self, path: &Path) -> Step {
+        let name = path.file_name().unwrap_or(OsStr::new("unnamed"));
+        let mut candidate = name.to_os_string();
+        let mut n = 1;
+        while self.is_taken(&candidate) {
+            n += 1;
+            candidate = name.to_os_string();
+            candidate.push(format!(".{}", n));
+        }
+        self.reserved.insert(candidate.clone());
+        Step::Trash {
+            path: path.to_path_buf(),
+            to: self.dir.join(FILES_DIR).join(candidate),
+        }
+    }
+
+    fn is_taken(&self, name: &OsStr) -> bool {
+        self.reserved.contains(name)
+            || fs::symlink_metadata(self.dir.join(FILES_DIR).join(name)).is_ok()
+            || fs::symlink_metadata(info_path(&self.dir.join(FILES_DIR).join(name))).is_ok()
+    }
+}
+
+/// The `.trashinfo` file describing the trashed path `trashed`
+///
+/// It records where the path came from and when it was deleted, so file managers can
+/// list and restore it.
+pub(crate) fn info_path(trashed: &Path) -> PathBuf {
+    let trash = trashed
+        .parent()
+        .and_then(Path::parent)
+        .unwrap_or(Path::new(""));
+    let mut name = trashed.file_name().unwrap_or_default().to_os_string();
+    name.push(INFO_EXTENSION);
+    trash.join(INFO_DIR).join(name)
+}
+
+/// Move `path` into the trash as `to`, writing its trash info first
+///
+/// The info file is created exclusively, as the spec asks, so two programs trashing
+/// at once cannot claim the same name.
+pub(crate) fn trash(path: &Path, to: &Path) -> io::Result<()> {
+    let info = info_path(to);
+    if let Some(dir) = info.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&info)?;
+    let written = file.write_all(trash_info(path).as_bytes());
+    let moved = written.and_then(|()| journal::move_path(path, to));
+    if moved.is_err() {
+        let _ = fs::remove_file(&info);
+    }
+    moved
+}
+
+/// Move the trashed path `from` back to `to` and drop its trash info
+pub(crate) fn restore(from: &Path, to: &Path) -> io::Result<()> {
+    journal::move_path(from, to)?;
+    forget(from)
+}
+
+/// Drop the trash info of `trashed`, if there is any
+pub(crate) fn forget(trashed: &Path) -> io::Result<()> {
+    match fs::remove_file(info_path(trashed)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// The contents of a `.trashinfo` file for `path`, deleted now
+fn trash_info(path: &Path) -> String {
+    format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode(path),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    )
+}
+
+/// Percent-encode a path as the spec's `Path` key expects, keeping `/` as is
+fn encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+impl Engine {
+    /// The home trash, `$XDG_DATA_HOME/Trash`
+    ///
+    /// Forge always uses the home trash, copying paths that live on another filesystem.
+    pub(crate) fn trash(&self) -> Trash {
+        Trash::new(self.config.get_data_home().join(TRASH_DIR))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::test_engine;
+    use crate::engine::{LinkOptions, Selection, StageOptions};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_delete_moves_files_to_the_trash() {
+        let temp = TempDir::new().unwrap();
+        let engine = test_engine(&temp);
+        let trash = temp.child("home/.local/share/Trash");
+        let tracked = |engine: &Engine| -> Vec<Selection> {
+            let dotfiles = engine.list(None).unwrap();
+            dotfiles.into_iter().map(Selection::Tracked).collect()
+        };
+
+        // The trash already holds a .bashrc, so the new one is numbered
+        trash.child("files/.bashrc").write_str("old\n").unwrap();
+        let file = temp.child("home/.bashrc");
+        file.write_str("alias ll='ls -l'\n").unwrap();
+        engine
+            .stage(&[file.to_path_buf()], &StageOptions::default())
+            .unwrap();
+        engine.link(&[], &LinkOptions::default()).unwrap();
+
+        let report = engine.delete(&tracked(&engine), false).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert!(fs::symlink_metadata(file.path()).is_err());
+        assert!(!temp.child("forge/.bashrc").exists());
+        assert_eq!(
+            fs::read_to_string(trash.child("files/.bashrc.2").path()).unwrap(),
+            "alias ll='ls -l'\n"
+        );
+        let info = fs::read_to_string(trash.child("info/.bashrc.2.trashinfo").path()).unwrap();
+        let forge = temp.path().join("forge/.bashrc");
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains(&format!("Path={}\n", encode(&forge))));
+        assert!(info.contains("DeletionDate="));
+
+        // Undo takes the file back out of the trash
+        engine.undo(&engine.undo_candidate(None).unwrap()).unwrap();
+        assert!(fs::read_link(file.path()).is_ok());
+        assert!(!trash.child("files/.bashrc.2").exists());
+        assert!(!trash.child("info/.bashrc.2.trashinfo").exists());
+
+        // A purge leaves nothing behind and cannot be undone
+        let report = engine.delete(&tracked(&engine), true).unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        assert!(!temp.child("forge/.bashrc").exists());
+        assert!(!trash.child("files/.bashrc.2").exists());
+        let purge = &engine.history(Some(1)).unwrap()[0];
+        assert!(engine.undo_candidate(Some(&purge.id)).is_err());
+        assert_ne!(engine.undo_candidate(None).unwrap().id, purge.id);
+    }
+
+    #[test]
+    fn test_trash_info_paths_are_percent_encoded() {
+        assert_eq!(
+            encode(Path::new("/home/me/my notes#1.txt")),
+            "/home/me/my%20notes%231.txt"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+// Error type shared by the library and the process exit codes derived from it
+use serde::Serialize;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Process exit codes used by the forge binary
+pub mod exit_code {
+    /// Every file was processed successfully
+    pub const SUCCESS: u8 = 0;
+    /// The operation could not run at all (no managed folder, database or archive errors)
+    pub const FATAL: u8 = 1;
+    /// Invalid command-line usage, as reported by the argument parser
+    pub const USAGE: u8 = 2;
+    /// One or more files failed while others may have succeeded
+    pub const PARTIAL_FAILURE: u8 = 3;
+    /// One or more files conflicted with existing paths and were left untouched
+    pub const CONFLICT: u8 = 4;
+    /// `forge status --check` found drifted files, or `forge db check` found pending migrations
+    pub const DRIFT: u8 = 5;
+}
+
+#[derive(Debug, Error)]
+pub enum ForgeError {
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("{} already exists", .0.display())]
+    Conflict(PathBuf),
+
+    #[error("No managed folders found. Please run 'forge init' first.")]
+    NoManagedFolder,
+
+    #[error("{} does not exist", .0.display())]
+    NotFound(PathBuf),
+
+    #[error("{} is not tracked by forge", .0.display())]
+    NotTracked(PathBuf),
+
+    #[error("hash mismatch for {path}: expected {expected}, got {actual}")]
+    HashMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("archive error: {0}")]
+    Archive(String),
+
+    #[error("invalid pack manifest: {0}")]
+    Manifest(String),
+
+    #[error("{0}")]
+    InvalidInput(String),
+
+    #[error("an interrupted {0} needs recovery; run 'forge recover' first")]
+    RecoveryNeeded(String),
+}
+
+/// Broad classification of a [`ForgeError`], kept on per-file outcomes
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Database,
+    Io,
+    Conflict,
+    NoManagedFolder,
+    NotFound,
+    NotTracked,
+    HashMismatch,
+    Archive,
+    InvalidInput,
+    RecoveryNeeded,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Database => "database",
+            ErrorKind::Io => "io",
+            ErrorKind::Conflict => "conflict",
+            ErrorKind::NoManagedFolder => "no_managed_folder",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::NotTracked => "not_tracked",
+            ErrorKind::HashMismatch => "hash_mismatch",
+            ErrorKind::Archive => "archive",
+            ErrorKind::InvalidInput => "invalid_input",
+            ErrorKind::RecoveryNeeded => "recovery_needed",
+        }
+    }
+}
+
+impl ForgeError {
+    /// Wrap an I/O error with a description of what was being attempted
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        ForgeError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ForgeError::Database(_) => ErrorKind::Database,
+            ForgeError::Io { .. } => ErrorKind::Io,
+            ForgeError::Conflict(_) => ErrorKind::Conflict,
+            ForgeError::NoManagedFolder => ErrorKind::NoManagedFolder,
+            ForgeError::NotFound(_) => ErrorKind::NotFound,
+            ForgeError::NotTracked(_) => ErrorKind::NotTracked,
+            ForgeError::HashMismatch { .. } => ErrorKind::HashMismatch,
+            ForgeError::Archive(_) => ErrorKind::Archive,
+            ForgeError::Manifest(_) => ErrorKind::Archive,
+            ForgeError::InvalidInput(_) => ErrorKind::InvalidInput,
+            ForgeError::RecoveryNeeded(_) => ErrorKind::RecoveryNeeded,
+        }
+    }
+
+    /// Exit code for an operation that stopped with this error
+    pub fn exit_code(&self) -> u8 {
+        match self.kind() {
+            ErrorKind::Conflict => exit_code::CONFLICT,
+            _ => exit_code::FATAL,
+        }
+    }
+}
+
+impl From<io::Error> for ForgeError {
+    fn from(source: io::Error) -> Self {
+        ForgeError::io("I/O error", source)
+    }
+}
+
+impl From<walkdir::Error> for ForgeError {
+    fn from(error: walkdir::Error) -> Self {
+        let context = match error.path() {
+            Some(path) => format!("failed to read {}", path.display()),
+            None => "failed to walk directory".to_string(),
+        };
+        ForgeError::io(context, error.into())
+    }
+}
+
+impl From<zip::result::ZipError> for ForgeError {
+    fn from(error: zip::result::ZipError) -> Self {
+        ForgeError::Archive(error.to_string())
+    }
+}
+
+impl From<toml::de::Error> for ForgeError {
+    fn from(error: toml::de::Error) -> Self {
+        ForgeError::Manifest(error.to_string())
+    }
+}
+
+impl From<toml::ser::Error> for ForgeError {
+    fn from(error: toml::ser::Error) -> Self {
+        ForgeError::Manifest(error.to_string())
+    }
+}
+
+pub type Result<T, E = ForgeError> = std::result::Result<T, E>;
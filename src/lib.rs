@@ -0,0 +1,17 @@
+//! Forge manages dotfiles by moving them into managed folders and symlinking them
+//! back into place.
+//!
+//! The [`Engine`] type drives every mutating operation and returns a [`Report`] of
+//! per-file outcomes, so tools can use forge without parsing its terminal output.
+
+pub mod config;
+pub mod dotfile;
+pub mod engine;
+pub mod error;
+pub mod pack;
+pub mod scanner;
+pub mod symlink;
+pub mod utils;
+
+pub use engine::{Action, Engine, LinkOptions, Outcome, Report, Selection, StageOptions, Status};
+pub use error::{ErrorKind, ForgeError, Result};
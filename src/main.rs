@@ -1,12 +1,11 @@
 use clap::{Parser, Subcommand};
+use cli::render::Format;
+use forge_rs::config::folder::{ConflictPolicy, DeployMode, LinkStyle};
+use forge_rs::engine::{ImportOptions, Layout, StowOptions};
 use std::path::PathBuf;
+use std::process::ExitCode;
 
 mod cli;
-mod config;
-mod dotfile;
-mod scanner;
-mod symlink;
-mod utils;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -15,6 +14,10 @@ struct Cli {
     #[arg(short = 'I', long)]
     interactive: bool,
 
+    /// Output format for command results
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -30,6 +33,18 @@ enum Commands {
         /// Directory to initialize (defaults to current directory)
         #[arg(short, long)]
         dir: Option<PathBuf>,
+        
+        /// How staged files are placed in the folder: home, package or flat
+        #[arg(long)]
+        layout: Option<Layout>,
+        
+        /// Link files with relative symlinks by default
+        #[arg(long)]
+        relative: bool,
+        
+        /// How conflicts are resolved by default: backup, skip, overwrite, adopt or prompt
+        #[arg(long, value_name = "POLICY")]
+        on_conflict: Option<ConflictPolicy>,
     },
     /// Stage files or directories for tracking (temporary, requires linking to make permanent)
     Stage {
@@ -43,11 +58,55 @@ enum Commands {
         /// Maximum recursion depth for directories (overrides --recursive)
         #[arg(long)]
         depth: Option<usize>,
+        
+        /// How to place files in the managed folder: home, package or flat
+        #[arg(long)]
+        layout: Option<Layout>,
+        
+        /// Package directory for the package layout (implies --layout package)
+        #[arg(long, value_name = "NAME")]
+        package: Option<String>,
+        
+        /// How to deploy the files once linked: symlink, hardlink, copy or template
+        #[arg(long)]
+        mode: Option<DeployMode>,
     },
     /// Create symlinks for staged/tracked files
     Link {
         /// Files to link (if not specified, links all staged files)
         files: Vec<PathBuf>,
+        
+        /// Write symlinks relative to their directory
+        #[arg(long, conflicts_with = "absolute")]
+        relative: bool,
+        
+        /// Write symlinks as absolute paths
+        #[arg(long)]
+        absolute: bool,
+        
+        /// How to resolve a forge copy that differs from the file: backup, skip, overwrite, adopt or prompt
+        #[arg(long, value_name = "POLICY")]
+        on_conflict: Option<ConflictPolicy>,
+    },
+    /// Replace folded directory symlinks with one symlink per file
+    Unfold {
+        /// Folded directories to unfold
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+    },
+    /// Copy changes between files deployed as copies and their forge copies, and render stale templates again
+    Sync {
+        /// Files to sync (if not specified, syncs every copy and template)
+        files: Vec<PathBuf>,
+        
+        /// How to resolve a file changed on both sides, or a generated file edited by hand: backup, skip, overwrite, adopt or prompt
+        #[arg(long, value_name = "POLICY")]
+        on_conflict: Option<ConflictPolicy>,
+    },
+    /// Print what a template renders as on this machine, without deploying it
+    Render {
+        /// Template to render, by its deployed or forge path
+        file: PathBuf,
     },
     /// Remove symlinks but keep files in forge folder
     Unlink {
@@ -67,7 +126,7 @@ enum Commands {
         #[arg(short, long)]
         yes: bool,
     },
-    /// Delete files completely from the system
+    /// Delete files from the system, moving them to the trash
     Delete {
         /// Files to delete
         files: Vec<PathBuf>,
@@ -75,6 +134,10 @@ enum Commands {
         /// Skip confirmation prompt (USE WITH CAUTION)
         #[arg(short, long)]
         yes: bool,
+        
+        /// Delete permanently instead of moving to the trash; cannot be undone
+        #[arg(long)]
+        purge: bool,
     },
     /// List tracked files or profiles
     List {
@@ -86,10 +149,125 @@ enum Commands {
         #[arg(short, long)]
         profile: Option<String>,
     },
+    /// Check tracked files for drift between the database and the filesystem
+    Status {
+        /// Only report drifted files and exit with status 5 if any are found
+        #[arg(long)]
+        check: bool,
+        
+        /// Filter by profile name
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+    /// Diagnose drifted files and optionally repair them
+    Doctor {
+        /// Repair drifted files, asking before each repair
+        #[arg(long)]
+        fix: bool,
+        
+        /// Show the repairs that would be made without making them
+        #[arg(long)]
+        dry_run: bool,
+        
+        /// Skip confirmation prompts
+        #[arg(short, long)]
+        yes: bool,
+        
+        /// Filter by profile name
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+    /// Roll back an interrupted batch of file operations
+    Recover {
+        /// Finish the interrupted operation instead of rolling it back
+        #[arg(long)]
+        resume: bool,
+    },
+    /// Show the history of operations that changed files
+    Log {
+        /// Show at most this many operations
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+        
+        /// List the filesystem steps of each operation
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Reverse a recorded operation (defaults to the most recent one)
+    Undo {
+        /// Id of the operation to undo, as shown by 'forge log'
+        id: Option<String>,
+        
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Manage the versions kept in the backup store
+    Backups {
+        #[command(subcommand)]
+        action: BackupActions,
+    },
+    /// Rebuild the tracking database from symlinks into managed folders
+    Reindex {
+        /// Directory to search for symlinks (defaults to the home directory; repeatable)
+        #[arg(long = "root", value_name = "DIR")]
+        roots: Vec<PathBuf>,
+        
+        /// Show the records that would be created without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Adopt files already managed by another tool
+    Import {
+        #[command(subcommand)]
+        source: ImportSources,
+    },
+    /// Stow, unstow or restow packages with GNU Stow's arguments
+    Stow {
+        /// Packages to stow
+        packages: Vec<String>,
+        
+        /// Stow directory holding the packages (defaults to the current directory)
+        #[arg(short = 'd', long = "dir", value_name = "DIR")]
+        dir: Option<PathBuf>,
+        
+        /// Directory to stow into (defaults to the parent of the stow directory)
+        #[arg(short = 't', long = "target", value_name = "DIR")]
+        target: Option<PathBuf>,
+        
+        /// Stow the packages that follow
+        #[arg(short = 'S', long = "stow", value_name = "PACKAGE", num_args = 1..)]
+        stow: Vec<String>,
+        
+        /// Unstow the packages that follow
+        #[arg(short = 'D', long = "delete", value_name = "PACKAGE", num_args = 1..)]
+        delete: Vec<String>,
+        
+        /// Unstow and stow again the packages that follow
+        #[arg(short = 'R', long = "restow", value_name = "PACKAGE", num_args = 1..)]
+        restow: Vec<String>,
+        
+        /// Show what would be done without changing anything
+        #[arg(short = 'n', long = "no", visible_alias = "simulate")]
+        simulate: bool,
+        
+        /// Accepted for compatibility; every file is always reported
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+    },
+    /// Inspect or upgrade the tracking database schema
+    Db {
+        #[command(subcommand)]
+        action: DbActions,
+    },
     /// Switch to a profile
     Switch {
         /// Profile name
         name: String,
+        
+        /// How to resolve files in the way: backup, skip, overwrite, adopt or prompt
+        #[arg(long, value_name = "POLICY")]
+        on_conflict: Option<ConflictPolicy>,
     },
     /// Create a new profile at a specific location
     New {
@@ -137,10 +315,13 @@ enum Commands {
     Install {
         /// Path to the pack archive (.zip file)
         archive: PathBuf,
-        /// Skip conflict warnings and overwrite existing files
-        #[arg(short, long)]
+        /// How to resolve files that already exist: backup, skip, overwrite, adopt or prompt
+        #[arg(long, value_name = "POLICY", conflicts_with_all = ["force", "skip_existing"])]
+        on_conflict: Option<ConflictPolicy>,
+        /// Overwrite existing files (same as --on-conflict overwrite)
+        #[arg(short, long, conflicts_with = "skip_existing")]
         force: bool,
-        /// Skip files that already exist (opposite of --force)
+        /// Skip files that already exist (same as --on-conflict skip)
         #[arg(long)]
         skip_existing: bool,
         /// Target directory for installation (defaults to current working directory)
@@ -157,10 +338,13 @@ enum Commands {
     Restore {
         /// Path to the pack archive (.zip file)
         archive: PathBuf,
-        /// Skip conflict warnings and overwrite existing files
-        #[arg(short, long)]
+        /// How to resolve files that already exist: backup, skip, overwrite, adopt or prompt
+        #[arg(long, value_name = "POLICY", conflicts_with_all = ["force", "skip_existing"])]
+        on_conflict: Option<ConflictPolicy>,
+        /// Overwrite existing files (same as --on-conflict overwrite)
+        #[arg(short, long, conflicts_with = "skip_existing")]
         force: bool,
-        /// Skip files that already exist (opposite of --force)
+        /// Skip files that already exist (same as --on-conflict skip)
         #[arg(long)]
         skip_existing: bool,
         /// Test mode: restore to current directory using filenames only (safe for testing)
@@ -211,6 +395,84 @@ enum StartActions {
     },
 }
 
+#[derive(Subcommand)]
+enum ImportSources {
+    /// Adopt the symlinks a GNU Stow directory has created
+    Stow {
+        /// Stow directory holding one directory per package
+        dir: PathBuf,
+        
+        /// Directory the packages are stowed into (defaults to the parent of the stow directory)
+        #[arg(short, long, value_name = "DIR")]
+        target: Option<PathBuf>,
+        
+        /// Name to register the stow directory under (defaults to its directory name)
+        #[arg(long)]
+        name: Option<String>,
+        
+        /// Show the links that would be adopted without recording them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbActions {
+    /// Apply pending schema migrations
+    Migrate {
+        /// Show the migrations that would be applied without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show the schema version and exit with status 5 if migrations are pending
+    Check,
+}
+
+#[derive(Subcommand)]
+enum BackupActions {
+    /// List stored backups, newest first
+    List {
+        /// Only list backups of this path
+        path: Option<PathBuf>,
+    },
+    /// Show a backup's details and content
+    Show {
+        /// Id of the backup, or a unique prefix of it
+        id: String,
+    },
+    /// Put a backup back at its original path
+    Restore {
+        /// Id of the backup, or a unique prefix of it
+        id: String,
+        
+        /// Restore to this path instead of the original one
+        #[arg(long, value_name = "PATH")]
+        to: Option<PathBuf>,
+        
+        /// How a different file at the path is resolved: backup, skip, overwrite or prompt
+        #[arg(long, value_name = "POLICY")]
+        on_conflict: Option<ConflictPolicy>,
+    },
+    /// Drop the backups and history archives the retention policy no longer keeps
+    Prune {
+        /// Keep this many versions of each path
+        #[arg(long, value_name = "N")]
+        keep: Option<usize>,
+        
+        /// Drop versions older than this many days (the newest of each path is always kept)
+        #[arg(long, value_name = "DAYS")]
+        max_age: Option<u32>,
+        
+        /// Show what would be dropped without dropping it
+        #[arg(long)]
+        dry_run: bool,
+        
+        /// Save --keep and --max-age as the retention policy
+        #[arg(long)]
+        save: bool,
+    },
+}
+
 #[derive(Subcommand)]
 enum ProfileActions {
     /// Create a new profile
@@ -227,90 +489,176 @@ enum ProfileActions {
     },
 }
 
-fn main() {
+fn main() -> ExitCode {
     let cli = Cli::parse();
+    cli::render::set_format(cli.format);
 
     match &cli.command {
-        Some(Commands::Init { name, dir }) => {
-            cli::commands::init_command(name.as_deref(), dir.as_deref());
+        Some(Commands::Init { name, dir, layout, relative, on_conflict }) => {
+            let links = relative.then_some(LinkStyle::Relative);
+            cli::commands::init_command(name.as_deref(), dir.as_deref(), *layout, links, *on_conflict)
+        }
+        Some(Commands::Stage { files, recursive, depth, layout, package, mode }) => {
+            cli::commands::stage_command(files, *recursive, *depth, *layout, package.as_deref(), *mode)
+        }
+        Some(Commands::Link { files, relative, absolute, on_conflict }) => {
+            let style = match (relative, absolute) {
+                (true, _) => Some(LinkStyle::Relative),
+                (_, true) => Some(LinkStyle::Absolute),
+                _ => None,
+            };
+            cli::commands::link_command(files, style, *on_conflict)
         }
-        Some(Commands::Stage { files, recursive, depth }) => {
-            cli::commands::stage_command(files, *recursive, *depth);
+        Some(Commands::Unfold { dirs }) => {
+            cli::commands::unfold_command(dirs)
         }
-        Some(Commands::Link { files }) => {
-            cli::commands::link_command(files);
+        Some(Commands::Sync { files, on_conflict }) => {
+            cli::commands::sync_command(files, *on_conflict)
+        }
+        Some(Commands::Render { file }) => {
+            cli::commands::render_command(file)
         }
         Some(Commands::Unlink { files, yes }) => {
-            cli::commands::unlink_command(files, *yes);
+            cli::commands::unlink_command(files, *yes)
         }
         Some(Commands::Remove { files, yes }) => {
-            cli::commands::remove_command(files, *yes);
+            cli::commands::remove_command(files, *yes)
         }
-        Some(Commands::Delete { files, yes }) => {
-            cli::commands::delete_command(files, *yes);
+        Some(Commands::Delete { files, yes, purge }) => {
+            cli::commands::delete_command(files, *yes, *purge)
         }
         Some(Commands::List { profile, profiles }) => {
             if *profiles {
-                cli::commands::profile::list();
+                cli::commands::profile::list()
             } else {
-                cli::commands::list_command(profile.as_deref());
+                cli::commands::list_command(profile.as_deref())
+            }
+        }
+        Some(Commands::Status { check, profile }) => {
+            cli::commands::status_command(profile.as_deref(), *check)
+        }
+        Some(Commands::Doctor { fix, dry_run, yes, profile }) => {
+            cli::commands::doctor_command(profile.as_deref(), *fix, *dry_run, *yes)
+        }
+        Some(Commands::Recover { resume }) => {
+            cli::commands::recover_command(*resume)
+        }
+        Some(Commands::Log { limit, verbose }) => {
+            cli::commands::log_command(*limit, *verbose)
+        }
+        Some(Commands::Undo { id, yes }) => {
+            cli::commands::undo_command(id.as_deref(), *yes)
+        }
+        Some(Commands::Backups { action }) => match action {
+            BackupActions::List { path } => cli::commands::backups::list(path.as_deref()),
+            BackupActions::Show { id } => cli::commands::backups::show(id),
+            BackupActions::Restore { id, to, on_conflict } => {
+                cli::commands::backups::restore(id, to.as_deref(), *on_conflict)
+            }
+            BackupActions::Prune { keep, max_age, dry_run, save } => {
+                cli::commands::backups::prune(*keep, *max_age, *dry_run, *save)
+            }
+        },
+        Some(Commands::Reindex { roots, dry_run }) => {
+            cli::commands::reindex_command(roots, *dry_run)
+        }
+        Some(Commands::Import { source }) => match source {
+            ImportSources::Stow { dir, target, name, dry_run } => {
+                let options = ImportOptions {
+                    target: target.clone(),
+                    name: name.clone(),
+                    dry_run: *dry_run,
+                };
+                cli::commands::stow::import(dir, &options)
             }
+        },
+        Some(Commands::Stow { packages, dir, target, stow, delete, restow, simulate, .. }) => {
+            let options = StowOptions {
+                dir: dir.clone().unwrap_or_else(|| PathBuf::from(".")),
+                target: target.clone(),
+                stow: packages.iter().chain(stow).cloned().collect(),
+                delete: delete.clone(),
+                restow: restow.clone(),
+                simulate: *simulate,
+            };
+            cli::commands::stow::stow(&options)
         }
-        Some(Commands::Switch { name }) => {
-            cli::commands::profile::switch(name);
+        Some(Commands::Db { action }) => match action {
+            DbActions::Migrate { dry_run } => cli::commands::db::migrate(*dry_run),
+            DbActions::Check => cli::commands::db::check(),
+        },
+        Some(Commands::Switch { name, on_conflict }) => {
+            cli::commands::profile::switch(name, *on_conflict)
         }
         Some(Commands::New { profile, path }) => {
             // Initialize the directory as a forge managed folder with the profile name
-            cli::commands::init_command(Some(profile), Some(path.as_path()));
+            cli::commands::init_command(Some(profile), Some(path.as_path()), None, None, None)
         }
         Some(Commands::Profile { action }) => match action {
             ProfileActions::Create { name } => {
-                println!("Note: This command is deprecated, please use 'forge new --profile {}' instead", name);
-                cli::commands::profile::create(name);
+                eprintln!("Note: This command is deprecated, please use 'forge new --profile {}' instead", name);
+                cli::commands::profile::create(name)
             }
             ProfileActions::List => {
-                println!("Note: This command is deprecated, please use 'forge list --profiles' instead");
-                cli::commands::profile::list();
+                eprintln!("Note: This command is deprecated, please use 'forge list --profiles' instead");
+                cli::commands::profile::list()
             }
             ProfileActions::Switch { name } => {
-                println!("Note: This command is deprecated, please use 'forge switch {}' instead", name);
-                cli::commands::profile::switch(name);
+                eprintln!("Note: This command is deprecated, please use 'forge switch {}' instead", name);
+                cli::commands::profile::switch(name, None)
             }
         },
         Some(Commands::Start { action }) => match action {
             StartActions::Packing { scope } => {
-                cli::commands::pack::start_packing(scope);
+                cli::commands::pack::start_packing(scope)
             }
         },
         Some(Commands::Pack { files, scope, recursive, depth, dry_run }) => {
-            cli::commands::pack::pack_files(files, scope.as_deref(), *recursive, *depth, *dry_run);
+            cli::commands::pack::pack_files(files, scope.as_deref(), *recursive, *depth, *dry_run)
         }
         Some(Commands::Seal { scope }) => {
-            cli::commands::pack::seal_pack(scope.as_deref());
+            cli::commands::pack::seal_pack(scope.as_deref())
         }
-        Some(Commands::Install { archive, force, skip_existing, target, map_home, dry_run }) => {
-            cli::commands::pack::install_pack(archive, *force, *skip_existing, target.as_deref(), *map_home, *dry_run);
+        Some(Commands::Install { archive, on_conflict, force, skip_existing, target, map_home, dry_run }) => {
+            let on_conflict = conflict_policy(*on_conflict, *force, *skip_existing);
+            cli::commands::pack::install_pack(archive, on_conflict, target.as_deref(), *map_home, *dry_run)
         }
-        Some(Commands::Restore { archive, force, skip_existing, test, dry_run }) => {
-            cli::commands::pack::restore_pack(archive, *force, *skip_existing, *test, *dry_run);
+        Some(Commands::Restore { archive, on_conflict, force, skip_existing, test, dry_run }) => {
+            let on_conflict = conflict_policy(*on_conflict, *force, *skip_existing);
+            cli::commands::pack::restore_pack(archive, on_conflict, *test, *dry_run)
         }
         Some(Commands::Repack { scope, files }) => {
-            cli::commands::pack::repack_files(scope.as_deref(), files);
+            cli::commands::pack::repack_files(scope.as_deref(), files)
         }
         Some(Commands::Unpack { files, scope }) => {
-            cli::commands::pack::unpack_files(files, scope.as_deref());
+            cli::commands::pack::unpack_files(files, scope.as_deref())
         }
         Some(Commands::Explain { archive, install, restore, target }) => {
-            cli::commands::pack::explain_pack(archive, *install, *restore, target.as_deref());
+            cli::commands::pack::explain_pack(archive, *install, *restore, target.as_deref())
         }
-        None => {
-            if cli.interactive {
+        None if cli.interactive => {
+            // TODO: implement interactive mode
+            if cli::render::is_text() {
                 println!("Starting interactive mode");
-                // TODO: implement interactive mode
-            } else {
-                println!("No command provided. Use --help for more information.");
             }
+            ExitCode::SUCCESS
         }
+        None => {
+            cli::render::error(
+                "No command provided. Use --help for more information.",
+                Some(forge_rs::ErrorKind::InvalidInput),
+            );
+            ExitCode::from(forge_rs::error::exit_code::USAGE)
+        }
+    }
+}
+
+/// The conflict policy for install and restore, where --force and --skip-existing are shorthands
+fn conflict_policy(on_conflict: Option<ConflictPolicy>, force: bool, skip_existing: bool) -> Option<ConflictPolicy> {
+    match (force, skip_existing) {
+        (true, _) => Some(ConflictPolicy::Overwrite),
+        (_, true) => Some(ConflictPolicy::Skip),
+        _ => on_conflict,
     }
 }
 
@@ -397,6 +745,16 @@ mod tests {
         use assert_fs::TempDir;
         use assert_fs::prelude::*;
         
+        /// Run forge with its home, configuration and data all inside `home`
+        fn forge(home: &TempDir, args: &[&str]) -> Command {
+            let mut cmd = Command::cargo_bin("forge").unwrap();
+            cmd.env("HOME", home.path())
+                .env("XDG_CONFIG_HOME", home.path().join(".config"))
+                .env("XDG_DATA_HOME", home.path().join(".local/share"))
+                .args(args);
+            cmd
+        }
+        
         #[test]
         fn test_cli_help() {
             let mut cmd = Command::cargo_bin("forge").unwrap();
@@ -406,19 +764,242 @@ mod tests {
         }
         
         #[test]
-        fn test_add_command() {
-            // Create a temporary directory for our test files
-            let temp = TempDir::new().unwrap();
-            let test_file = temp.child("test_file");
+        fn test_stage_command() {
+            // Run against a temporary home so the real configuration is untouched
+            let home = TempDir::new().unwrap();
+            let test_file = home.child("test_file");
             test_file.touch().unwrap();
             
-            let mut cmd = Command::cargo_bin("forge").unwrap();
-            cmd.arg("add").arg(test_file.path());
+            let dotfiles = home.path().join("dotfiles");
+            forge(&home, &["init", "--name", "default", "--dir", dotfiles.to_str().unwrap()])
+                .assert().success();
             
-            // For now just check that the command runs without error
-            // Later we'll check that the file is actually added
-            cmd.assert().success()
-                .stdout(predicates::str::contains("Adding files:"));
+            forge(&home, &["stage", test_file.path().to_str().unwrap()])
+                .assert().success()
+                .stdout(predicates::str::contains("Staged:"));
+            assert!(home.child("dotfiles/test_file").path().is_symlink());
+        }
+
+        #[test]
+        fn test_exit_codes() {
+            let home = TempDir::new().unwrap();
+
+            // Nothing can run before a managed folder exists
+            forge(&home, &["link"]).assert().code(1)
+                .stderr(predicates::str::contains("forge init"));
+
+            let dotfiles = home.path().join("dotfiles");
+            forge(&home, &["init", "--name", "default", "--dir", dotfiles.to_str().unwrap()])
+                .assert().success();
+
+            // One file staged, one missing: a partial failure
+            let present = home.child("present");
+            present.touch().unwrap();
+            let missing = home.path().join("missing");
+            forge(&home, &["stage", present.path().to_str().unwrap(), missing.to_str().unwrap()])
+                .assert().code(3);
+
+            // Invalid usage is reported by the argument parser
+            forge(&home, &["stage", "--depth", "many"]).assert().code(2);
+        }
+
+        #[test]
+        fn test_structured_output() {
+            let home = TempDir::new().unwrap();
+            let dotfiles = home.path().join("dotfiles");
+            forge(&home, &["init", "--name", "default", "--dir", dotfiles.to_str().unwrap()])
+                .assert().success();
+
+            let bashrc = home.child(".bashrc");
+            bashrc.touch().unwrap();
+            let missing = home.path().join(".missing");
+            let output = forge(&home, &[
+                "stage", "--format", "ndjson",
+                bashrc.path().to_str().unwrap(), missing.to_str().unwrap(),
+            ]).output().unwrap();
+            let records: Vec<serde_json::Value> = String::from_utf8(output.stdout).unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0]["action"], "stage");
+            assert_eq!(records[0]["status"], "done");
+            assert_eq!(records[1]["status"], "failed");
+            assert_eq!(records[1]["error"], "not_found");
+
+            let output = forge(&home, &["list", "--format", "json"]).output().unwrap();
+            let dotfiles: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            assert_eq!(dotfiles[0]["source"], bashrc.path().to_str().unwrap());
+            assert_eq!(dotfiles[0]["status"], "staged");
+
+            // Deprecation notes go to stderr, leaving stdout to the records
+            forge(&home, &["profile", "create", "work"]).assert().success();
+            let output = forge(&home, &["--format", "json", "profile", "list"]).output().unwrap();
+            let profiles: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            assert_eq!(profiles[0]["name"], "work");
+            assert!(String::from_utf8(output.stderr).unwrap().contains("deprecated"));
+
+            // Without a command there is still a record to parse
+            let output = forge(&home, &["--format", "json"]).output().unwrap();
+            assert_eq!(output.status.code(), Some(2));
+            let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            assert_eq!(error["error"], "invalid_input");
+        }
+
+        #[test]
+        fn test_status_check_reports_drift() {
+            let home = TempDir::new().unwrap();
+            let dotfiles = home.path().join("dotfiles");
+            forge(&home, &["init", "--name", "default", "--dir", dotfiles.to_str().unwrap()])
+                .assert().success();
+            let zshrc = home.child(".zshrc");
+            zshrc.touch().unwrap();
+            forge(&home, &["stage", zshrc.path().to_str().unwrap()]).assert().success();
+            forge(&home, &["link"]).assert().success();
+
+            forge(&home, &["status", "--check"]).assert().success();
+
+            // An editor that writes by rename replaces the symlink with a regular file
+            std::fs::remove_file(zshrc.path()).unwrap();
+            zshrc.write_str("export PATH\n").unwrap();
+            forge(&home, &["status", "--check"]).assert().code(5)
+                .stdout(predicates::str::contains("[replaced_by_file]"));
+        }
+
+        #[test]
+        fn test_db_migrate_and_check() {
+            let home = TempDir::new().unwrap();
+
+            forge(&home, &["db", "check"]).assert().code(5)
+                .stdout(predicates::str::contains("Pending migrations"));
+            forge(&home, &["db", "migrate", "--dry-run"]).assert().success();
+            forge(&home, &["db", "check"]).assert().code(5);
+            forge(&home, &["db", "migrate"]).assert().success()
+                .stdout(predicates::str::contains("Migrated"));
+            forge(&home, &["db", "check"]).assert().success()
+                .stdout(predicates::str::contains("up to date"));
+        }
+
+        #[test]
+        fn test_unlink_by_home_path() {
+            let home = TempDir::new().unwrap();
+            let dotfiles = home.path().join("dotfiles");
+            forge(&home, &["init", "--name", "default", "--dir", dotfiles.to_str().unwrap()])
+                .assert().success();
+            let bashrc = home.child(".bashrc");
+            bashrc.write_str("alias ll='ls -l'\n").unwrap();
+            forge(&home, &["stage", bashrc.path().to_str().unwrap()]).assert().success();
+            forge(&home, &["link"]).assert().success();
+
+            // The home path names the tracked file, not an untracked symlink
+            let output = forge(&home, &["unlink", "-y", bashrc.path().to_str().unwrap()]).output().unwrap();
+            assert!(output.status.success());
+            assert!(!String::from_utf8(output.stdout).unwrap().contains("untracked"));
+            assert!(!bashrc.path().is_symlink());
+            assert_eq!(std::fs::read_to_string(bashrc.path()).unwrap(), "alias ll='ls -l'\n");
+            let output = forge(&home, &["list", "--format", "json"]).output().unwrap();
+            let dotfiles: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            assert_eq!(dotfiles, serde_json::json!([]));
+            forge(&home, &["status", "--check"]).assert().success();
+        }
+
+        #[test]
+        fn test_undo_switch_forgets_profile_files() {
+            let home = TempDir::new().unwrap();
+            let dotfiles = home.path().join("dotfiles");
+            forge(&home, &["init", "--name", "default", "--dir", dotfiles.to_str().unwrap()])
+                .assert().success();
+            home.child(".forge/filetypes").write_str(".conf\n").unwrap();
+            home.child(".forge/profiles/work/app.conf").write_str("work\n").unwrap();
+            home.child(".forge/profiles/work/notes").touch().unwrap();
+
+            // Only the file that was linked is tracked, once however often it is switched to
+            forge(&home, &["switch", "work"]).assert().success();
+            forge(&home, &["switch", "work"]).assert().success();
+            let link = home.child(".forge/app.conf");
+            assert!(link.path().is_symlink());
+            let output = forge(&home, &["list", "--format", "json"]).output().unwrap();
+            let dotfiles: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            assert_eq!(dotfiles.as_array().unwrap().len(), 1, "{}", dotfiles);
+
+            // Undoing the switch takes the record away with the link
+            forge(&home, &["undo", "--yes"]).assert().success();
+            assert!(!link.path().exists());
+            let output = forge(&home, &["list", "--format", "json"]).output().unwrap();
+            let dotfiles: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            assert_eq!(dotfiles, serde_json::json!([]));
+        }
+
+        #[test]
+        fn test_log_and_undo() {
+            let home = TempDir::new().unwrap();
+            let dotfiles = home.path().join("dotfiles");
+            forge(&home, &["init", "--name", "default", "--dir", dotfiles.to_str().unwrap()])
+                .assert().success();
+            let gitconfig = home.child(".gitconfig");
+            gitconfig.write_str("[user]\n").unwrap();
+            forge(&home, &["stage", gitconfig.path().to_str().unwrap()]).assert().success();
+            forge(&home, &["link"]).assert().success();
+
+            forge(&home, &["log", "-v"]).assert().success()
+                .stdout(predicates::str::contains("link (1 file(s))"))
+                .stdout(predicates::str::contains("stage (1 file(s))"));
+            forge(&home, &["undo", "--format", "json"]).assert().code(2);
+            forge(&home, &["undo", "--yes"]).assert().success()
+                .stdout(predicates::str::contains("Reverted"));
+
+            assert!(!gitconfig.path().is_symlink());
+            assert_eq!(std::fs::read_to_string(gitconfig.path()).unwrap(), "[user]\n");
+            let output = forge(&home, &["log", "--format", "json"]).output().unwrap();
+            let operations: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            assert_eq!(operations[0]["action"], "undo");
+            assert_eq!(operations[1]["undone_by"], operations[0]["id"]);
+        }
+
+        #[test]
+        fn test_doctor_fix() {
+            let home = TempDir::new().unwrap();
+            let dotfiles = home.path().join("dotfiles");
+            forge(&home, &["init", "--name", "default", "--dir", dotfiles.to_str().unwrap()])
+                .assert().success();
+            let zshrc = home.child(".zshrc");
+            zshrc.touch().unwrap();
+            forge(&home, &["stage", zshrc.path().to_str().unwrap()]).assert().success();
+            forge(&home, &["link"]).assert().success();
+            std::fs::remove_file(zshrc.path()).unwrap();
+
+            forge(&home, &["doctor", "--fix", "--dry-run"]).assert().success()
+                .stdout(predicates::str::contains("Would repair"));
+            assert!(!zshrc.path().is_symlink());
+
+            forge(&home, &["--format", "json", "doctor", "--fix"]).assert().code(2);
+            forge(&home, &["doctor", "--fix", "--yes"]).assert().success();
+            assert!(zshrc.path().is_symlink());
+            forge(&home, &["status", "--check"]).assert().success();
+        }
+
+        #[test]
+        fn test_delete_uses_the_trash() {
+            let home = TempDir::new().unwrap();
+            let data = home.path().join(".local/share");
+            let dotfiles = home.path().join("dotfiles");
+            forge(&home, &["init", "--name", "default", "--dir", dotfiles.to_str().unwrap()])
+                .assert().success();
+            let vimrc = home.child(".vimrc");
+            vimrc.write_str("set nu\n").unwrap();
+            let inputrc = home.child(".inputrc");
+            inputrc.write_str("set bell-style none\n").unwrap();
+
+            // Deleting moves the file to the trash, with a .trashinfo beside it
+            forge(&home, &["delete", "-y", vimrc.path().to_str().unwrap()]).assert().success();
+            assert_eq!(std::fs::read_to_string(data.join("Trash/files/.vimrc")).unwrap(), "set nu\n");
+            let info = std::fs::read_to_string(data.join("Trash/info/.vimrc.trashinfo")).unwrap();
+            assert!(info.contains("Path=") && info.contains("DeletionDate="));
+
+            // A purge keeps nothing
+            forge(&home, &["delete", "-y", "--purge", inputrc.path().to_str().unwrap()]).assert().success();
+            assert!(!inputrc.path().exists());
+            assert!(!data.join("Trash/files/.inputrc").exists());
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,890 @@
+// Pack-and-Go functionality for Forge
+use crate::config::folder::ConflictPolicy;
+use crate::engine::{Action, Outcome, Report};
+use crate::error::{ForgeError, Result};
+use crate::utils::path_utils;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Manifest format written by this release
+///
+/// Version 1.1 escapes paths with [`path_utils::escape`] so names that are not valid
+/// UTF-8 survive; 1.0 manifests stored them verbatim.
+const MANIFEST_VERSION: &str = "1.1";
+
+/// Whether manifests and zip entry names of this version hold escaped paths
+fn escapes_paths(version: &str) -> bool {
+    version != "1.0"
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PackManifest {
+    pub version: String,
+    pub scope: String,
+    pub created: DateTime<Utc>,
+    pub files: HashMap<String, PackFile>,
+}
+
+/// A file in a pack; paths are escaped, see [`PackFile::target`] and [`PackFile::relative`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PackFile {
+    pub target_path: String,
+    pub relative_path: String,
+    pub hash: Option<String>,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+impl PackManifest {
+    pub fn new(scope: &str) -> Self {
+        Self {
+            version: MANIFEST_VERSION.to_string(),
+            scope: scope.to_string(),
+            created: Utc::now(),
+            files: HashMap::new(),
+        }
+    }
+
+    pub fn add_file(
+        &mut self,
+        target_path: &Path,
+        relative_path: &Path,
+        hash: Option<String>,
+    ) -> Result<()> {
+        let metadata = fs::metadata(target_path)?;
+        let modified = metadata.modified()?;
+        let modified_dt = DateTime::<Utc>::from(modified);
+
+        let pack_file = PackFile {
+            target_path: path_utils::escape(target_path),
+            relative_path: path_utils::escape(relative_path),
+            hash,
+            size: metadata.len(),
+            modified: modified_dt,
+        };
+
+        self.files
+            .insert(path_utils::escape(target_path), pack_file);
+
+        Ok(())
+    }
+
+    /// Read a manifest from a TOML file, upgrading older formats in memory
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let mut manifest: Self = toml::from_str(&content)?;
+
+        if !escapes_paths(&manifest.version) {
+            let escape = |text: &str| path_utils::escape(Path::new(text));
+            manifest.files = manifest
+                .files
+                .into_iter()
+                .map(|(key, mut file)| {
+                    file.target_path = escape(&file.target_path);
+                    file.relative_path = escape(&file.relative_path);
+                    (escape(&key), file)
+                })
+                .collect();
+            manifest.version = MANIFEST_VERSION.to_string();
+        }
+
+        Ok(manifest)
+    }
+
+    /// Write the manifest to a TOML file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = toml::to_string_pretty(self)?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+impl PackFile {
+    /// Where the file was packed from
+    pub fn target(&self) -> PathBuf {
+        path_utils::unescape(&self.target_path)
+    }
+
+    /// Where the file is kept inside the pack
+    pub fn relative(&self) -> PathBuf {
+        path_utils::unescape(&self.relative_path)
+    }
+}
+
+/// Options for adding files to a pack
+#[derive(Debug, Clone, Default)]
+pub struct PackOptions {
+    /// Process directories recursively (all levels)
+    pub recursive: bool,
+    /// Maximum recursion depth for directories (overrides `recursive`)
+    pub depth: Option<usize>,
+    /// Report what would be packed without packing
+    pub dry_run: bool,
+}
+
+/// Options for installing a sealed pack
+#[derive(Debug, Clone, Default)]
+pub struct InstallOptions {
+    /// How to resolve files that already exist, instead of the managed folder's
+    /// configured policy
+    pub on_conflict: Option<ConflictPolicy>,
+    /// Directory to install into (defaults to the current directory)
+    pub target: Option<PathBuf>,
+    /// Map home directory paths to the current user's home
+    pub map_home: bool,
+    /// Report what would be installed without installing
+    pub dry_run: bool,
+}
+
+/// Options for restoring a sealed pack to its original locations
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    /// How to resolve files that already exist, instead of the managed folder's
+    /// configured policy
+    pub on_conflict: Option<ConflictPolicy>,
+    /// Restore to the current directory using file names only
+    pub test: bool,
+    /// Report what would be restored without restoring
+    pub dry_run: bool,
+}
+
+/// The manifest of an installed or restored pack and what happened to each file
+#[derive(Debug)]
+pub struct Deployment {
+    pub manifest: PackManifest,
+    pub report: Report,
+}
+
+/// A pack file checked and ready to be copied into place
+#[derive(Debug, Clone)]
+pub struct Placement {
+    /// The extracted copy of the file
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub relative_path: PathBuf,
+    /// Something already exists at `to`; the engine resolves it by conflict policy
+    pub overwrite: bool,
+}
+
+/// An extracted pack with the files that passed every check
+///
+/// The extracted files are deleted when this is dropped, so placements have to be
+/// copied before then.
+#[derive(Debug)]
+pub struct Extraction {
+    pub manifest: PackManifest,
+    /// Files that were skipped, failed a check or, in a dry run, would be placed
+    pub report: Report,
+    pub placements: Vec<Placement>,
+    _temp_dir: tempfile::TempDir,
+}
+
+/// Where a single pack file would be placed
+#[derive(Debug, Serialize)]
+pub struct PlanEntry {
+    pub relative_path: String,
+    #[serde(with = "path_utils::escaped")]
+    pub destination: PathBuf,
+    /// The destination already exists
+    pub conflict: bool,
+}
+
+/// Pack contents along with install and restore plans
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    pub manifest: PackManifest,
+    /// Directory the install plan was computed against
+    #[serde(with = "path_utils::escaped")]
+    pub install_target: PathBuf,
+    pub install_plan: Vec<PlanEntry>,
+    pub restore_plan: Vec<PlanEntry>,
+}
+
+impl Explanation {
+    pub fn total_size(&self) -> u64 {
+        self.manifest.files.values().map(|f| f.size).sum()
+    }
+}
+
+/// Get the pack staging directory for a given scope
+pub fn staging_dir(forge_path: &Path, scope: &str) -> PathBuf {
+    forge_path
+        .join(".forge")
+        .join("tmp")
+        .join("pack")
+        .join(scope)
+}
+
+/// Get the pack archives directory
+pub fn archives_dir(forge_path: &Path) -> PathBuf {
+    forge_path.join(".forge").join("archives")
+}
+
+/// Get default scope name from current working directory
+pub fn default_scope() -> Result<String> {
+    let cwd = env::current_dir()?;
+    let scope = cwd
+        .file_name()
+        .ok_or_else(|| {
+            ForgeError::InvalidInput(
+                "could not determine directory name for default scope".to_string(),
+            )
+        })?
+        .to_string_lossy()
+        .to_string();
+    Ok(scope)
+}
+
+/// Create the staging area for a new pack, returning its directory
+pub fn start(forge_path: &Path, scope: &str) -> Result<PathBuf> {
+    let staging_dir = staging_dir(forge_path, scope);
+
+    // Check if pack already exists
+    if staging_dir.exists() {
+        return Err(ForgeError::InvalidInput(format!(
+            "Pack '{}' already exists. Use 'forge pack' to add files or 'forge seal' to finalize.",
+            scope
+        )));
+    }
+
+    // Create staging directory structure
+    let files_dir = staging_dir.join("files");
+    fs::create_dir_all(&files_dir)?;
+
+    // Create initial manifest
+    PackManifest::new(scope).save(&staging_dir.join("manifest.toml"))?;
+
+    Ok(staging_dir)
+}
+
+/// Add files to an existing pack
+pub fn add_files(
+    forge_path: &Path,
+    scope: &str,
+    files: &[PathBuf],
+    options: &PackOptions,
+) -> Result<Report> {
+    let staging_dir = staging_dir(forge_path, scope);
+
+    if !staging_dir.exists() {
+        return Err(ForgeError::InvalidInput(format!(
+            "Pack '{}' does not exist. Use 'forge start packing {}' first.",
+            scope, scope
+        )));
+    }
+
+    // Load existing manifest
+    let manifest_path = staging_dir.join("manifest.toml");
+    let mut manifest = if manifest_path.exists() {
+        PackManifest::load(&manifest_path)?
+    } else {
+        PackManifest::new(scope)
+    };
+
+    let files_dir = staging_dir.join("files");
+    let mut report = Report::new();
+
+    // Collect all files to process (including from directories if recursive)
+    let mut files_to_process = Vec::new();
+
+    for file in files {
+        let abs_source = path_utils::normalize(file);
+
+        if !abs_source.exists() {
+            report.push(Outcome::failed(
+                Action::Pack,
+                &abs_source,
+                ForgeError::NotFound(abs_source.clone()),
+            ));
+            continue;
+        }
+
+        if abs_source.is_dir() {
+            if options.recursive || options.depth.is_some() {
+                // Unlimited depth for recursive mode
+                let walkdir_depth = options.depth.unwrap_or(usize::MAX);
+
+                // Use walkdir to recursively collect files from directory
+                for entry in walkdir::WalkDir::new(&abs_source)
+                    .min_depth(1) // Skip the root dir itself
+                    .max_depth(walkdir_depth)
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.path().is_file())
+                {
+                    // Calculate relative path from original directory
+                    let rel_path = entry
+                        .path()
+                        .strip_prefix(&abs_source)
+                        .unwrap_or_else(|_| Path::new(entry.file_name()));
+
+                    // Include directory name in the relative path to preserve structure
+                    let dir_name = abs_source.file_name().unwrap_or_default();
+                    let full_relative_path = Path::new(dir_name).join(rel_path);
+
+                    files_to_process.push((entry.path().to_path_buf(), full_relative_path));
+                }
+            } else if options.dry_run {
+                report.push(Outcome::skipped(
+                    Action::Pack,
+                    &abs_source,
+                    "directory (use --recursive or --depth to include contents)",
+                ));
+            } else {
+                report.push(Outcome::failed(
+                    Action::Pack,
+                    &abs_source,
+                    ForgeError::InvalidInput(
+                        "directories not supported without --recursive or --depth".to_string(),
+                    ),
+                ));
+            }
+        } else {
+            // Regular file - generate simple relative path
+            let filename = abs_source.file_name().ok_or_else(|| {
+                ForgeError::InvalidInput(format!("invalid filename: {}", abs_source.display()))
+            })?;
+            files_to_process.push((abs_source.clone(), PathBuf::from(filename)));
+        }
+    }
+
+    // Process all collected files
+    for (abs_source, relative_path) in files_to_process {
+        let target_in_pack = files_dir.join(&relative_path);
+
+        // Check if already exists
+        if target_in_pack.exists() {
+            report.push(
+                Outcome::skipped(Action::Pack, &abs_source, "already in pack")
+                    .with_target(&relative_path),
+            );
+            continue;
+        }
+
+        if options.dry_run {
+            report.push(Outcome::planned(Action::Pack, &abs_source).with_target(&relative_path));
+            continue;
+        }
+
+        // Copy file to pack
+        if let Some(parent) = target_in_pack.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&abs_source, &target_in_pack)?;
+
+        // Calculate hash
+        let hash = calculate_file_hash(&target_in_pack)?;
+
+        // Add to manifest
+        manifest.add_file(&abs_source, &relative_path, Some(hash))?;
+
+        report.push(Outcome::done(Action::Pack, &abs_source).with_target(&relative_path));
+    }
+
+    // Save updated manifest (skip in dry-run mode)
+    if !options.dry_run {
+        manifest.save(&manifest_path)?;
+    }
+
+    Ok(report)
+}
+
+/// Calculate BLAKE3 hash of a file
+pub fn calculate_file_hash(path: &Path) -> Result<String> {
+    let content = fs::read(path)?;
+    let hash = blake3::hash(&content);
+    Ok(hash.to_hex().to_string())
+}
+
+/// Seal a pack into a portable archive, returning the archive path
+pub fn seal(forge_path: &Path, scope: &str) -> Result<PathBuf> {
+    let staging_dir = staging_dir(forge_path, scope);
+
+    if !staging_dir.exists() {
+        return Err(ForgeError::InvalidInput(format!(
+            "Pack '{}' does not exist. Use 'forge start packing {}' first.",
+            scope, scope
+        )));
+    }
+
+    // Create archives directory
+    let archives_dir = archives_dir(forge_path);
+    fs::create_dir_all(&archives_dir)?;
+
+    // Generate archive filename with timestamp
+    let now = Utc::now();
+    let timestamp = now.format("%Y-%m-%d");
+    let archive_name = format!("{}-{}.zip", scope, timestamp);
+    let archive_path = archives_dir.join(&archive_name);
+
+    // Create ZIP archive
+    create_zip_archive(&staging_dir, &archive_path)?;
+
+    // Clean up staging directory
+    fs::remove_dir_all(&staging_dir)?;
+
+    Ok(archive_path)
+}
+
+/// Create a ZIP archive from the staging directory
+fn create_zip_archive(staging_dir: &Path, archive_path: &Path) -> Result<()> {
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    let file = fs::File::create(archive_path)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .unix_permissions(0o755);
+
+    // Add manifest
+    let manifest_path = staging_dir.join("manifest.toml");
+    if manifest_path.exists() {
+        zip.start_file("manifest.toml", options)?;
+        let manifest_content = fs::read(&manifest_path)?;
+        zip.write_all(&manifest_content)?;
+    }
+
+    // Add all files from files/ directory
+    let files_dir = staging_dir.join("files");
+    if files_dir.exists() {
+        for entry in walkdir::WalkDir::new(&files_dir) {
+            let entry = entry?;
+            let path = entry.path();
+
+            if path.is_file() {
+                let relative_path = path
+                    .strip_prefix(&files_dir)
+                    .map_err(|e| ForgeError::Archive(e.to_string()))?;
+                let zip_path = format!("files/{}", path_utils::escape(relative_path));
+
+                zip.start_file(&zip_path, options)?;
+                let content = fs::read(path)?;
+                zip.write_all(&content)?;
+            }
+        }
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// Extract a sealed pack and plan installing it on a new system
+pub fn install(archive: &Path, options: &InstallOptions) -> Result<Extraction> {
+    prepare(archive, Action::Install, options.dry_run, |pack_file| {
+        calculate_install_target_path(
+            &pack_file.target(),
+            options.target.as_deref(),
+            options.map_home,
+        )
+    })
+}
+
+/// Extract a sealed pack and plan restoring it to original locations on current system
+pub fn restore(archive: &Path, options: &RestoreOptions) -> Result<Extraction> {
+    prepare(archive, Action::Restore, options.dry_run, |pack_file| {
+        calculate_restore_target_path(&pack_file.target(), options.test)
+    })
+}
+
+/// Extract an archive and check each file against the destination chosen by `destination`
+fn prepare<F>(archive: &Path, action: Action, dry_run: bool, destination: F) -> Result<Extraction>
+where
+    F: Fn(&PackFile) -> Result<PathBuf>,
+{
+    let (temp_dir, manifest) = open_archive(archive)?;
+
+    let files_dir = temp_dir.path().join("files");
+    let mut report = Report::new();
+    let mut placements = Vec::new();
+
+    for pack_file in manifest.files.values() {
+        let relative_path = pack_file.relative();
+        let source_in_archive = files_dir.join(&relative_path);
+
+        if !source_in_archive.exists() {
+            report.push(Outcome::failed(
+                action,
+                &relative_path,
+                ForgeError::Archive(format!(
+                    "{} is missing from archive",
+                    relative_path.display()
+                )),
+            ));
+            continue;
+        }
+
+        let target_path = destination(pack_file)?;
+
+        if dry_run {
+            report.push(Outcome::planned(action, &target_path).with_target(&relative_path));
+            continue;
+        }
+
+        // Conflicts are left to the engine's conflict policy
+        let overwrite = target_path.exists();
+
+        // Validate hash if available
+        if let Some(expected_hash) = &pack_file.hash {
+            let actual_hash = calculate_file_hash(&source_in_archive)?;
+            if actual_hash != *expected_hash {
+                report.push(Outcome::failed(
+                    action,
+                    &target_path,
+                    ForgeError::HashMismatch {
+                        path: pack_file.relative_path.clone(),
+                        expected: expected_hash.clone(),
+                        actual: actual_hash,
+                    },
+                ));
+                continue;
+            }
+        }
+
+        placements.push(Placement {
+            from: source_in_archive,
+            to: target_path,
+            relative_path,
+            overwrite,
+        });
+    }
+
+    Ok(Extraction {
+        manifest,
+        report,
+        placements,
+        _temp_dir: temp_dir,
+    })
+}
+
+/// Extract an archive to a temporary directory and read its manifest
+fn open_archive(archive: &Path) -> Result<(tempfile::TempDir, PackManifest)> {
+    if !archive.exists() {
+        return Err(ForgeError::NotFound(archive.to_path_buf()));
+    }
+
+    // Extract archive to temporary directory
+    let temp_dir = tempfile::tempdir()?;
+    extract_zip_archive(archive, temp_dir.path())?;
+
+    // Read manifest
+    let manifest_path = temp_dir.path().join("manifest.toml");
+    if !manifest_path.exists() {
+        return Err(ForgeError::Archive(
+            "invalid pack archive: missing manifest.toml".to_string(),
+        ));
+    }
+
+    let manifest = PackManifest::load(&manifest_path)?;
+    Ok((temp_dir, manifest))
+}
+
+/// Calculate the target installation path for install command
+fn calculate_install_target_path(
+    original: &Path,
+    target_dir: Option<&Path>,
+    map_home: bool,
+) -> Result<PathBuf> {
+    if let Some(target) = target_dir {
+        // Install relative to specified target directory
+        if map_home && original.is_absolute() {
+            // For absolute paths, try to map home directory
+            if let Some(home_dir) = dirs::home_dir()
+                && let Ok(relative_to_home) = original.strip_prefix(&home_dir)
+            {
+                // Map /home/user/... to target/...
+                return Ok(target.join(relative_to_home));
+            }
+
+            // For other absolute paths, use just the filename in target
+            if let Some(filename) = original.file_name() {
+                return Ok(target.join(filename));
+            }
+        }
+
+        // Default: use relative path from original or just filename
+        if original.is_relative() {
+            Ok(target.join(original))
+        } else if let Some(filename) = original.file_name() {
+            Ok(target.join(filename))
+        } else {
+            Err(ForgeError::InvalidInput(format!(
+                "cannot determine target path for: {}",
+                original.display()
+            )))
+        }
+    } else if map_home {
+        // Map to current user's home directory
+        if let Some(current_home) = dirs::home_dir() {
+            // Try to detect if this was a home directory path, replacing /home/username
+            let mut components = original.components();
+            if let (Some(Component::RootDir), Some(Component::Normal(home)), Some(_)) =
+                (components.next(), components.next(), components.next())
+                && home == "home"
+            {
+                return Ok(current_home.join(components.as_path()));
+            }
+
+            // Fallback: put file in current home
+            if let Some(filename) = original.file_name() {
+                Ok(current_home.join(filename))
+            } else {
+                Err(ForgeError::InvalidInput(format!(
+                    "cannot determine target path for: {}",
+                    original.display()
+                )))
+            }
+        } else {
+            Err(ForgeError::InvalidInput(
+                "cannot determine current user's home directory".to_string(),
+            ))
+        }
+    } else {
+        // Default for install: Install to current working directory (CWD)
+        let cwd = std::env::current_dir()?;
+
+        // Use just the filename from the original path
+        if let Some(filename) = original.file_name() {
+            Ok(cwd.join(filename))
+        } else {
+            Err(ForgeError::InvalidInput(format!(
+                "cannot determine filename from path: {}",
+                original.display()
+            )))
+        }
+    }
+}
+
+fn calculate_restore_target_path(original: &Path, test: bool) -> Result<PathBuf> {
+    if test {
+        // Test mode: restore to current directory using filenames only
+        let cwd = std::env::current_dir()?;
+        if let Some(filename) = original.file_name() {
+            Ok(cwd.join(filename))
+        } else {
+            Err(ForgeError::InvalidInput(format!(
+                "cannot determine filename from path: {}",
+                original.display()
+            )))
+        }
+    } else {
+        // Default for restore: Use original absolute paths
+        Ok(original.to_path_buf())
+    }
+}
+
+/// Extract ZIP archive to a directory
+///
+/// Entry names are unescaped unless the archive's manifest predates escaping.
+fn extract_zip_archive(archive: &Path, target_dir: &Path) -> Result<()> {
+    use std::io::Read;
+
+    let file = fs::File::open(archive)?;
+    let mut zip = zip::ZipArchive::new(file)?;
+
+    let escaped = match zip.by_name("manifest.toml") {
+        Ok(mut manifest) => {
+            let mut content = String::new();
+            manifest.read_to_string(&mut content)?;
+            toml::from_str::<PackManifest>(&content).is_ok_and(|m| escapes_paths(&m.version))
+        }
+        Err(_) => true,
+    };
+
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let outpath = if escaped {
+            target_dir.join(path_utils::unescape(file.name()))
+        } else {
+            target_dir.join(file.name())
+        };
+
+        if file.name().ends_with('/') {
+            fs::create_dir_all(&outpath)?;
+        } else {
+            if let Some(parent) = outpath.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut outfile = fs::File::create(&outpath)?;
+            std::io::copy(&mut file, &mut outfile)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Update files in an existing pack (repack)
+pub fn repack(forge_path: &Path, scope: &str, files: &[PathBuf]) -> Result<Report> {
+    let staging_dir = staging_dir(forge_path, scope);
+
+    if !staging_dir.exists() {
+        return Err(ForgeError::InvalidInput(format!(
+            "Pack '{}' does not exist. Use 'forge start packing {}' first.",
+            scope, scope
+        )));
+    }
+
+    if files.is_empty() {
+        // Repack all files in manifest
+        let manifest = PackManifest::load(&staging_dir.join("manifest.toml"))?;
+
+        let files_to_repack: Vec<PathBuf> = manifest.files.values().map(PackFile::target).collect();
+
+        add_files(forge_path, scope, &files_to_repack, &PackOptions::default())
+    } else {
+        add_files(forge_path, scope, files, &PackOptions::default())
+    }
+}
+
+/// Remove files from a pack
+pub fn unpack(forge_path: &Path, scope: &str, files: &[PathBuf]) -> Result<Report> {
+    let staging_dir = staging_dir(forge_path, scope);
+
+    if !staging_dir.exists() {
+        return Err(ForgeError::InvalidInput(format!(
+            "Pack '{}' does not exist.",
+            scope
+        )));
+    }
+
+    // Load manifest
+    let manifest_path = staging_dir.join("manifest.toml");
+    if !manifest_path.exists() {
+        return Err(ForgeError::Manifest("manifest.toml not found".to_string()));
+    }
+    let mut manifest = PackManifest::load(&manifest_path)?;
+
+    let files_dir = staging_dir.join("files");
+    let mut report = Report::new();
+
+    for file in files {
+        let abs_path = path_utils::normalize(file);
+        let key = path_utils::escape(&abs_path);
+
+        if let Some(pack_file) = manifest.files.remove(&key) {
+            let file_in_pack = files_dir.join(pack_file.relative());
+
+            if file_in_pack.exists() {
+                fs::remove_file(&file_in_pack)?;
+                report.push(
+                    Outcome::done(Action::Unpack, &abs_path).with_target(&pack_file.relative()),
+                );
+            }
+        } else {
+            report.push(Outcome::skipped(
+                Action::Unpack,
+                &abs_path,
+                "not found in pack",
+            ));
+        }
+    }
+
+    // Save updated manifest
+    manifest.save(&manifest_path)?;
+
+    Ok(report)
+}
+
+/// Describe pack contents and where its files would be installed or restored
+pub fn explain(archive: &Path, target: Option<&Path>) -> Result<Explanation> {
+    let (_temp_dir, manifest) = open_archive(archive)?;
+
+    let install_target = match target {
+        Some(dir) => dir.to_path_buf(),
+        None => std::env::current_dir()?,
+    };
+
+    let mut install_plan = Vec::new();
+    let mut restore_plan = Vec::new();
+
+    for pack_file in manifest.files.values() {
+        let install_dest = calculate_install_target_path(&pack_file.target(), target, false)?;
+        install_plan.push(PlanEntry {
+            relative_path: pack_file.relative_path.clone(),
+            conflict: install_dest.exists(),
+            destination: install_dest,
+        });
+
+        let restore_dest = calculate_restore_target_path(&pack_file.target(), false)?;
+        restore_plan.push(PlanEntry {
+            relative_path: pack_file.relative_path.clone(),
+            conflict: restore_dest.exists(),
+            destination: restore_dest,
+        });
+    }
+
+    Ok(Explanation {
+        manifest,
+        install_target,
+        install_plan,
+        restore_plan,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid_utf8_names_survive_seal_and_restore() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp = TempDir::new().unwrap();
+        let forge_path = temp.path().join("forge");
+        let file = temp.path().join(OsStr::from_bytes(b"caf\xe9 \\ \xff.conf"));
+        fs::write(&file, "colors = true\n").unwrap();
+
+        start(&forge_path, "shell").unwrap();
+        let report = add_files(
+            &forge_path,
+            "shell",
+            std::slice::from_ref(&file),
+            &PackOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.succeeded(), 1, "{:?}", report);
+        let archive = seal(&forge_path, "shell").unwrap();
+
+        fs::remove_file(&file).unwrap();
+        let extraction = restore(&archive, &RestoreOptions::default()).unwrap();
+        assert_eq!(extraction.placements.len(), 1, "{:?}", extraction.report);
+        let placement = &extraction.placements[0];
+        assert_eq!(placement.to, file);
+        assert_eq!(
+            fs::read_to_string(&placement.from).unwrap(),
+            "colors = true\n"
+        );
+    }
+
+    #[test]
+    fn test_reads_unescaped_manifests() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("manifest.toml");
+        fs::write(
+            &path,
+            r#"version = "1.0"
+scope = "old"
+created = "2025-06-23T00:00:00Z"
+
+[files.'/home/ada/back\slash']
+target_path = '/home/ada/back\slash'
+relative_path = 'back\slash'
+size = 0
+modified = "2025-06-23T00:00:00Z"
+"#,
+        )
+        .unwrap();
+
+        let manifest = PackManifest::load(&path).unwrap();
+        let file = manifest.files.values().next().unwrap();
+        assert_eq!(file.target(), Path::new(r"/home/ada/back\slash"));
+        assert_eq!(file.relative(), Path::new(r"back\slash"));
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::fs::{self};
 use std::io::{self, Error, ErrorKind};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use walkdir::WalkDir;
 
 use crate::config;
@@ -23,6 +23,48 @@ pub fn create_symlink<P: AsRef<Path>, Q: AsRef<Path>>(source: P, target: Q) -> i
     }
 }
 
+/// Create a symlink at `link` whose content is the path from the link's directory to
+/// `destination`, so it keeps working when both are moved under another prefix
+pub fn create_relative_symlink<P: AsRef<Path>, Q: AsRef<Path>>(
+    destination: P,
+    link: Q,
+) -> io::Result<()> {
+    let link = link.as_ref();
+    create_symlink(relative_target(link, destination.as_ref()), link)
+}
+
+/// The path from the directory of `link` to `destination`
+///
+/// The system follows `..` from where the directory really is, so when a directory
+/// the path climbs out of is a symlink, the path is worked out from the directory's
+/// real location instead. If that cannot be found, `destination` is returned as is.
+fn relative_target(link: &Path, destination: &Path) -> PathBuf {
+    let parent = path_utils::clean(link.parent().unwrap_or_else(|| Path::new("")));
+    let relative = path_utils::relative_to(&parent, destination);
+    let climbs = relative
+        .components()
+        .take_while(|component| *component == Component::ParentDir)
+        .count();
+    if climbs == 0 {
+        return relative;
+    }
+
+    let Ok(real_parent) = fs::canonicalize(&parent) else {
+        return path_utils::clean(destination);
+    };
+    let mut top = parent;
+    let mut climbed = real_parent.clone();
+    for _ in 0..climbs {
+        top.pop();
+        climbed.pop();
+    }
+    if fs::canonicalize(&top).is_ok_and(|real_top| real_top == climbed) {
+        relative
+    } else {
+        path_utils::relative_to(&real_parent, destination)
+    }
+}
+
 /// Check if path is a symlink
 pub fn is_symlink<P: AsRef<Path>>(path: P) -> bool {
     path.as_ref().is_symlink()
@@ -38,9 +80,86 @@ pub fn get_symlink_target<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
     std::fs::read_link(path)
 }
 
+/// Get the absolute path a symlink points to
+///
+/// Relative link targets are resolved against the directory containing the link, at
+/// its real location if the target climbs out of it with `..`.
+pub fn resolve_link<P: AsRef<Path>>(link: P) -> io::Result<PathBuf> {
+    let link = link.as_ref();
+    let destination = fs::read_link(link)?;
+    let mut parent = path_utils::clean(link.parent().unwrap_or_else(|| Path::new("")));
+    if destination.components().any(|c| c == Component::ParentDir)
+        && let Ok(real_parent) = fs::canonicalize(&parent)
+    {
+        parent = real_parent;
+    }
+    Ok(path_utils::clean(parent.join(destination)))
+}
+
+/// `path` with the directories leading to it resolved to where they really are, or
+/// just cleaned if they cannot be
+fn real_location(path: &Path) -> PathBuf {
+    let path = path_utils::clean(path);
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent)
+            .map(|parent| parent.join(name))
+            .unwrap_or_else(|_| path.clone()),
+        _ => path,
+    }
+}
+
+/// Follow a chain of symlinks to the first path that is not a symlink
+///
+/// Returns the symlinks passed through, starting with `link`, and the path the chain
+/// ends at, which may not exist. A chain that loops back on itself is an
+/// `InvalidData` error.
+pub fn follow_chain<P: AsRef<Path>>(link: P) -> io::Result<(Vec<PathBuf>, PathBuf)> {
+    let mut hops = Vec::new();
+    let mut current = path_utils::clean(link);
+    while is_symlink(&current) {
+        if hops.contains(&current) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("symlink cycle through {}", current.display()),
+            ));
+        }
+        let next = resolve_link(&current)?;
+        hops.push(current);
+        current = next;
+    }
+    Ok((hops, current))
+}
+
+/// Check whether a symlink points to `destination`, whether it was written as a
+/// relative or an absolute path
+pub fn points_to<P: AsRef<Path>, Q: AsRef<Path>>(link: P, destination: Q) -> bool {
+    let destination = destination.as_ref();
+    resolve_link(link)
+        .map(|resolved| {
+            resolved == path_utils::clean(destination)
+                || real_location(&resolved) == real_location(destination)
+        })
+        .unwrap_or(false)
+}
+
 /// Creates symlinks from files in source directory to target directory
 /// based on file types and ignored paths from configuration
 pub fn create_symlinks<P: AsRef<Path>, Q: AsRef<Path>>(source: P, target: Q) -> io::Result<()> {
+    for (path, target_path) in plan_symlinks(source, target)? {
+        match create_symlink(&path, &target_path) {
+            Ok(_) => println!("Created symlink for {:?} -> {:?}", path, target_path),
+            Err(e) => println!("Failed to create symlink for {:?}: {}", path, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Work out which symlinks `create_symlinks` would create, as (file, link) pairs
+pub fn plan_symlinks<P: AsRef<Path>, Q: AsRef<Path>>(
+    source: P,
+    target: Q,
+) -> io::Result<Vec<(PathBuf, PathBuf)>> {
     // Resolve and normalize source directory
     let abs_source = path_utils::normalize(source.as_ref());
 
@@ -76,7 +195,9 @@ pub fn create_symlinks<P: AsRef<Path>, Q: AsRef<Path>>(source: P, target: Q) ->
         .map(|path| (PathBuf::from(path), true))
         .collect();
 
-    // Walk through source directory and create symlinks in target
+    let mut links = Vec::new();
+
+    // Walk through source directory and collect symlinks to create in target
     for entry in WalkDir::new(&abs_source)
         .follow_links(false)
         .into_iter()
@@ -86,9 +207,6 @@ pub fn create_symlinks<P: AsRef<Path>, Q: AsRef<Path>>(source: P, target: Q) ->
 
         // Skip if path is in ignored paths
         if ignored_paths_map.contains_key(&PathBuf::from(path)) {
-            if path.is_dir() {
-                continue; // Skip entire directory
-            }
             continue;
         }
 
@@ -97,30 +215,16 @@ pub fn create_symlinks<P: AsRef<Path>, Q: AsRef<Path>>(source: P, target: Q) ->
             // Check if the file extension is in the approved list
             if let Some(ext) = path.extension() {
                 let ext_str = format!(".{}", ext.to_string_lossy());
-                if filetypes_map.contains_key(&ext_str) {
-                    // Create the symlink in the target directory
-                    let file_name = match path.file_name() {
-                        Some(name) => name,
-                        None => {
-                            println!(
-                                "Warning: Invalid path with no filename component: {:?}",
-                                path
-                            );
-                            continue;
-                        }
-                    };
-                    let target_path = abs_target.join(file_name);
-
-                    match create_symlink(path, &target_path) {
-                        Ok(_) => println!("Created symlink for {:?} -> {:?}", path, target_path),
-                        Err(e) => println!("Failed to create symlink for {:?}: {}", path, e),
-                    }
+                if filetypes_map.contains_key(&ext_str)
+                    && let Some(file_name) = path.file_name()
+                {
+                    links.push((path.to_path_buf(), abs_target.join(file_name)));
                 }
             }
         }
     }
 
-    Ok(())
+    Ok(links)
 }
 
 /// Remove all symlinks in the given directory
@@ -1,29 +1,13 @@
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 pub fn expand_tilde<P: AsRef<Path>>(path: P) -> PathBuf {
     let path = path.as_ref();
-    if !path.starts_with("~") {
+    let Ok(rest) = path.strip_prefix("~") else {
         return path.to_path_buf();
-    }
-
-    let path_str = path.to_string_lossy();
-    let path_str = match path_str.strip_prefix("~") {
-        Some(stripped) => stripped,
-        None => {
-            // This should not happen due to the guard above, but be defensive
-            return path.to_path_buf();
-        }
     };
 
     match dirs::home_dir() {
-        Some(mut home) => {
-            if path_str.starts_with('/') || path_str.starts_with('\\') {
-                home.push(&path_str[1..]);
-            } else if !path_str.is_empty() {
-                home.push(path_str);
-            }
-            home
-        }
+        Some(home) => home.join(rest),
         None => path.to_path_buf(),
     }
 }
@@ -37,11 +21,205 @@ pub fn normalize<P: AsRef<Path>>(path: P) -> PathBuf {
     let path = expand_tilde(path);
 
     // Make the path absolute if it's not already
-    if !path.is_absolute() {
-        if let Ok(current_dir) = std::env::current_dir() {
-            return current_dir.join(path);
-        }
+    if !path.is_absolute()
+        && let Ok(current_dir) = std::env::current_dir()
+    {
+        return current_dir.join(path);
     }
 
     path
 }
+
+/// Remove `.` components and resolve `..` components without touching the filesystem
+pub fn clean<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut cleaned = PathBuf::new();
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !cleaned.pop() {
+                    cleaned.push(component);
+                }
+            }
+            _ => cleaned.push(component),
+        }
+    }
+    cleaned
+}
+
+/// The path that leads from the directory `base` to `path`, using `..` where needed
+///
+/// Both paths are cleaned first. If they share no root, `path` is returned unchanged.
+pub fn relative_to<P: AsRef<Path>, Q: AsRef<Path>>(base: P, path: Q) -> PathBuf {
+    let base = clean(base);
+    let path = clean(path);
+    let common = base
+        .components()
+        .zip(path.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 && path.has_root() {
+        return path;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in base.components().skip(common) {
+        relative.push(Component::ParentDir);
+    }
+    relative.extend(path.components().skip(common));
+    relative
+}
+
+/// The raw bytes of a path, as stored in the database
+#[cfg(unix)]
+pub fn to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+pub fn to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Rebuild a path from the bytes returned by [`to_bytes`]
+#[cfg(unix)]
+pub fn from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// A text form of `path` that keeps bytes which are not valid UTF-8
+///
+/// Backslashes are doubled and every byte that is not part of valid UTF-8 is written as
+/// `\xNN`, so paths that are valid UTF-8 without backslashes are unchanged. Used wherever
+/// paths have to be written as text, such as pack manifests, zip entry names and JSON.
+pub fn escape(path: &Path) -> String {
+    let bytes = to_bytes(path);
+    let mut escaped = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        escaped.push_str(&chunk.valid().replace('\\', "\\\\"));
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    escaped
+}
+
+/// Reverse [`escape`]; a backslash that does not start an escape is kept as it is
+pub fn unescape(text: &str) -> PathBuf {
+    let text = text.as_bytes();
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let escape = match text[i..] {
+            [b'\\', b'\\', ..] => Some((b'\\', 2)),
+            [b'\\', b'x', high, low, ..] => std::str::from_utf8(&[high, low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .map(|byte| (byte, 4)),
+            _ => None,
+        };
+        match escape {
+            Some((byte, len)) => {
+                bytes.push(byte);
+                i += len;
+            }
+            None => {
+                bytes.push(text[i]);
+                i += 1;
+            }
+        }
+    }
+    from_bytes(bytes)
+}
+
+/// Serialize a path with [`escape`], for use with `#[serde(with = "...")]`
+pub mod escaped {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::path::{Path, PathBuf};
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::escape(path))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(super::unescape(&String::deserialize(deserializer)?))
+    }
+}
+
+/// [`escaped`] for optional paths
+pub mod escaped_opt {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::path::PathBuf;
+
+    pub fn serialize<S: Serializer>(
+        path: &Option<PathBuf>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match path {
+            Some(path) => serializer.serialize_some(&super::escape(path)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<PathBuf>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.map(|text| super::unescape(&text)))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn test_relative_to() {
+        let cases = [
+            (
+                "/home/ada",
+                "/home/ada/dotfiles/.bashrc",
+                "dotfiles/.bashrc",
+            ),
+            (
+                "/home/ada/.config/git",
+                "/home/ada/dotfiles/git/config",
+                "../../dotfiles/git/config",
+            ),
+            (
+                "/home/ada/./.config/../",
+                "/home/ada/dotfiles/.bashrc",
+                "dotfiles/.bashrc",
+            ),
+            ("/home/ada", "/srv/dots/.bashrc", "../../srv/dots/.bashrc"),
+        ];
+        for (base, path, relative) in cases {
+            assert_eq!(relative_to(base, path), Path::new(relative));
+            assert_eq!(clean(Path::new(base).join(relative)), clean(path));
+        }
+    }
+
+    #[test]
+    fn test_escape_round_trips_invalid_utf8() {
+        let cases = [
+            Path::new("/home/ada/.bashrc"),
+            Path::new(r"C:\dos\path"),
+            Path::new(r"literal\x41"),
+            Path::new(OsStr::from_bytes(b"/home/ada/caf\xe9\xff.conf")),
+        ];
+        for path in cases {
+            assert_eq!(unescape(&escape(path)), path);
+            assert_eq!(from_bytes(to_bytes(path)), path);
+        }
+        assert_eq!(escape(cases[0]), "/home/ada/.bashrc");
+        assert_eq!(escape(cases[3]), r"/home/ada/caf\xe9\xff.conf");
+    }
+}
@@ -1,9 +1,11 @@
+use crate::config::folder::ConflictPolicy;
+use crate::engine::{Action, Conflict};
 use std::io::{self, Write};
 
 /// Prompt the user for confirmation, returning true if they answer yes
 pub fn confirm(message: &str) -> bool {
     print!("{} [y/N]: ", message);
-    if let Err(_) = io::stdout().flush() {
+    if io::stdout().flush().is_err() {
         eprintln!("Warning: Failed to flush stdout");
     }
 
@@ -20,7 +22,7 @@ pub fn confirm(message: &str) -> bool {
 pub fn confirm_with_text(message: &str, required_text: &str) -> bool {
     println!("{}", message);
     print!("Type '{}' to confirm: ", required_text);
-    if let Err(_) = io::stdout().flush() {
+    if io::stdout().flush().is_err() {
         eprintln!("Warning: Failed to flush stdout");
     }
 
@@ -32,6 +34,43 @@ pub fn confirm_with_text(message: &str, required_text: &str) -> bool {
     input.trim() == required_text
 }
 
+/// Ask how to resolve a conflict, returning skip unless another policy is chosen
+pub fn choose_conflict(conflict: &Conflict) -> ConflictPolicy {
+    match conflict.action {
+        Action::Sync => println!(
+            "{} and {} both changed since the last sync",
+            conflict.path.display(),
+            conflict.managed.display()
+        ),
+        Action::Render => println!(
+            "{} was edited since it was rendered from {}",
+            conflict.path.display(),
+            conflict.managed.display()
+        ),
+        _ => println!(
+            "{} already exists and differs from {}",
+            conflict.path.display(),
+            conflict.managed.display()
+        ),
+    }
+    print!("[b]ackup, [s]kip, [o]verwrite or [a]dopt? [s]: ");
+    if io::stdout().flush().is_err() {
+        eprintln!("Warning: Failed to flush stdout");
+    }
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return ConflictPolicy::Skip;
+    }
+
+    match input.trim().to_lowercase().as_str() {
+        "b" | "backup" => ConflictPolicy::Backup,
+        "o" | "overwrite" => ConflictPolicy::Overwrite,
+        "a" | "adopt" => ConflictPolicy::Adopt,
+        _ => ConflictPolicy::Skip,
+    }
+}
+
 /// Display a warning message in a standard format
 pub fn show_warning(message: &str) {
     println!("WARNING: {}", message);